@@ -154,7 +154,8 @@ impl App {
 
     pub fn open_local_workspace<T: 'static + fs::LocalTree>(&mut self, roots: Vec<T>) {
         let file_provider = self.file_provider.clone();
-        let workspace = LocalWorkspace::new(LocalProject::new(file_provider, roots)).into_shared();
+        let project = LocalProject::new(self.foreground.clone(), file_provider, roots);
+        let workspace = LocalWorkspace::new(project).into_shared();
         self.add_workspace(WorkspaceEntry::Local(workspace.clone()));
         self.open_workspace_window(workspace);
     }
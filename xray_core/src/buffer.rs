@@ -1,18 +1,22 @@
 use super::rpc::{client, Error as RpcError};
 use super::tree::{self, SeekBias, Tree};
+use diff;
 use futures::{unsync, Stream};
 use notify_cell::{NotifyCell, NotifyCellObserver};
 use serde::{self, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json;
 use std::cell::RefCell;
 use std::cmp;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt;
 use std::iter;
 use std::marker;
 use std::mem;
 use std::ops::{Add, AddAssign, Range, Sub};
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 use std::sync::Arc;
+use std::time::Duration;
+use time::Instant;
 use ForegroundExecutor;
 use IntoShared;
 use UserId;
@@ -20,11 +24,15 @@ use UserId;
 pub type ReplicaId = usize;
 type LocalTimestamp = usize;
 type LamportTimestamp = usize;
+type UndoCount = usize;
 pub type SelectionSetId = usize;
-type SelectionSetVersion = usize;
+pub type SelectionSetVersion = usize;
+pub type MarkerLayerId = usize;
+pub type MarkerId = usize;
+type MarkerLayerVersion = usize;
 pub type BufferId = usize;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Version(
     #[serde(serialize_with = "serialize_arc", deserialize_with = "deserialize_arc")]
     Arc<HashMap<ReplicaId, LocalTimestamp>>,
@@ -36,6 +44,9 @@ pub enum Error {
     InvalidAnchor,
     InvalidOperation,
     SelectionSetNotFound,
+    MarkerLayerNotFound,
+    MarkerNotFound,
+    TransactionNotStarted,
 }
 
 pub struct Buffer {
@@ -49,14 +60,24 @@ pub struct Buffer {
     anchor_cache: RefCell<HashMap<Anchor, (usize, Point)>>,
     offset_cache: RefCell<HashMap<Point, usize>>,
     pub version: Version,
+    saved_version: Version,
+    unsaved_changes: UnsavedChanges,
+    modified: NotifyCell<bool>,
+    conflict: bool,
+    undo_map: UndoMap,
+    history: History,
     client: Option<client::Service<rpc::Service>>,
     operation_txs: Vec<unsync::mpsc::UnboundedSender<Arc<Operation>>>,
+    edit_subscriptions: Vec<Weak<RefCell<Vec<Edit>>>>,
     updates: NotifyCell<()>,
     next_local_selection_set_id: SelectionSetId,
     selections: HashMap<(ReplicaId, SelectionSetId), SelectionSet>,
+    next_local_marker_layer_id: MarkerLayerId,
+    marker_layers: HashMap<(ReplicaId, MarkerLayerId), MarkerLayer>,
+    indent_settings: IndentSettings,
 }
 
-#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Hash)]
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Deserialize, Serialize, Hash)]
 pub struct Point {
     pub row: u32,
     pub column: u32,
@@ -82,6 +103,13 @@ enum AnchorBias {
     Right,
 }
 
+/// Determines the whitespace inserted to indent a row by one level.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct IndentSettings {
+    pub hard_tabs: bool,
+    pub tab_width: u32,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Selection {
     pub start: Anchor,
@@ -102,11 +130,109 @@ pub struct SelectionSetState {
     selections: Vec<Selection>,
 }
 
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Marker {
+    pub id: MarkerId,
+    pub start: Anchor,
+    pub end: Anchor,
+    #[serde(serialize_with = "serialize_json", deserialize_with = "deserialize_json")]
+    pub properties: serde_json::Value,
+}
+
+struct MarkerLayer {
+    replicated: bool,
+    markers: Vec<Marker>,
+    // An implicit binary tree over the markers, in which each node holds the index of the marker
+    // that ends last among those it covers. Queries use it to skip runs of markers that end before
+    // the queried range. Edits preserve the relative order of anchors, so it only needs to be
+    // rebuilt when markers are added or removed.
+    max_ends: RefCell<Option<Vec<Option<usize>>>>,
+    next_marker_id: MarkerId,
+    version: MarkerLayerVersion,
+    // The operations that produced the most recent versions, so replicas that are only a few
+    // versions behind can be sent those instead of every marker. It is trimmed to be no longer
+    // than the layer itself, beyond which sending the whole layer is cheaper.
+    operations: VecDeque<MarkerOperation>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MarkerLayerState {
+    markers: Vec<Marker>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum MarkerOperation {
+    Add(Marker),
+    Remove(MarkerId),
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct UndoMap(HashMap<EditId, UndoCount>);
+
+// Tracks the operations that aren't reflected in the saved version, so that undoing or redoing
+// back to the saved state makes the buffer clean again.
+#[derive(Default)]
+struct UnsavedChanges {
+    changes: Vec<UndoCountChange>,
+    saved_visibility: HashMap<EditId, bool>,
+    modified_edits: HashSet<EditId>,
+}
+
+// Records that an operation set the undo count of an edit. Edits count as having been set to
+// zero by the operation that inserted them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct UndoCountChange {
+    op_id: EditId,
+    edit_id: EditId,
+    count: UndoCount,
+}
+
+struct History {
+    undo_stack: Vec<Transaction>,
+    redo_stack: Vec<Transaction>,
+    transaction_depth: usize,
+    group_interval: Duration,
+}
+
+struct Transaction {
+    edits: Vec<Arc<Operation>>,
+    selections_before: Option<(SelectionSetId, Vec<Selection>)>,
+    selections_after: Option<(SelectionSetId, Vec<Selection>)>,
+    first_edit_at: Instant,
+    last_edit_at: Instant,
+}
+
+/// Describes a change to a contiguous region of the buffer's visible text. The old range is
+/// expressed in coordinates from before the change and the new range in coordinates from after
+/// it. Both ranges start at the same point, but may extend past the text that actually changed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Edit {
+    pub old_range: Range<Point>,
+    pub new_range: Range<Point>,
+}
+
+/// Accumulates the edits applied to a buffer since the subscription was created or last drained.
+pub struct EditSubscription(Rc<RefCell<Vec<Edit>>>);
+
+/// An immutable copy of a buffer's text that can be sent to another thread.
+#[derive(Clone)]
+pub struct Snapshot {
+    version: Version,
+    fragments: Tree<Fragment>,
+}
+
 pub struct Iter<'a> {
     fragment_cursor: tree::Cursor<'a, Fragment>,
     fragment_offset: usize,
 }
 
+/// Iterates over the visible text preceding a point in reverse order.
+pub struct BackwardIter<'a> {
+    fragment_cursor: tree::Cursor<'a, Fragment>,
+    // The offset of the character following the next one to be returned.
+    offset: usize,
+}
+
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Insertion {
     id: EditId,
@@ -153,6 +279,7 @@ struct Fragment {
     start_offset: usize,
     end_offset: usize,
     deletions: HashSet<EditId>,
+    visible: bool,
 }
 
 #[derive(Eq, PartialEq, Clone, Debug)]
@@ -196,6 +323,20 @@ pub enum Operation {
         #[serde(deserialize_with = "deserialize_option_arc")]
         new_text: Option<Arc<Text>>,
     },
+    Undo {
+        id: EditId,
+        edits: Vec<UndoneEdit>,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UndoneEdit {
+    edit_id: EditId,
+    count: UndoCount,
+    start_id: EditId,
+    start_offset: usize,
+    end_id: EditId,
+    end_offset: usize,
 }
 
 impl Version {
@@ -209,14 +350,22 @@ impl Version {
     }
 
     fn include(&mut self, insertion: &Insertion) {
+        self.observe(insertion.id);
+    }
+
+    fn observe(&mut self, edit_id: EditId) {
         let map = Arc::make_mut(&mut self.0);
-        let value = map.entry(insertion.id.replica_id).or_insert(0);
-        *value = cmp::max(*value, insertion.id.timestamp);
+        let value = map.entry(edit_id.replica_id).or_insert(0);
+        *value = cmp::max(*value, edit_id.timestamp);
     }
 
     fn includes(&self, insertion: &Insertion) -> bool {
-        if let Some(timestamp) = self.0.get(&insertion.id.replica_id) {
-            *timestamp >= insertion.id.timestamp
+        self.observed(insertion.id)
+    }
+
+    fn observed(&self, edit_id: EditId) -> bool {
+        if let Some(timestamp) = self.0.get(&edit_id.replica_id) {
+            *timestamp >= edit_id.timestamp
         } else {
             false
         }
@@ -224,15 +373,17 @@ impl Version {
 }
 
 pub mod rpc {
-    use super::{Buffer, BufferId, EditId, FragmentId, Insertion, InsertionSplit, Operation,
-                ReplicaId, SelectionSetId, SelectionSetState, SelectionSetVersion, Version};
+    use super::{Buffer, BufferId, EditId, FragmentId, Insertion, InsertionSplit, MarkerLayerId,
+                MarkerLayerState, MarkerLayerVersion, MarkerOperation, Operation, ReplicaId,
+                SelectionSetId, SelectionSetState, SelectionSetVersion, UndoCountChange, UndoMap,
+                Version};
     use futures::{Async, Future, Stream};
     use never::Never;
     use notify_cell::NotifyCellObserver;
     use rpc;
     use serde::{Deserialize, Deserializer, Serialize, Serializer};
     use std::cell::RefCell;
-    use std::collections::{HashMap, HashSet};
+    use std::collections::{HashMap, HashSet, VecDeque};
     use std::rc::Rc;
     use std::sync::Arc;
 
@@ -244,7 +395,11 @@ pub mod rpc {
         pub(super) insertions: HashMap<EditId, Insertion>,
         pub(super) insertion_splits: HashMap<EditId, Vec<InsertionSplit>>,
         pub(super) version: Version,
+        pub(super) saved_version: Version,
+        pub(super) unsaved_changes: Vec<UndoCountChange>,
+        pub(super) undo_map: UndoMap,
         pub(super) selections: HashMap<(ReplicaId, SelectionSetId), SelectionSetState>,
+        pub(super) marker_layers: HashMap<(ReplicaId, MarkerLayerId), MarkerLayerState>,
     }
 
     #[derive(Serialize, Deserialize)]
@@ -255,6 +410,9 @@ pub mod rpc {
         ),
         UpdateSelectionSet(SelectionSetId, SelectionSetState),
         RemoveSelectionSet(SelectionSetId),
+        UpdateMarkerLayer(MarkerLayerId, MarkerLayerState),
+        UpdateMarkers(MarkerLayerId, MarkerOperation),
+        RemoveMarkerLayer(MarkerLayerId),
     }
 
     #[derive(Serialize, Deserialize)]
@@ -267,6 +425,12 @@ pub mod rpc {
             updated: HashMap<(ReplicaId, SelectionSetId), SelectionSetState>,
             removed: HashSet<(ReplicaId, SelectionSetId)>,
         },
+        MarkerLayers {
+            updated: HashMap<(ReplicaId, MarkerLayerId), MarkerLayerState>,
+            operations: HashMap<(ReplicaId, MarkerLayerId), Vec<MarkerOperation>>,
+            removed: HashSet<(ReplicaId, MarkerLayerId)>,
+        },
+        Saved(Version),
     }
 
     #[derive(Serialize, Deserialize)]
@@ -276,6 +440,7 @@ pub mod rpc {
         pub start_offset: usize,
         pub end_offset: usize,
         pub deletions: HashSet<EditId>,
+        pub visible: bool,
     }
 
     pub struct Service {
@@ -283,6 +448,9 @@ pub mod rpc {
         buffer_updates: NotifyCellObserver<()>,
         outgoing_ops: Box<Stream<Item = Arc<Operation>, Error = ()>>,
         selection_set_versions: HashMap<(ReplicaId, SelectionSetId), SelectionSetVersion>,
+        marker_layer_versions: HashMap<(ReplicaId, MarkerLayerId), MarkerLayerVersion>,
+        pending_updates: VecDeque<Update>,
+        saved_version: Version,
         buffer: Rc<RefCell<Buffer>>,
     }
 
@@ -303,11 +471,22 @@ pub mod rpc {
                 .iter()
                 .map(|(key, set)| (*key, set.version))
                 .collect();
+            let marker_layer_versions = buffer
+                .borrow()
+                .marker_layers
+                .iter()
+                .filter(|(_, layer)| layer.replicated)
+                .map(|(key, layer)| (*key, layer.version))
+                .collect();
+            let saved_version = buffer.borrow().saved_version.clone();
             Self {
                 replica_id,
                 buffer_updates,
                 outgoing_ops: Box::new(outgoing_ops),
                 selection_set_versions,
+                marker_layer_versions,
+                pending_updates: VecDeque::new(),
+                saved_version,
                 buffer,
             }
         }
@@ -319,8 +498,22 @@ pub mod rpc {
                 .map(|option| option.map(|update| Update::Operation(update)))
         }
 
-        fn poll_outgoing_selection_updates(&mut self) -> Async<Option<Update>> {
+        fn poll_outgoing_saved_version(&mut self) -> Option<Update> {
+            let buffer = self.buffer.borrow();
+            if buffer.saved_version != self.saved_version {
+                self.saved_version = buffer.saved_version.clone();
+                Some(Update::Saved(self.saved_version.clone()))
+            } else {
+                None
+            }
+        }
+
+        fn poll_outgoing_state_updates(&mut self) -> Async<Option<Update>> {
             loop {
+                if let Some(update) = self.pending_updates.pop_front() {
+                    return Async::Ready(Some(update));
+                }
+
                 match self.buffer_updates
                     .poll()
                     .expect("Polling a NotifyCellObserver cannot produce an error")
@@ -328,42 +521,101 @@ pub mod rpc {
                     Async::NotReady => return Async::NotReady,
                     Async::Ready(None) => unreachable!(),
                     Async::Ready(Some(())) => {
-                        let mut removed = HashSet::new();
-                        let mut updated = HashMap::new();
-
-                        let buffer = self.buffer.borrow();
-                        self.selection_set_versions
-                            .retain(|id, last_polled_version| {
-                                if let Some(selection_set) = buffer.selections.get(id) {
-                                    if selection_set.version > *last_polled_version {
-                                        *last_polled_version = selection_set.version;
-                                        updated.insert(*id, selection_set.state());
-                                    }
-                                    true
-                                } else {
-                                    removed.insert(*id);
-                                    false
-                                }
-                            });
-
-                        for ((replica_id, set_id), selection_set) in &buffer.selections {
-                            if *replica_id != self.replica_id {
-                                self.selection_set_versions
-                                    .entry((*replica_id, *set_id))
-                                    .or_insert_with(|| {
-                                        updated
-                                            .insert((*replica_id, *set_id), selection_set.state());
-                                        selection_set.version
-                                    });
-                            }
+                        if let Some(update) = self.selection_updates() {
+                            self.pending_updates.push_back(update);
+                        }
+                        if let Some(update) = self.marker_layer_updates() {
+                            self.pending_updates.push_back(update);
                         }
+                    }
+                }
+            }
+        }
+
+        fn selection_updates(&mut self) -> Option<Update> {
+            let mut removed = HashSet::new();
+            let mut updated = HashMap::new();
+
+            let buffer = self.buffer.borrow();
+            self.selection_set_versions
+                .retain(|id, last_polled_version| {
+                    if let Some(selection_set) = buffer.selections.get(id) {
+                        if selection_set.version > *last_polled_version {
+                            *last_polled_version = selection_set.version;
+                            updated.insert(*id, selection_set.state());
+                        }
+                        true
+                    } else {
+                        removed.insert(*id);
+                        false
+                    }
+                });
+
+            for ((replica_id, set_id), selection_set) in &buffer.selections {
+                if *replica_id != self.replica_id {
+                    self.selection_set_versions
+                        .entry((*replica_id, *set_id))
+                        .or_insert_with(|| {
+                            updated.insert((*replica_id, *set_id), selection_set.state());
+                            selection_set.version
+                        });
+                }
+            }
+
+            if updated.len() > 0 || removed.len() > 0 {
+                Some(Update::Selections { updated, removed })
+            } else {
+                None
+            }
+        }
 
-                        if updated.len() > 0 || removed.len() > 0 {
-                            return Async::Ready(Some(Update::Selections { updated, removed }));
+        fn marker_layer_updates(&mut self) -> Option<Update> {
+            let mut removed = HashSet::new();
+            let mut updated = HashMap::new();
+            let mut operations = HashMap::new();
+
+            let buffer = self.buffer.borrow();
+            self.marker_layer_versions
+                .retain(|id, last_polled_version| {
+                    if let Some(layer) = buffer.marker_layers.get(id) {
+                        if layer.version > *last_polled_version {
+                            match layer.operations_since(*last_polled_version) {
+                                Some(layer_operations) => {
+                                    operations.insert(*id, layer_operations);
+                                }
+                                None => {
+                                    updated.insert(*id, layer.state());
+                                }
+                            }
+                            *last_polled_version = layer.version;
                         }
+                        true
+                    } else {
+                        removed.insert(*id);
+                        false
                     }
+                });
+
+            for ((replica_id, layer_id), layer) in &buffer.marker_layers {
+                if *replica_id != self.replica_id && layer.replicated {
+                    self.marker_layer_versions
+                        .entry((*replica_id, *layer_id))
+                        .or_insert_with(|| {
+                            updated.insert((*replica_id, *layer_id), layer.state());
+                            layer.version
+                        });
                 }
             }
+
+            if updated.len() > 0 || operations.len() > 0 || removed.len() > 0 {
+                Some(Update::MarkerLayers {
+                    updated,
+                    operations,
+                    removed,
+                })
+            } else {
+                None
+            }
         }
     }
 
@@ -382,7 +634,11 @@ pub mod rpc {
                 insertions: HashMap::new(),
                 insertion_splits: HashMap::new(),
                 version: buffer.version.clone(),
+                saved_version: buffer.saved_version.clone(),
+                unsaved_changes: buffer.unsaved_changes.changes.clone(),
+                undo_map: buffer.undo_map.clone(),
                 selections: HashMap::new(),
+                marker_layers: HashMap::new(),
             };
 
             for fragment in buffer.fragments.iter() {
@@ -397,6 +653,7 @@ pub mod rpc {
                     start_offset: fragment.start_offset,
                     end_offset: fragment.end_offset,
                     deletions: fragment.deletions.clone(),
+                    visible: fragment.visible,
                 });
             }
 
@@ -411,18 +668,34 @@ pub mod rpc {
                 state.selections.insert(*id, selection_set.state());
             }
 
+            for (id, layer) in &buffer.marker_layers {
+                if layer.replicated {
+                    state.marker_layers.insert(*id, layer.state());
+                }
+            }
+
             state
         }
 
         fn poll_update(&mut self, _: &rpc::server::Connection) -> Async<Option<Self::Update>> {
-            match self.poll_outgoing_op() {
-                Async::Ready(Some(update)) => Async::Ready(Some(update)),
-                Async::Ready(None) => match self.poll_outgoing_selection_updates() {
+            let outgoing_op = self.poll_outgoing_op();
+            if let Async::Ready(Some(update)) = outgoing_op {
+                return Async::Ready(Some(update));
+            }
+
+            // Send the saved version after any pending operations, since it may include them.
+            if let Some(update) = self.poll_outgoing_saved_version() {
+                return Async::Ready(Some(update));
+            }
+
+            match outgoing_op {
+                Async::Ready(Some(_)) => unreachable!(),
+                Async::Ready(None) => match self.poll_outgoing_state_updates() {
                     Async::Ready(Some(update)) => Async::Ready(Some(update)),
                     Async::Ready(None) => Async::Ready(None),
                     Async::NotReady => Async::NotReady,
                 },
-                Async::NotReady => match self.poll_outgoing_selection_updates() {
+                Async::NotReady => match self.poll_outgoing_state_updates() {
                     Async::Ready(Some(update)) => Async::Ready(Some(update)),
                     Async::Ready(None) | Async::NotReady => Async::NotReady,
                 },
@@ -454,6 +727,25 @@ pub mod rpc {
                         .borrow_mut()
                         .remove_remote_selection_set(self.replica_id, set_id);
                 }
+                Request::UpdateMarkerLayer(layer_id, state) => {
+                    self.buffer.borrow_mut().update_remote_marker_layer(
+                        self.replica_id,
+                        layer_id,
+                        state,
+                    );
+                }
+                Request::UpdateMarkers(layer_id, operation) => {
+                    let mut buffer = self.buffer.borrow_mut();
+                    let id = (self.replica_id, layer_id);
+                    if buffer.apply_marker_operation(id, operation).is_err() {
+                        unimplemented!("Invalid marker operation");
+                    }
+                }
+                Request::RemoveMarkerLayer(layer_id) => {
+                    self.buffer
+                        .borrow_mut()
+                        .remove_remote_marker_layer(self.replica_id, layer_id);
+                }
             };
 
             None
@@ -462,9 +754,9 @@ pub mod rpc {
 
     impl Drop for Service {
         fn drop(&mut self) {
-            self.buffer
-                .borrow_mut()
-                .remove_remote_selection_sets(self.replica_id);
+            let mut buffer = self.buffer.borrow_mut();
+            buffer.remove_remote_selection_sets(self.replica_id);
+            buffer.remove_remote_marker_layers(self.replica_id);
         }
     }
 
@@ -479,6 +771,26 @@ pub mod rpc {
     }
 }
 
+impl IndentSettings {
+    /// Returns the text of a single level of indentation.
+    pub fn unit(&self) -> String {
+        if self.hard_tabs {
+            String::from("\t")
+        } else {
+            " ".repeat(self.tab_width as usize)
+        }
+    }
+}
+
+impl Default for IndentSettings {
+    fn default() -> Self {
+        IndentSettings {
+            hard_tabs: false,
+            tab_width: 4,
+        }
+    }
+}
+
 impl Buffer {
     pub fn new(id: BufferId) -> Self {
         let mut fragments = Tree::new();
@@ -522,11 +834,21 @@ impl Buffer {
             anchor_cache: RefCell::new(HashMap::new()),
             offset_cache: RefCell::new(HashMap::new()),
             version: Version::new(),
+            saved_version: Version::new(),
+            unsaved_changes: UnsavedChanges::default(),
+            modified: NotifyCell::new(false),
+            conflict: false,
+            undo_map: UndoMap::default(),
+            history: History::new(),
+            indent_settings: IndentSettings::default(),
             client: None,
             operation_txs: Vec::new(),
+            edit_subscriptions: Vec::new(),
             updates: NotifyCell::new(()),
             selections: HashMap::new(),
             next_local_selection_set_id: 0,
+            marker_layers: HashMap::new(),
+            next_local_marker_layer_id: 0,
         }
     }
 
@@ -549,6 +871,7 @@ impl Buffer {
             start_offset: fragment.start_offset,
             end_offset: fragment.end_offset,
             deletions: fragment.deletions,
+            visible: fragment.visible,
         }));
 
         let mut insertion_splits = HashMap::new();
@@ -558,6 +881,9 @@ impl Buffer {
             insertion_splits.insert(insertion_id, split_tree);
         }
 
+        let unsaved_changes = UnsavedChanges::new(state.unsaved_changes, &state.undo_map);
+        let modified = unsaved_changes.is_modified();
+
         let mut selection_sets = HashMap::new();
         for (id, state) in state.selections {
             selection_sets.insert(
@@ -570,6 +896,11 @@ impl Buffer {
             );
         }
 
+        let mut marker_layers = HashMap::new();
+        for (id, state) in state.marker_layers {
+            marker_layers.insert(id, MarkerLayer::remote(state));
+        }
+
         let buffer = Buffer {
             id: state.id,
             replica_id: state.replica_id,
@@ -581,11 +912,21 @@ impl Buffer {
             anchor_cache: RefCell::new(HashMap::new()),
             offset_cache: RefCell::new(HashMap::new()),
             version: state.version,
+            saved_version: state.saved_version,
+            unsaved_changes,
+            modified: NotifyCell::new(modified),
+            conflict: false,
+            undo_map: state.undo_map,
+            history: History::new(),
+            indent_settings: IndentSettings::default(),
             client: Some(client),
             operation_txs: Vec::new(),
+            edit_subscriptions: Vec::new(),
             updates: NotifyCell::new(()),
             selections: selection_sets,
             next_local_selection_set_id: 0,
+            marker_layers,
+            next_local_marker_layer_id: 0,
         }.into_shared();
 
         let buffer_weak = Rc::downgrade(&buffer);
@@ -610,6 +951,31 @@ impl Buffer {
                                 buffer.remove_remote_selection_set(replica_id, set_id);
                             }
                         }
+                        rpc::Update::MarkerLayers {
+                            updated,
+                            operations,
+                            removed,
+                        } => {
+                            for ((replica_id, layer_id), state) in updated {
+                                debug_assert!(replica_id != buffer.replica_id);
+                                buffer.update_remote_marker_layer(replica_id, layer_id, state);
+                            }
+
+                            for (id, layer_operations) in operations {
+                                debug_assert!(id.0 != buffer.replica_id);
+                                for operation in layer_operations {
+                                    if buffer.apply_marker_operation(id, operation).is_err() {
+                                        unimplemented!("Invalid marker operation");
+                                    }
+                                }
+                            }
+
+                            for (replica_id, layer_id) in removed {
+                                debug_assert!(replica_id != buffer.replica_id);
+                                buffer.remove_remote_marker_layer(replica_id, layer_id);
+                            }
+                        }
+                        rpc::Update::Saved(version) => buffer.did_save(version),
                     }
                 }
 
@@ -661,11 +1027,27 @@ impl Buffer {
     }
 
     pub fn iter(&self) -> Iter {
-        Iter::new(self)
+        Iter::new(&self.fragments)
     }
 
     pub fn iter_starting_at_row(&self, row: u32) -> Iter {
-        Iter::starting_at_row(self, row)
+        Iter::starting_at_point(&self.fragments, Point::new(row, 0))
+    }
+
+    pub fn iter_starting_at_point(&self, point: Point) -> Iter {
+        Iter::starting_at_point(&self.fragments, point)
+    }
+
+    pub fn backward_iter_starting_at_point(&self, point: Point) -> BackwardIter {
+        let offset = self.offset_for_point(point).unwrap_or(self.len());
+        BackwardIter::new(&self.fragments, offset)
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            version: self.version.clone(),
+            fragments: self.fragments.clone(),
+        }
     }
 
     pub fn edit<T: Into<Text>>(
@@ -681,11 +1063,24 @@ impl Buffer {
         };
 
         if new_text.is_some() || old_range.end > old_range.start {
+            let old_start = self.point_for_offset(old_range.start).unwrap();
+            let old_end = self.point_for_offset(old_range.end).unwrap();
+            let new_end = new_text.as_ref().map_or(old_start, |new_text| {
+                old_start + &new_text.point_for_offset(new_text.len()).unwrap()
+            });
+
+            let now = Instant::now();
+            self.start_transaction_at(None, now).unwrap();
             let op = Arc::new(self.splice_fragments(old_range, new_text));
+            self.record_edit(old_start..old_end, old_start..new_end);
             self.anchor_cache.borrow_mut().clear();
             self.offset_cache.borrow_mut().clear();
             self.version.inc(self.replica_id);
+            self.history.push_edit(op.clone());
+            self.end_transaction_at(None, now).unwrap();
             self.broadcast_op(&op);
+            self.record_unsaved_op(&op);
+            self.update_modified();
             self.updates.set(());
             Some(op)
         } else {
@@ -693,6 +1088,267 @@ impl Buffer {
         }
     }
 
+    /// Groups all edits performed until the matching call to `end_transaction` into a single
+    /// undoable unit. Transactions can be nested, in which case only the outermost one is
+    /// recorded. When a selection set is supplied, its state before and after the transaction
+    /// is restored on undo and redo.
+    pub fn start_transaction(&mut self, set_id: Option<SelectionSetId>) -> Result<(), Error> {
+        self.start_transaction_at(set_id, Instant::now())
+    }
+
+    fn start_transaction_at(
+        &mut self,
+        set_id: Option<SelectionSetId>,
+        now: Instant,
+    ) -> Result<(), Error> {
+        let selections = self.selections_snapshot(set_id)?;
+        self.history.start_transaction(selections, now);
+        Ok(())
+    }
+
+    pub fn end_transaction(&mut self, set_id: Option<SelectionSetId>) -> Result<(), Error> {
+        self.end_transaction_at(set_id, Instant::now())
+    }
+
+    fn end_transaction_at(
+        &mut self,
+        set_id: Option<SelectionSetId>,
+        now: Instant,
+    ) -> Result<(), Error> {
+        // Close the transaction even if the selection set is gone, since undo and redo are
+        // refused for as long as it stays open.
+        let (selections, result) = match self.selections_snapshot(set_id) {
+            Ok(selections) => (selections, Ok(())),
+            Err(error) => (None, Err(error)),
+        };
+        self.history.end_transaction(selections, now)?;
+        result
+    }
+
+    #[cfg(test)]
+    pub fn set_group_interval(&mut self, group_interval: Duration) {
+        self.history.group_interval = group_interval;
+    }
+
+    pub fn indent_settings(&self) -> IndentSettings {
+        self.indent_settings
+    }
+
+    /// Sets how rows are indented. A tab width of zero is treated as a width of one, since tab
+    /// stops are computed relative to it.
+    pub fn set_indent_settings(&mut self, mut indent_settings: IndentSettings) {
+        indent_settings.tab_width = cmp::max(indent_settings.tab_width, 1);
+        self.indent_settings = indent_settings;
+    }
+
+    /// Returns true if this buffer contains edits that aren't reflected in the version that was
+    /// most recently loaded from or saved to disk.
+    pub fn is_modified(&self) -> bool {
+        self.unsaved_changes.is_modified()
+    }
+
+    pub fn modified_updates(&self) -> NotifyCellObserver<bool> {
+        self.modified.observe()
+    }
+
+    /// Records that the contents of this buffer at the given version now match what's on disk.
+    pub fn did_save(&mut self, version: Version) {
+        self.unsaved_changes.did_save(&version, &self.undo_map);
+        self.saved_version = version;
+        self.conflict = false;
+        self.update_modified();
+        self.updates.set(());
+    }
+
+    /// Returns true if a reload encountered changes on disk that overlapped unsaved changes in
+    /// this buffer. The flag is cleared by the next save.
+    pub fn has_conflict(&self) -> bool {
+        self.conflict
+    }
+
+    /// Merges changes that were made on disk into this buffer. The `base_text` is the content of
+    /// the file when it was last loaded or saved, and `new_text` is its current content.
+    ///
+    /// Changes on disk are applied as ordinary edits in a single transaction, so they replicate
+    /// like any other edit and preserve unsaved changes made here or by collaborators. A clean
+    /// buffer simply converges to the new content. Changes that overlap unsaved changes in the
+    /// buffer are skipped and flag the buffer as conflicted.
+    pub fn reload(&mut self, base_text: &[u16], new_text: &[u16]) {
+        let was_modified = self.is_modified();
+        let current_text = self.to_u16_chars();
+        let local_hunks = diff::diff(base_text, &current_text);
+        let disk_hunks = diff::diff(base_text, new_text);
+
+        let mut edits = Vec::new();
+        let mut conflict = false;
+        let mut local_hunks = local_hunks.iter().peekable();
+        let mut delta = 0_isize;
+        for disk_hunk in &disk_hunks {
+            let mut overlapping_hunks = Vec::new();
+            while let Some(local_hunk) = local_hunks.peek().cloned() {
+                if local_hunk.precedes(disk_hunk) {
+                    delta += local_hunk.delta();
+                } else if disk_hunk.precedes(local_hunk) {
+                    break;
+                } else {
+                    overlapping_hunks.push(local_hunk);
+                }
+                local_hunks.next();
+            }
+
+            if overlapping_hunks.is_empty() {
+                let start = (disk_hunk.old_range.start as isize + delta) as usize;
+                let end = (disk_hunk.old_range.end as isize + delta) as usize;
+                edits.push((start..end, new_text[disk_hunk.new_range.clone()].to_vec()));
+            } else {
+                // Don't report a conflict when the same change was made on disk and in the buffer.
+                let already_applied = overlapping_hunks.len() == 1
+                    && overlapping_hunks[0].old_range == disk_hunk.old_range
+                    && current_text[overlapping_hunks[0].new_range.clone()]
+                        == new_text[disk_hunk.new_range.clone()];
+                if !already_applied {
+                    conflict = true;
+                }
+                for local_hunk in overlapping_hunks {
+                    delta += local_hunk.delta();
+                }
+            }
+        }
+
+        if !edits.is_empty() {
+            self.start_transaction(None).unwrap();
+            for (range, text) in edits.into_iter().rev() {
+                self.edit(range, text);
+            }
+            self.end_transaction(None).unwrap();
+        }
+
+        if !was_modified {
+            let version = self.version.clone();
+            self.did_save(version);
+        } else if conflict {
+            self.conflict = true;
+            self.updates.set(());
+        }
+    }
+
+    fn record_unsaved_op(&mut self, op: &Operation) {
+        if self.saved_version.observed(op.id()) {
+            return;
+        }
+
+        match op {
+            &Operation::Edit { id, .. } => {
+                let change = UndoCountChange {
+                    op_id: id,
+                    edit_id: id,
+                    count: 0,
+                };
+                self.unsaved_changes.push(change, &self.undo_map);
+            }
+            &Operation::Undo { id, ref edits } => for edit in edits {
+                let change = UndoCountChange {
+                    op_id: id,
+                    edit_id: edit.edit_id,
+                    count: edit.count,
+                };
+                self.unsaved_changes.push(change, &self.undo_map);
+            },
+        }
+    }
+
+    fn update_modified(&mut self) {
+        let modified = self.is_modified();
+        if modified != self.modified.get() {
+            self.modified.set(modified);
+        }
+    }
+
+    pub fn undo(&mut self) -> Option<Arc<Operation>> {
+        let transaction = self.history.pop_undo()?;
+        let op = self.undo_or_redo(&transaction);
+        if let Some((set_id, ref selections)) = transaction.selections_before {
+            self.restore_selections(set_id, selections.clone());
+        }
+        self.history.redo_stack.push(transaction);
+        Some(op)
+    }
+
+    pub fn redo(&mut self) -> Option<Arc<Operation>> {
+        let transaction = self.history.pop_redo()?;
+        let op = self.undo_or_redo(&transaction);
+        if let Some((set_id, ref selections)) = transaction.selections_after {
+            self.restore_selections(set_id, selections.clone());
+        }
+        self.history.undo_stack.push(transaction);
+        Some(op)
+    }
+
+    // Undo and redo are expressed in the same way: we increment the undo count of every edit in
+    // the transaction. An edit with an odd undo count is undone, and an edit with an even undo
+    // count is applied. Because replicas always take the maximum count they have observed for a
+    // given edit, concurrent undos of the same edit converge.
+    fn undo_or_redo(&mut self, transaction: &Transaction) -> Arc<Operation> {
+        let mut edits = Vec::new();
+        for edit in &transaction.edits {
+            match edit.as_ref() {
+                &Operation::Edit {
+                    id,
+                    start_id,
+                    start_offset,
+                    end_id,
+                    end_offset,
+                    ..
+                } => edits.push(UndoneEdit {
+                    edit_id: id,
+                    count: self.undo_map.undo_count(id) + 1,
+                    start_id,
+                    start_offset,
+                    end_id,
+                    end_offset,
+                }),
+                &Operation::Undo { .. } => unreachable!(),
+            }
+        }
+
+        self.local_clock += 1;
+        let id = EditId {
+            replica_id: self.replica_id,
+            timestamp: self.local_clock,
+        };
+        self.integrate_undo(&edits)
+            .expect("Undoing a local edit should always succeed");
+        let op = Arc::new(Operation::Undo { id, edits });
+        self.anchor_cache.borrow_mut().clear();
+        self.offset_cache.borrow_mut().clear();
+        self.version.inc(self.replica_id);
+        self.broadcast_op(&op);
+        self.record_unsaved_op(&op);
+        self.update_modified();
+        self.updates.set(());
+        op
+    }
+
+    fn selections_snapshot(
+        &self,
+        set_id: Option<SelectionSetId>,
+    ) -> Result<Option<(SelectionSetId, Vec<Selection>)>, Error> {
+        if let Some(set_id) = set_id {
+            let selections = self.selections(set_id)
+                .map_err(|_| Error::SelectionSetNotFound)?;
+            Ok(Some((set_id, selections.to_vec())))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn restore_selections(&mut self, set_id: SelectionSetId, selections: Vec<Selection>) {
+        // The selection set may have been removed since the selections were recorded.
+        let _ = self.mutate_selections(set_id, |_, old_selections| {
+            *old_selections = selections;
+        });
+    }
+
     pub fn add_selection_set(
         &mut self,
         user_id: UserId,
@@ -726,6 +1382,14 @@ impl Buffer {
         Ok(())
     }
 
+    /// Returns a number that changes whenever the given local selection set is mutated.
+    pub fn selections_version(&self, set_id: SelectionSetId) -> Result<SelectionSetVersion, ()> {
+        self.selections
+            .get(&(self.replica_id, set_id))
+            .ok_or(())
+            .map(|set| set.version)
+    }
+
     pub fn selections(&self, set_id: SelectionSetId) -> Result<&[Selection], ()> {
         self.selections
             .get(&(self.replica_id, set_id))
@@ -783,28 +1447,288 @@ impl Buffer {
             })
     }
 
-    pub fn updates(&self) -> NotifyCellObserver<()> {
-        self.updates.observe()
-    }
+    /// Creates an empty marker layer. Markers in a replicated layer are visible to every replica
+    /// of this buffer, whereas markers in other layers are only visible locally.
+    pub fn add_marker_layer(&mut self, replicated: bool) -> MarkerLayerId {
+        let id = self.next_local_marker_layer_id;
+        let layer = MarkerLayer {
+            replicated,
+            markers: Vec::new(),
+            max_ends: RefCell::new(None),
+            next_marker_id: 0,
+            version: 0,
+            operations: VecDeque::new(),
+        };
 
-    fn broadcast_op(&mut self, op: &Arc<Operation>) {
-        for i in (0..self.operation_txs.len()).rev() {
-            if self.operation_txs[i].unbounded_send(op.clone()).is_err() {
-                self.operation_txs.swap_remove(i);
+        if replicated {
+            if let Some(ref client) = self.client {
+                client.request(rpc::Request::UpdateMarkerLayer(id, layer.state()));
             }
         }
 
-        if let Some(ref client) = self.client {
-            client.request(rpc::Request::Operation(op.clone()));
+        self.next_local_marker_layer_id += 1;
+        self.marker_layers.insert((self.replica_id, id), layer);
+        self.updates.set(());
+        id
+    }
+
+    pub fn remove_marker_layer(&mut self, id: MarkerLayerId) -> Result<(), Error> {
+        let layer = self.marker_layers
+            .remove(&(self.replica_id, id))
+            .ok_or(Error::MarkerLayerNotFound)?;
+        if layer.replicated {
+            if let Some(ref client) = self.client {
+                client.request(rpc::Request::RemoveMarkerLayer(id));
+            }
         }
+        self.updates.set(());
+        Ok(())
     }
 
-    fn integrate_op(&mut self, op: Arc<Operation>) -> Result<(), Error> {
-        match op.as_ref() {
-            &Operation::Edit {
-                ref id,
-                ref start_id,
-                ref start_offset,
+    pub fn add_marker(
+        &mut self,
+        layer_id: MarkerLayerId,
+        range: Range<Anchor>,
+        properties: serde_json::Value,
+    ) -> Result<MarkerId, Error> {
+        let marker_id = {
+            let layer = self.marker_layers
+                .get_mut(&(self.replica_id, layer_id))
+                .ok_or(Error::MarkerLayerNotFound)?;
+            layer.next_marker_id += 1;
+            layer.next_marker_id - 1
+        };
+        self.mutate_marker_layer(
+            layer_id,
+            MarkerOperation::Add(Marker {
+                id: marker_id,
+                start: range.start,
+                end: range.end,
+                properties,
+            }),
+        )?;
+        Ok(marker_id)
+    }
+
+    pub fn remove_marker(
+        &mut self,
+        layer_id: MarkerLayerId,
+        marker_id: MarkerId,
+    ) -> Result<(), Error> {
+        self.mutate_marker_layer(layer_id, MarkerOperation::Remove(marker_id))
+    }
+
+    /// Returns the markers in the given local layer that intersect or touch the given range,
+    /// ordered by their start position.
+    pub fn markers_in_range(
+        &self,
+        layer_id: MarkerLayerId,
+        range: Range<Point>,
+    ) -> Result<Vec<&Marker>, Error> {
+        let layer = self.marker_layers
+            .get(&(self.replica_id, layer_id))
+            .ok_or(Error::MarkerLayerNotFound)?;
+        Ok(self.query_markers(layer, range))
+    }
+
+    /// Returns the markers in every layer visible to this replica that intersect or touch the
+    /// given range, along with the replica and layer they belong to.
+    pub fn all_markers_in_range(
+        &self,
+        range: Range<Point>,
+    ) -> Vec<(ReplicaId, MarkerLayerId, &Marker)> {
+        let mut markers = Vec::new();
+        for (&(replica_id, layer_id), layer) in &self.marker_layers {
+            for marker in self.query_markers(layer, range.clone()) {
+                markers.push((replica_id, layer_id, marker));
+            }
+        }
+        markers
+    }
+
+    fn query_markers<'a>(&self, layer: &'a MarkerLayer, range: Range<Point>) -> Vec<&'a Marker> {
+        let mut markers = Vec::new();
+        if layer.markers.is_empty() {
+            return markers;
+        }
+
+        // Markers are sorted by their start, so only those preceding the first marker that starts
+        // after the end of the range can intersect it.
+        let end_index = if range.end >= self.max_point() {
+            layer.markers.len()
+        } else {
+            let end = self.anchor_after_point(range.end).unwrap();
+            match layer.markers.binary_search_by(|probe| {
+                self.cmp_anchors(&probe.start, &end)
+                    .unwrap()
+                    .then(cmp::Ordering::Less)
+            }) {
+                Ok(index) | Err(index) => index,
+            }
+        };
+
+        let mut max_ends = layer.max_ends.borrow_mut();
+        if max_ends.is_none() {
+            *max_ends = Some(self.build_max_ends(&layer.markers));
+        }
+        let max_ends = max_ends.as_ref().unwrap();
+
+        // Descend from the root, visiting left children first to preserve the order of markers.
+        let start = self.anchor_before_point(range.start).unwrap();
+        let mut stack = vec![(1, 0, max_ends.len() / 2)];
+        while let Some((node, node_start, node_end)) = stack.pop() {
+            if node_start >= end_index {
+                continue;
+            }
+            if let Some(index) = max_ends[node] {
+                let marker = &layer.markers[index];
+                if self.cmp_anchors(&marker.end, &start).unwrap() == cmp::Ordering::Less {
+                    continue;
+                }
+
+                if node_end - node_start == 1 {
+                    markers.push(marker);
+                } else {
+                    let middle = (node_start + node_end) / 2;
+                    stack.push((2 * node + 1, middle, node_end));
+                    stack.push((2 * node, node_start, middle));
+                }
+            }
+        }
+        markers
+    }
+
+    fn build_max_ends(&self, markers: &[Marker]) -> Vec<Option<usize>> {
+        let leaf_count = markers.len().next_power_of_two();
+        let mut max_ends = vec![None; 2 * leaf_count];
+        for index in 0..markers.len() {
+            max_ends[leaf_count + index] = Some(index);
+        }
+        for node in (1..leaf_count).rev() {
+            max_ends[node] = match (max_ends[2 * node], max_ends[2 * node + 1]) {
+                (Some(left), Some(right)) => {
+                    let ordering = self.cmp_anchors(&markers[left].end, &markers[right].end)
+                        .unwrap();
+                    if ordering == cmp::Ordering::Less {
+                        Some(right)
+                    } else {
+                        Some(left)
+                    }
+                }
+                (left, None) => left,
+                (None, right) => right,
+            };
+        }
+        max_ends
+    }
+
+    fn mutate_marker_layer(
+        &mut self,
+        layer_id: MarkerLayerId,
+        operation: MarkerOperation,
+    ) -> Result<(), Error> {
+        let id = (self.replica_id, layer_id);
+        self.apply_marker_operation(id, operation.clone())?;
+        if self.marker_layers[&id].replicated {
+            if let Some(ref client) = self.client {
+                client.request(rpc::Request::UpdateMarkers(layer_id, operation));
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_marker_operation(
+        &mut self,
+        id: (ReplicaId, MarkerLayerId),
+        operation: MarkerOperation,
+    ) -> Result<(), Error> {
+        let mut layer = self.marker_layers
+            .remove(&id)
+            .ok_or(Error::MarkerLayerNotFound)?;
+        let result = match operation {
+            MarkerOperation::Add(ref marker) => {
+                let index = match layer.markers.binary_search_by(|probe| {
+                    self.cmp_anchors(&probe.start, &marker.start)
+                        .unwrap()
+                        .then_with(|| self.cmp_anchors(&probe.end, &marker.end).unwrap())
+                        .then(cmp::Ordering::Less)
+                }) {
+                    Ok(index) | Err(index) => index,
+                };
+                layer.markers.insert(index, marker.clone());
+                Ok(())
+            }
+            MarkerOperation::Remove(marker_id) => {
+                let index = layer
+                    .markers
+                    .iter()
+                    .position(|marker| marker.id == marker_id);
+                if let Some(index) = index {
+                    layer.markers.remove(index);
+                    Ok(())
+                } else {
+                    Err(Error::MarkerNotFound)
+                }
+            }
+        };
+        if result.is_ok() {
+            *layer.max_ends.get_mut() = None;
+            layer.version += 1;
+            layer.operations.push_back(operation);
+            while layer.operations.len() > layer.markers.len() {
+                layer.operations.pop_front();
+            }
+            self.updates.set(());
+        }
+        self.marker_layers.insert(id, layer);
+        result
+    }
+
+    pub fn updates(&self) -> NotifyCellObserver<()> {
+        self.updates.observe()
+    }
+
+    /// Returns a subscription that records every subsequent edit, whether it was performed
+    /// locally, received from a peer, or applied by undoing and redoing.
+    pub fn subscribe_edits(&mut self) -> EditSubscription {
+        let edits = Rc::new(RefCell::new(Vec::new()));
+        self.edit_subscriptions.push(Rc::downgrade(&edits));
+        EditSubscription(edits)
+    }
+
+    fn record_edit(&mut self, old_range: Range<Point>, new_range: Range<Point>) {
+        let edit = Edit {
+            old_range,
+            new_range,
+        };
+        self.edit_subscriptions.retain(|subscription| {
+            if let Some(edits) = subscription.upgrade() {
+                edits.borrow_mut().push(edit.clone());
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    fn broadcast_op(&mut self, op: &Arc<Operation>) {
+        for i in (0..self.operation_txs.len()).rev() {
+            if self.operation_txs[i].unbounded_send(op.clone()).is_err() {
+                self.operation_txs.swap_remove(i);
+            }
+        }
+
+        if let Some(ref client) = self.client {
+            client.request(rpc::Request::Operation(op.clone()));
+        }
+    }
+
+    fn integrate_op(&mut self, op: Arc<Operation>) -> Result<(), Error> {
+        match op.as_ref() {
+            &Operation::Edit {
+                ref id,
+                ref start_id,
+                ref start_offset,
                 ref end_id,
                 ref end_offset,
                 ref new_text,
@@ -820,9 +1744,13 @@ impl Buffer {
                 version_in_range,
                 *timestamp,
             )?,
+            &Operation::Undo { ref edits, .. } => self.integrate_undo(edits)?,
         }
         self.anchor_cache.borrow_mut().clear();
         self.offset_cache.borrow_mut().clear();
+        self.version.observe(op.id());
+        self.record_unsaved_op(&op);
+        self.update_modified();
         self.updates.set(());
         Ok(())
     }
@@ -845,6 +1773,7 @@ impl Buffer {
         let old_fragments = self.fragments.clone();
         let mut cursor = old_fragments.cursor();
         let mut new_fragments = cursor.build_prefix(&start_fragment_id, SeekBias::Left);
+        let old_start = cursor.start::<Point>();
 
         if start_offset == cursor.item().unwrap().end_offset {
             new_fragments.push(cursor.item().unwrap().clone());
@@ -892,6 +1821,7 @@ impl Buffer {
                 if let Some(mut fragment) = within_range {
                     if version_in_range.includes(&fragment.insertion) {
                         fragment.deletions.insert(id);
+                        fragment.visible = fragment.is_visible_in(&self.undo_map);
                     }
                     new_fragments.push(fragment);
                 }
@@ -914,6 +1844,7 @@ impl Buffer {
                 let mut fragment = fragment.clone();
                 if version_in_range.includes(&fragment.insertion) {
                     fragment.deletions.insert(id);
+                    fragment.visible = fragment.is_visible_in(&self.undo_map);
                 }
                 new_fragments.push(fragment);
             }
@@ -931,12 +1862,58 @@ impl Buffer {
             ));
         }
 
+        let old_end = cursor.start::<Point>();
+        let new_end = new_fragments.len::<Point>();
         new_fragments.push_tree(cursor.build_suffix());
         self.fragments = new_fragments;
+        self.record_edit(old_start..old_end, old_start..new_end);
         self.lamport_clock = cmp::max(self.lamport_clock, timestamp) + 1;
         Ok(())
     }
 
+    fn integrate_undo(&mut self, edits: &[UndoneEdit]) -> Result<(), Error> {
+        for edit in edits {
+            self.undo_map.insert(edit.edit_id, edit.count);
+        }
+
+        for edit in edits {
+            let start_fragment_id = self.resolve_fragment_id(edit.start_id, edit.start_offset)?;
+            let mut end_fragment_id = self.resolve_fragment_id(edit.end_id, edit.end_offset)?;
+
+            // Text inserted by the edit may have been pushed past the end of the deleted range by
+            // concurrent insertions at the same location, so we extend the range to cover it.
+            if let Some(splits) = self.insertion_splits.get(&edit.edit_id) {
+                if let Some(last_split) = splits.iter().last() {
+                    if last_split.fragment_id > end_fragment_id {
+                        end_fragment_id = last_split.fragment_id.clone();
+                    }
+                }
+            }
+
+            let old_fragments = self.fragments.clone();
+            let mut cursor = old_fragments.cursor();
+            let mut new_fragments = cursor.build_prefix(&start_fragment_id, SeekBias::Left);
+            let old_start = cursor.start::<Point>();
+            while let Some(fragment) = cursor.item() {
+                if fragment.id > end_fragment_id {
+                    break;
+                }
+
+                let mut fragment = fragment.clone();
+                fragment.visible = fragment.is_visible_in(&self.undo_map);
+                new_fragments.push(fragment);
+                cursor.next();
+            }
+            let old_end = cursor.start::<Point>();
+            let new_end = new_fragments.len::<Point>();
+            new_fragments.push_tree(cursor.build_suffix());
+            self.fragments = new_fragments;
+            self.record_edit(old_start..old_end, old_start..new_end);
+        }
+
+        Ok(())
+    }
+
     fn update_remote_selection_set(
         &mut self,
         replica_id: ReplicaId,
@@ -966,6 +1943,37 @@ impl Buffer {
         self.updates.set(());
     }
 
+    fn update_remote_marker_layer(
+        &mut self,
+        replica_id: ReplicaId,
+        layer_id: MarkerLayerId,
+        state: MarkerLayerState,
+    ) {
+        let layer = self.marker_layers
+            .entry((replica_id, layer_id))
+            .or_insert_with(|| {
+                MarkerLayer::remote(MarkerLayerState {
+                    markers: Vec::new(),
+                })
+            });
+        layer.version += 1;
+        layer.markers = state.markers;
+        layer.operations.clear();
+        *layer.max_ends.get_mut() = None;
+        self.updates.set(());
+    }
+
+    fn remove_remote_marker_layer(&mut self, replica_id: ReplicaId, layer_id: MarkerLayerId) {
+        self.marker_layers.remove(&(replica_id, layer_id));
+        self.updates.set(());
+    }
+
+    fn remove_remote_marker_layers(&mut self, id: ReplicaId) {
+        self.marker_layers
+            .retain(|(replica_id, _), _| *replica_id != id);
+        self.updates.set(());
+    }
+
     fn resolve_fragment_id(&self, edit_id: EditId, offset: usize) -> Result<FragmentId, Error> {
         let split_tree = self.insertion_splits
             .get(&edit_id)
@@ -1083,10 +2091,11 @@ impl Buffer {
                     new_fragments.push(fragment);
                 }
                 if let Some(mut fragment) = within_range {
-                    if fragment.is_visible() {
-                        fragment.deletions.insert(edit_id.clone());
-                        version_in_range.include(&fragment.insertion);
-                    }
+                    // Invisible fragments are marked as deleted too, so that undoing the edit
+                    // that hid them doesn't resurrect text that was subsequently deleted.
+                    fragment.deletions.insert(edit_id.clone());
+                    fragment.visible = false;
+                    version_in_range.include(&fragment.insertion);
                     new_fragments.push(fragment);
                 }
                 if let Some(fragment) = after_range {
@@ -1391,7 +2400,22 @@ impl Buffer {
         }
     }
 
-    fn offset_for_point(&self, point: Point) -> Result<usize, Error> {
+    fn point_for_offset(&self, offset: usize) -> Result<Point, Error> {
+        if offset > self.len() {
+            return Err(Error::OffsetOutOfRange);
+        }
+
+        let mut fragments_cursor = self.fragments.cursor();
+        fragments_cursor.seek(&CharacterCount(offset), SeekBias::Left);
+        if let Some(fragment) = fragments_cursor.item() {
+            let overshoot = offset - fragments_cursor.start::<CharacterCount>().0;
+            Ok(fragments_cursor.start::<Point>() + &fragment.point_for_offset(overshoot)?)
+        } else {
+            Ok(self.max_point())
+        }
+    }
+
+    pub fn offset_for_point(&self, point: Point) -> Result<usize, Error> {
         let cached_offset = {
             let offset_cache = self.offset_cache.try_borrow().ok();
             offset_cache
@@ -1529,9 +2553,64 @@ impl SelectionSet {
     }
 }
 
+impl MarkerLayer {
+    fn remote(state: MarkerLayerState) -> Self {
+        MarkerLayer {
+            replicated: true,
+            markers: state.markers,
+            max_ends: RefCell::new(None),
+            next_marker_id: 0,
+            version: 0,
+            operations: VecDeque::new(),
+        }
+    }
+
+    fn state(&self) -> MarkerLayerState {
+        MarkerLayerState {
+            markers: self.markers.clone(),
+        }
+    }
+
+    // Returns the operations performed since the given version, unless they have been trimmed.
+    fn operations_since(&self, version: MarkerLayerVersion) -> Option<Vec<MarkerOperation>> {
+        let count = self.version - version;
+        if count <= self.operations.len() {
+            let start = self.operations.len() - count;
+            Some(self.operations.iter().skip(start).cloned().collect())
+        } else {
+            None
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn version(&self) -> &Version {
+        &self.version
+    }
+
+    pub fn max_point(&self) -> Point {
+        self.fragments.len::<Point>()
+    }
+
+    pub fn iter(&self) -> Iter {
+        Iter::new(&self.fragments)
+    }
+
+    pub fn iter_starting_at_row(&self, row: u32) -> Iter {
+        Iter::starting_at_point(&self.fragments, Point::new(row, 0))
+    }
+}
+
+impl EditSubscription {
+    /// Removes and returns the edits recorded since the last call, in the order they occurred.
+    pub fn take(&self) -> Vec<Edit> {
+        mem::replace(&mut *self.0.borrow_mut(), Vec::new())
+    }
+}
+
 impl<'a> Iter<'a> {
-    fn new(buffer: &'a Buffer) -> Self {
-        let mut fragment_cursor = buffer.fragments.cursor();
+    fn new(fragments: &'a Tree<Fragment>) -> Self {
+        let mut fragment_cursor = fragments.cursor();
         fragment_cursor.seek(&CharacterCount(0), SeekBias::Right);
         Self {
             fragment_cursor,
@@ -1539,18 +2618,12 @@ impl<'a> Iter<'a> {
         }
     }
 
-    fn starting_at_row(buffer: &'a Buffer, target_row: u32) -> Self {
-        let mut fragment_cursor = buffer.fragments.cursor();
-        fragment_cursor.seek(
-            &Point {
-                row: target_row,
-                column: 0,
-            },
-            SeekBias::Right,
-        );
+    fn starting_at_point(fragments: &'a Tree<Fragment>, target: Point) -> Self {
+        let mut fragment_cursor = fragments.cursor();
+        fragment_cursor.seek(&target, SeekBias::Right);
 
         let fragment_offset = if let Some(fragment) = fragment_cursor.item() {
-            let point_in_fragment = Point::new(target_row, 0) - &fragment_cursor.start::<Point>();
+            let point_in_fragment = target - &fragment_cursor.start::<Point>();
             fragment.offset_for_point(point_in_fragment).unwrap()
         } else {
             0
@@ -1590,6 +2663,39 @@ impl<'a> Iterator for Iter<'a> {
     }
 }
 
+impl<'a> BackwardIter<'a> {
+    fn new(fragments: &'a Tree<Fragment>, offset: usize) -> Self {
+        let mut fragment_cursor = fragments.cursor();
+        fragment_cursor.seek(&CharacterCount(offset), SeekBias::Left);
+        Self {
+            fragment_cursor,
+            offset,
+        }
+    }
+}
+
+impl<'a> Iterator for BackwardIter<'a> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset == 0 {
+            return None;
+        }
+        self.offset -= 1;
+
+        // Seeking skips over the fragments that don't contain visible text.
+        let fragment_start = self.fragment_cursor.start::<CharacterCount>().0;
+        if self.offset < fragment_start {
+            self.fragment_cursor
+                .seek(&CharacterCount(self.offset), SeekBias::Right);
+        }
+        let fragment_start = self.fragment_cursor.start::<CharacterCount>().0;
+        self.fragment_cursor
+            .item()
+            .and_then(|fragment| fragment.get_code_unit(self.offset - fragment_start))
+    }
+}
+
 impl Selection {
     pub fn head(&self) -> &Anchor {
         if self.reversed {
@@ -1814,6 +2920,23 @@ where
     deserializer.deserialize_option(visitor)
 }
 
+// Arbitrary JSON values can't be decoded from formats that aren't self-describing, such as the
+// bincode we replicate buffers with, so we encode marker properties as JSON strings instead.
+fn serialize_json<S>(value: &serde_json::Value, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&value.to_string())
+}
+
+fn deserialize_json<'de, D>(deserializer: D) -> Result<serde_json::Value, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let json = String::deserialize(deserializer)?;
+    serde_json::from_str(&json).map_err(serde::de::Error::custom)
+}
+
 fn serialize_arc<T, S>(arc: &Arc<T>, serializer: S) -> Result<S::Ok, S::Error>
 where
     T: Serialize,
@@ -1839,6 +2962,7 @@ impl Fragment {
             start_offset: 0,
             end_offset,
             deletions: HashSet::new(),
+            visible: true,
         }
     }
 
@@ -1859,7 +2983,12 @@ impl Fragment {
     }
 
     fn is_visible(&self) -> bool {
-        self.deletions.is_empty()
+        self.visible
+    }
+
+    fn is_visible_in(&self, undo_map: &UndoMap) -> bool {
+        !undo_map.is_undone(self.insertion.id)
+            && self.deletions.iter().all(|id| undo_map.is_undone(*id))
     }
 
     fn point_for_offset(&self, offset: usize) -> Result<Point, Error> {
@@ -1993,10 +3122,167 @@ impl AddAssign for InsertionOffset {
 }
 
 impl Operation {
-    fn replica_id(&self) -> ReplicaId {
+    fn id(&self) -> EditId {
         match *self {
-            Operation::Edit { ref id, .. } => id.replica_id,
+            Operation::Edit { id, .. } => id,
+            Operation::Undo { id, .. } => id,
+        }
+    }
+
+    fn replica_id(&self) -> ReplicaId {
+        self.id().replica_id
+    }
+}
+
+impl UndoMap {
+    fn insert(&mut self, edit_id: EditId, count: UndoCount) {
+        let prev_count = self.0.entry(edit_id).or_insert(0);
+        *prev_count = cmp::max(*prev_count, count);
+    }
+
+    fn undo_count(&self, edit_id: EditId) -> UndoCount {
+        self.0.get(&edit_id).cloned().unwrap_or(0)
+    }
+
+    fn is_undone(&self, edit_id: EditId) -> bool {
+        self.undo_count(edit_id) % 2 == 1
+    }
+}
+
+impl UnsavedChanges {
+    fn new(changes: Vec<UndoCountChange>, undo_map: &UndoMap) -> Self {
+        let mut unsaved_changes = Self::default();
+        for change in changes {
+            unsaved_changes.push(change, undo_map);
+        }
+        unsaved_changes
+    }
+
+    fn is_modified(&self) -> bool {
+        !self.modified_edits.is_empty()
+    }
+
+    // Must be called after the change has been applied to the undo map. The first change to each
+    // edit determines whether the edit was visible in the saved version, and the buffer is
+    // modified as long as any edit's visibility differs from that.
+    fn push(&mut self, change: UndoCountChange, undo_map: &UndoMap) {
+        let was_visible = change.edit_id != change.op_id && change.count % 2 == 1;
+        let saved_visible = *self.saved_visibility
+            .entry(change.edit_id)
+            .or_insert(was_visible);
+        if saved_visible == undo_map.is_undone(change.edit_id) {
+            self.modified_edits.insert(change.edit_id);
+        } else {
+            self.modified_edits.remove(&change.edit_id);
+        }
+        self.changes.push(change);
+    }
+
+    fn did_save(&mut self, version: &Version, undo_map: &UndoMap) {
+        let changes = mem::replace(&mut self.changes, Vec::new());
+        self.saved_visibility.clear();
+        self.modified_edits.clear();
+        for change in changes {
+            if !version.observed(change.op_id) {
+                self.push(change, undo_map);
+            }
+        }
+    }
+}
+
+impl History {
+    fn new() -> Self {
+        History {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            transaction_depth: 0,
+            group_interval: Duration::from_millis(300),
+        }
+    }
+
+    fn start_transaction(
+        &mut self,
+        selections_before: Option<(SelectionSetId, Vec<Selection>)>,
+        now: Instant,
+    ) {
+        self.transaction_depth += 1;
+        if self.transaction_depth == 1 {
+            self.undo_stack.push(Transaction {
+                edits: Vec::new(),
+                selections_before,
+                selections_after: None,
+                first_edit_at: now,
+                last_edit_at: now,
+            });
+        }
+    }
+
+    fn end_transaction(
+        &mut self,
+        selections_after: Option<(SelectionSetId, Vec<Selection>)>,
+        now: Instant,
+    ) -> Result<(), Error> {
+        if self.transaction_depth == 0 {
+            return Err(Error::TransactionNotStarted);
+        }
+        self.transaction_depth -= 1;
+        if self.transaction_depth == 0 {
+            let transaction = self.undo_stack.pop().unwrap();
+            if transaction.edits.is_empty() {
+                return Ok(());
+            }
+
+            let mut transaction = transaction;
+            transaction.selections_after = selections_after;
+            transaction.last_edit_at = now;
+            self.redo_stack.clear();
+
+            // Coalesce transactions performed on the same selection set in quick succession, so
+            // that undoing reverts a burst of typing rather than a single character.
+            if let Some(prev_transaction) = self.undo_stack.last_mut() {
+                let same_selection_set = match (
+                    &prev_transaction.selections_after,
+                    &transaction.selections_before,
+                ) {
+                    (&Some((prev_set_id, _)), &Some((set_id, _))) => prev_set_id == set_id,
+                    _ => false,
+                };
+
+                if same_selection_set
+                    && transaction.first_edit_at - prev_transaction.last_edit_at
+                        < self.group_interval
+                {
+                    prev_transaction.edits.extend(transaction.edits);
+                    prev_transaction.selections_after = transaction.selections_after;
+                    prev_transaction.last_edit_at = transaction.last_edit_at;
+                    return Ok(());
+                }
+            }
+
+            self.undo_stack.push(transaction);
+        }
+        Ok(())
+    }
+
+    fn push_edit(&mut self, edit: Arc<Operation>) {
+        debug_assert!(self.transaction_depth > 0);
+        self.undo_stack.last_mut().unwrap().edits.push(edit);
+    }
+
+    // While a transaction is open, the top of the undo stack is that transaction, so undoing or
+    // redoing is refused rather than tearing it apart.
+    fn pop_undo(&mut self) -> Option<Transaction> {
+        if self.transaction_depth > 0 {
+            return None;
+        }
+        self.undo_stack.pop()
+    }
+
+    fn pop_redo(&mut self) -> Option<Transaction> {
+        if self.transaction_depth > 0 {
+            return None;
         }
+        self.redo_stack.pop()
     }
 }
 
@@ -2025,6 +3311,7 @@ mod tests {
 
     use self::rand::{Rng, SeedableRng, StdRng};
     use super::*;
+    use futures::Async;
     use rpc;
     use std::cmp::Ordering;
     use std::time::Duration;
@@ -2048,6 +3335,253 @@ mod tests {
         assert_eq!(buffer.to_string(), "ghiamnoef");
     }
 
+    #[test]
+    fn test_undo_redo() {
+        let mut buffer = Buffer::new(0);
+        buffer.edit(0..0, "1234");
+        buffer.edit(1..1, "abx");
+        buffer.edit(3..4, "yzef");
+        buffer.edit(3..5, "cd");
+        assert_eq!(buffer.to_string(), "1abcdef234");
+
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "1abyzef234");
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "1abx234");
+        buffer.redo();
+        assert_eq!(buffer.to_string(), "1abyzef234");
+        buffer.redo();
+        assert_eq!(buffer.to_string(), "1abcdef234");
+        assert!(buffer.redo().is_none());
+
+        buffer.undo();
+        buffer.undo();
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "1234");
+
+        // Editing after undoing discards the redo stack.
+        buffer.edit(4..4, "5");
+        assert_eq!(buffer.to_string(), "12345");
+        assert!(buffer.redo().is_none());
+        buffer.undo();
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "");
+        assert!(buffer.undo().is_none());
+    }
+
+    #[test]
+    fn test_undo_concurrent_edits() {
+        let mut buffer_1 = Buffer::new(0);
+        let mut buffer_2 = Buffer::new(0);
+        buffer_2.replica_id = 2;
+
+        let op = buffer_1.edit(0..0, "abcdef").unwrap();
+        buffer_2.integrate_op(op).unwrap();
+
+        // Each replica only undoes its own edits, regardless of what happened after them.
+        let op_1 = buffer_1.edit(2..4, "123").unwrap();
+        let op_2 = buffer_2.edit(1..5, "").unwrap();
+        buffer_1.integrate_op(op_2).unwrap();
+        buffer_2.integrate_op(op_1).unwrap();
+        assert_eq!(buffer_1.to_string(), "a123f");
+        assert_eq!(buffer_2.to_string(), "a123f");
+
+        let op = buffer_2.undo().unwrap();
+        buffer_1.integrate_op(op).unwrap();
+        assert_eq!(buffer_1.to_string(), "ab123ef");
+        assert_eq!(buffer_2.to_string(), "ab123ef");
+
+        let op = buffer_1.undo().unwrap();
+        buffer_2.integrate_op(op).unwrap();
+        assert_eq!(buffer_1.to_string(), "abcdef");
+        assert_eq!(buffer_2.to_string(), "abcdef");
+
+        let op = buffer_2.redo().unwrap();
+        buffer_1.integrate_op(op).unwrap();
+        assert_eq!(buffer_1.to_string(), "af");
+        assert_eq!(buffer_2.to_string(), "af");
+    }
+
+    #[test]
+    fn test_transactions() {
+        let mut buffer = Buffer::new(0);
+        let now = Instant::now();
+        buffer.edit(0..0, "abc");
+        let set_id = buffer.add_selection_set(0, vec![empty_selection(&buffer, 1)]);
+
+        buffer.start_transaction_at(Some(set_id), now).unwrap();
+        buffer.edit(1..1, "d");
+        buffer.edit(3..3, "e");
+        buffer
+            .mutate_selections(set_id, |buffer, selections| {
+                *selections = vec![empty_selection(buffer, 2), empty_selection(buffer, 4)];
+            })
+            .unwrap();
+        buffer.end_transaction_at(Some(set_id), now).unwrap();
+        assert_eq!(buffer.to_string(), "adbec");
+
+        // Transactions on the same selection set within the group interval are coalesced.
+        let now = now + buffer.history.group_interval - Duration::from_millis(1);
+        buffer.start_transaction_at(Some(set_id), now).unwrap();
+        buffer.edit(5..5, "f");
+        buffer
+            .mutate_selections(set_id, |buffer, selections| {
+                *selections = vec![empty_selection(buffer, 6)];
+            })
+            .unwrap();
+        buffer.end_transaction_at(Some(set_id), now).unwrap();
+        assert_eq!(buffer.to_string(), "adbecf");
+
+        // Empty transactions are discarded.
+        let now = now + buffer.history.group_interval;
+        buffer.start_transaction_at(Some(set_id), now).unwrap();
+        buffer.end_transaction_at(Some(set_id), now).unwrap();
+
+        buffer.start_transaction_at(Some(set_id), now).unwrap();
+        buffer.edit(0..0, "g");
+        buffer.end_transaction_at(Some(set_id), now).unwrap();
+        assert_eq!(buffer.to_string(), "gadbecf");
+
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "adbecf");
+        assert_eq!(selection_offsets(&buffer, set_id), vec![(6, 6)]);
+
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "abc");
+        assert_eq!(selection_offsets(&buffer, set_id), vec![(1, 1)]);
+
+        buffer.redo();
+        assert_eq!(buffer.to_string(), "adbecf");
+        assert_eq!(selection_offsets(&buffer, set_id), vec![(6, 6)]);
+
+        buffer.undo();
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "");
+
+        // Undo and redo are ignored while a transaction is open.
+        buffer.start_transaction_at(Some(set_id), now).unwrap();
+        buffer.edit(0..0, "h");
+        assert!(buffer.undo().is_none());
+        assert!(buffer.redo().is_none());
+        buffer.end_transaction_at(Some(set_id), now).unwrap();
+        assert_eq!(buffer.to_string(), "h");
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "");
+
+        // Ending a transaction that was never started is an error and leaves history intact.
+        assert_eq!(
+            buffer.end_transaction_at(Some(set_id), now),
+            Err(Error::TransactionNotStarted)
+        );
+        buffer.redo();
+        assert_eq!(buffer.to_string(), "h");
+
+        // Transactions are closed even if their selection set was removed in the meantime.
+        let now = now + buffer.history.group_interval;
+        buffer.start_transaction_at(Some(set_id), now).unwrap();
+        buffer.edit(1..1, "i");
+        buffer.remove_selection_set(set_id).unwrap();
+        assert_eq!(
+            buffer.end_transaction_at(Some(set_id), now),
+            Err(Error::SelectionSetNotFound)
+        );
+        assert_eq!(buffer.to_string(), "hi");
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "h");
+    }
+
+    #[test]
+    fn test_modified() {
+        let mut buffer = Buffer::new(0);
+        let mut modified_updates = buffer.modified_updates();
+        assert!(!buffer.is_modified());
+
+        buffer.edit(0..0, "abc");
+        assert!(buffer.is_modified());
+        assert_eq!(modified_updates.poll(), Ok(Async::Ready(Some(true))));
+
+        let version = buffer.version.clone();
+        buffer.edit(3..3, "def");
+        buffer.did_save(version);
+        assert!(buffer.is_modified());
+
+        let version = buffer.version.clone();
+        buffer.did_save(version);
+        assert!(!buffer.is_modified());
+        assert_eq!(modified_updates.poll(), Ok(Async::Ready(Some(false))));
+
+        buffer.edit(0..1, "");
+        assert!(buffer.is_modified());
+        assert_eq!(modified_updates.poll(), Ok(Async::Ready(Some(true))));
+
+        // Undoing and redoing back to the saved state makes the buffer clean again
+        buffer.undo();
+        assert!(!buffer.is_modified());
+        assert_eq!(modified_updates.poll(), Ok(Async::Ready(Some(false))));
+        buffer.redo();
+        assert!(buffer.is_modified());
+        buffer.undo();
+        assert!(!buffer.is_modified());
+
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "abc");
+        assert!(buffer.is_modified());
+        buffer.redo();
+        assert_eq!(buffer.to_string(), "abcdef");
+        assert!(!buffer.is_modified());
+
+        // Undos performed after the saved version was captured still count as modifications
+        let version = buffer.version.clone();
+        buffer.undo();
+        buffer.did_save(version);
+        assert!(buffer.is_modified());
+    }
+
+    #[test]
+    fn test_reload() {
+        let base_text = "a\nb\nc\nd\n";
+        let base_text_u16 = base_text.encode_utf16().collect::<Vec<_>>();
+        let utf16 = |text: &str| text.encode_utf16().collect::<Vec<_>>();
+
+        // A clean buffer converges to the new content.
+        let mut buffer = Buffer::new(0);
+        buffer.edit(0..0, base_text);
+        let version = buffer.version.clone();
+        buffer.did_save(version);
+        buffer.reload(&base_text_u16, &utf16("a\nB\nc\nd\ne\n"));
+        assert_eq!(buffer.to_string(), "a\nB\nc\nd\ne\n");
+        assert!(!buffer.is_modified());
+        assert!(!buffer.has_conflict());
+        buffer.undo();
+        assert_eq!(buffer.to_string(), base_text);
+
+        // Unsaved edits that don't overlap changes on disk are preserved.
+        let mut buffer = Buffer::new(0);
+        buffer.edit(0..0, base_text);
+        let version = buffer.version.clone();
+        buffer.did_save(version);
+        buffer.edit(4..5, "C");
+        buffer.edit(0..0, "z\n");
+        buffer.reload(&base_text_u16, &utf16("a\nB\nc\nd\ne\n"));
+        assert_eq!(buffer.to_string(), "z\na\nB\nC\nd\ne\n");
+        assert!(buffer.is_modified());
+        assert!(!buffer.has_conflict());
+
+        // Identical changes on disk and in the buffer don't conflict.
+        buffer.reload(&utf16("a\nB\nc\nd\ne\n"), &utf16("a\nB\nC\nd\ne\n"));
+        assert_eq!(buffer.to_string(), "z\na\nB\nC\nd\ne\n");
+        assert!(!buffer.has_conflict());
+
+        // Changes that overlap unsaved edits keep the buffer's content and flag a conflict.
+        buffer.reload(&utf16("a\nB\nC\nd\ne\n"), &utf16("y\na\nB\nC\nD\ne\n"));
+        assert_eq!(buffer.to_string(), "z\na\nB\nC\nD\ne\n");
+        assert!(buffer.has_conflict());
+
+        let version = buffer.version.clone();
+        buffer.did_save(version);
+        assert!(!buffer.has_conflict());
+    }
+
     #[test]
     fn test_random_edits() {
         for seed in 0..100 {
@@ -2092,6 +3626,26 @@ mod tests {
         assert_eq!(buffer.len_for_row(6), Err(Error::OffsetOutOfRange));
     }
 
+    #[test]
+    fn backward_iter_starting_at_point() {
+        let mut buffer = Buffer::new(0);
+        buffer.edit(0..0, "abcd\nefgh\nij");
+        buffer.edit(12..12, "kl\nmno");
+        buffer.edit(18..18, "\npqrs");
+        buffer.edit(18..21, "\nPQ");
+        buffer.edit(2..7, "");
+
+        let text = buffer.to_string();
+        for offset in 0..text.len() + 1 {
+            let point = buffer.point_for_offset(offset).unwrap();
+            let iter = buffer.backward_iter_starting_at_point(point);
+            assert_eq!(
+                String::from_utf16_lossy(&iter.collect::<Vec<u16>>()),
+                text[..offset].chars().rev().collect::<String>()
+            );
+        }
+    }
+
     #[test]
     fn iter_starting_at_row() {
         let mut buffer = Buffer::new(0);
@@ -2133,6 +3687,12 @@ mod tests {
         let iter = buffer.iter_starting_at_row(5);
         assert_eq!(String::from_utf16_lossy(&iter.collect::<Vec<u16>>()), "");
 
+        let iter = buffer.iter_starting_at_point(Point::new(2, 3));
+        assert_eq!(
+            String::from_utf16_lossy(&iter.collect::<Vec<u16>>()),
+            "l\nmno\nPQrs"
+        );
+
         // Regression test:
         let mut buffer = Buffer::new(0);
         buffer.edit(0..0, "[workspace]\nmembers = [\n    \"xray_core\",\n    \"xray_server\",\n    \"xray_cli\",\n    \"xray_wasm\",\n]\n");
@@ -2423,6 +3983,20 @@ mod tests {
 
                         edit_count -= 1;
                     }
+                } else if edit_count > 0 && rng.gen_weighted_bool(4) {
+                    let op = if rng.gen() {
+                        buffer.undo()
+                    } else {
+                        buffer.redo()
+                    };
+
+                    if let Some(op) = op {
+                        for (index, queue) in queues.iter_mut().enumerate() {
+                            if index != replica_index {
+                                queue.push(op.clone());
+                            }
+                        }
+                    }
                 } else if !queues[replica_index].is_empty() {
                     buffer
                         .integrate_op(queues[replica_index].remove(0))
@@ -2440,6 +4014,61 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_edit_subscriptions() {
+        for seed in 0..100 {
+            println!("{:?}", seed);
+            let mut rng = StdRng::from_seed(&[seed]);
+
+            let mut buffers = Vec::new();
+            let mut subscriptions = Vec::new();
+            for i in 0..2 {
+                let mut buffer = Buffer::new(0);
+                buffer.replica_id = i + 1;
+                buffer.set_group_interval(Duration::from_millis(0));
+                subscriptions.push(buffer.subscribe_edits());
+                buffers.push(buffer);
+            }
+
+            for _i in 0..30 {
+                let replica_index = rng.gen_range::<usize>(0, 2);
+                let old_text = buffers[replica_index].to_u16_chars();
+                let op = if rng.gen_weighted_bool(4) {
+                    buffers[replica_index].undo()
+                } else {
+                    let buffer = &mut buffers[replica_index];
+                    let end = rng.gen_range::<usize>(0, buffer.len() + 1);
+                    let start = rng.gen_range::<usize>(0, end + 1);
+                    let new_text = (0..rng.gen_range(0, 10))
+                        .map(|_| *rng.choose(&['a', 'b', '\n']).unwrap())
+                        .collect::<String>();
+                    buffer.edit(start..end, new_text.as_str())
+                };
+
+                if let Some(op) = op {
+                    let other_index = 1 - replica_index;
+                    let other_old_text = buffers[other_index].to_u16_chars();
+                    buffers[other_index].integrate_op(op).unwrap();
+                    let old_texts = vec![(replica_index, old_text), (other_index, other_old_text)];
+                    for (index, old_text) in old_texts {
+                        let new_text = buffers[index].to_u16_chars();
+                        let edits = subscriptions[index].take();
+                        assert_eq!(edits.len(), 1);
+                        let edit = &edits[0];
+                        assert_eq!(edit.old_range.start, edit.new_range.start);
+                        let spliced_text = [
+                            &old_text[..offset_in(&old_text, edit.old_range.start)],
+                            &new_text[offset_in(&new_text, edit.new_range.start)
+                                          ..offset_in(&new_text, edit.new_range.end)],
+                            &old_text[offset_in(&old_text, edit.old_range.end)..],
+                        ].concat();
+                        assert_eq!(spliced_text, new_text);
+                    }
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_edit_replication() {
         let local_buffer = Buffer::new(0).into_shared();
@@ -2490,6 +4119,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_modified_replication() {
+        use stream_ext::StreamExt;
+
+        let local_buffer = Buffer::new(0).into_shared();
+        local_buffer.borrow_mut().edit(0..0, "abc");
+        let version = local_buffer.borrow().version.clone();
+        local_buffer.borrow_mut().did_save(version);
+        local_buffer.borrow_mut().edit(3..3, "def");
+
+        let mut reactor = reactor::Core::new().unwrap();
+        let foreground = Rc::new(reactor.handle());
+        let remote_buffer = Buffer::remote(
+            foreground,
+            rpc::tests::connect(&mut reactor, super::rpc::Service::new(local_buffer.clone())),
+        ).unwrap();
+        assert!(remote_buffer.borrow().is_modified());
+
+        let mut remote_modified_updates = remote_buffer.borrow().modified_updates();
+        let version = local_buffer.borrow().version.clone();
+        local_buffer.borrow_mut().did_save(version);
+        assert_eq!(
+            remote_modified_updates.wait_next(&mut reactor),
+            Some(false)
+        );
+
+        remote_buffer.borrow_mut().edit(0..0, "123");
+        assert!(remote_buffer.borrow().is_modified());
+        let mut local_modified_updates = local_buffer.borrow().modified_updates();
+        assert_eq!(local_modified_updates.wait_next(&mut reactor), Some(true));
+    }
+
     #[test]
     fn test_selection_replication() {
         use stream_ext::StreamExt;
@@ -2593,6 +4254,216 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_marker_layers() {
+        let mut buffer = Buffer::new(0);
+        buffer.edit(0..0, "abc\ndef\nghi\njkl");
+        let layer_id = buffer.add_marker_layer(false);
+        let marker_1 = add_marker(&mut buffer, layer_id, 9..10, json!("c"));
+        let marker_2 = add_marker(&mut buffer, layer_id, 1..5, json!({"a": 1}));
+        let marker_3 = add_marker(&mut buffer, layer_id, 6..6, json!(null));
+
+        assert_eq!(
+            marker_offsets(&buffer, layer_id, Point::new(0, 0)..buffer.max_point()),
+            vec![(marker_2, 1, 5), (marker_3, 6, 6), (marker_1, 9, 10)]
+        );
+        assert_eq!(
+            marker_offsets(&buffer, layer_id, Point::new(1, 2)..Point::new(1, 3)),
+            vec![(marker_3, 6, 6)]
+        );
+        assert_eq!(
+            marker_offsets(&buffer, layer_id, Point::new(1, 0)..Point::new(1, 1)),
+            vec![(marker_2, 1, 5)]
+        );
+        assert_eq!(
+            marker_offsets(&buffer, layer_id, Point::new(3, 0)..Point::new(3, 3)),
+            vec![]
+        );
+
+        // Markers move with the text surrounding them.
+        buffer.edit(0..0, "xyz\n");
+        buffer.edit(9..11, "");
+        assert_eq!(
+            marker_offsets(&buffer, layer_id, Point::new(0, 0)..buffer.max_point()),
+            vec![(marker_2, 5, 9), (marker_3, 9, 9), (marker_1, 11, 12)]
+        );
+        let markers = buffer
+            .markers_in_range(layer_id, Point::new(1, 2)..Point::new(1, 2))
+            .unwrap();
+        assert_eq!(markers.len(), 1);
+        assert_eq!(markers[0].id, marker_2);
+        assert_eq!(markers[0].properties, json!({"a": 1}));
+
+        buffer.remove_marker(layer_id, marker_3).unwrap();
+        assert_eq!(
+            buffer.remove_marker(layer_id, marker_3),
+            Err(Error::MarkerNotFound)
+        );
+        assert_eq!(
+            marker_offsets(&buffer, layer_id, Point::new(0, 0)..buffer.max_point()),
+            vec![(marker_2, 5, 9), (marker_1, 11, 12)]
+        );
+
+        let other_layer_id = buffer.add_marker_layer(false);
+        let marker_4 = add_marker(&mut buffer, other_layer_id, 0..1, json!(4));
+        let mut all_markers = buffer
+            .all_markers_in_range(Point::new(0, 0)..Point::new(1, 2))
+            .into_iter()
+            .map(|(_, layer_id, marker)| (layer_id, marker.id))
+            .collect::<Vec<_>>();
+        all_markers.sort();
+        assert_eq!(all_markers, vec![(layer_id, marker_2), (other_layer_id, marker_4)]);
+
+        buffer.remove_marker_layer(layer_id).unwrap();
+        assert_eq!(
+            buffer.markers_in_range(layer_id, Point::new(0, 0)..Point::new(1, 0)),
+            Err(Error::MarkerLayerNotFound)
+        );
+        assert_eq!(
+            buffer.add_marker(
+                layer_id,
+                Anchor(AnchorInner::Start)..Anchor(AnchorInner::End),
+                json!(null),
+            ),
+            Err(Error::MarkerLayerNotFound)
+        );
+    }
+
+    #[test]
+    fn test_random_marker_queries() {
+        for seed in 0..50 {
+            let mut rng = StdRng::from_seed(&[seed]);
+
+            let mut buffer = Buffer::new(0);
+            let text = RandomCharIter(rng).take(40).collect::<String>();
+            buffer.edit(0..0, text.as_str());
+            let layer_id = buffer.add_marker_layer(false);
+            for _ in 0..rng.gen_range(0, 20) {
+                let end = rng.gen_range::<usize>(0, buffer.len() + 1);
+                let start = rng.gen_range::<usize>(0, end + 1);
+                add_marker(&mut buffer, layer_id, start..end, json!(null));
+            }
+            for _ in 0..5 {
+                let end = rng.gen_range::<usize>(0, buffer.len() + 1);
+                let start = rng.gen_range::<usize>(0, end + 1);
+                let new_text = RandomCharIter(rng)
+                    .take(rng.gen_range(0, 10))
+                    .collect::<String>();
+                buffer.edit(start..end, new_text.as_str());
+            }
+
+            for _ in 0..10 {
+                let end = rng.gen_range::<usize>(0, buffer.len() + 1);
+                let start = rng.gen_range::<usize>(0, end + 1);
+                let range = buffer.point_for_offset(start).unwrap()
+                    ..buffer.point_for_offset(end).unwrap();
+                let expected = buffer.marker_layers[&(buffer.replica_id, layer_id)]
+                    .markers
+                    .iter()
+                    .map(|marker| {
+                        (
+                            marker.id,
+                            buffer.offset_for_anchor(&marker.start).unwrap(),
+                            buffer.offset_for_anchor(&marker.end).unwrap(),
+                        )
+                    })
+                    .filter(|&(_, marker_start, marker_end)| {
+                        marker_start <= end && marker_end >= start
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(marker_offsets(&buffer, layer_id, range), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_marker_layer_replication() {
+        use stream_ext::StreamExt;
+
+        let mut buffer_1 = Buffer::new(0);
+        buffer_1.edit(0..0, "abcdef");
+        let replicated_layer_id = buffer_1.add_marker_layer(true);
+        let local_layer_id = buffer_1.add_marker_layer(false);
+        add_marker(&mut buffer_1, replicated_layer_id, 1..2, json!({"x": 1}));
+        add_marker(&mut buffer_1, local_layer_id, 2..3, json!(null));
+        let buffer_1 = buffer_1.into_shared();
+
+        let mut reactor = reactor::Core::new().unwrap();
+        let foreground = Rc::new(reactor.handle());
+        let buffer_2 = Buffer::remote(
+            foreground,
+            rpc::tests::connect(&mut reactor, super::rpc::Service::new(buffer_1.clone())),
+        ).unwrap();
+        let buffer_3 = Buffer::remote(
+            Rc::new(reactor.handle()),
+            rpc::tests::connect(&mut reactor, super::rpc::Service::new(buffer_1.clone())),
+        ).unwrap();
+        assert_eq!(replicated_markers(&buffer_2), replicated_markers(&buffer_1));
+        assert_eq!(buffer_2.borrow().marker_layers.len(), 1);
+
+        let mut buffer_1_updates = buffer_1.borrow().updates();
+        let mut buffer_2_updates = buffer_2.borrow().updates();
+
+        let marker_id = add_marker(
+            &mut buffer_1.borrow_mut(),
+            replicated_layer_id,
+            3..5,
+            json!([1, 2]),
+        );
+        add_marker(&mut buffer_1.borrow_mut(), local_layer_id, 0..1, json!(null));
+        buffer_2_updates.wait_next(&mut reactor).unwrap();
+        assert_eq!(replicated_markers(&buffer_2), replicated_markers(&buffer_1));
+
+        buffer_1
+            .borrow_mut()
+            .remove_marker(replicated_layer_id, marker_id)
+            .unwrap();
+        buffer_2_updates.wait_next(&mut reactor).unwrap();
+        assert_eq!(replicated_markers(&buffer_2), replicated_markers(&buffer_1));
+
+        // Only as many operations as there are markers are kept for replicas that fall behind
+        {
+            let buffer_1 = buffer_1.borrow();
+            let layer = &buffer_1.marker_layers[&(buffer_1.replica_id, replicated_layer_id)];
+            assert_eq!(layer.operations.len(), 1);
+            assert_eq!(layer.operations_since(layer.version).unwrap().len(), 0);
+            assert_eq!(layer.operations_since(layer.version - 1).unwrap().len(), 1);
+            assert!(layer.operations_since(layer.version - 2).is_none());
+        }
+
+        let buffer_2_layer_id = buffer_2.borrow_mut().add_marker_layer(true);
+        buffer_2_updates.wait_next(&mut reactor).unwrap();
+        buffer_1_updates.wait_next(&mut reactor).unwrap();
+        assert_eq!(replicated_markers(&buffer_1), replicated_markers(&buffer_2));
+
+        // Markers added by one guest are relayed to the others by the host
+        add_marker(&mut buffer_2.borrow_mut(), buffer_2_layer_id, 4..6, json!("y"));
+        buffer_2_updates.wait_next(&mut reactor).unwrap();
+        buffer_1_updates.wait_next(&mut reactor).unwrap();
+        assert_eq!(replicated_markers(&buffer_1), replicated_markers(&buffer_2));
+        let mut remaining_tries = 10;
+        while replicated_markers(&buffer_3) != replicated_markers(&buffer_2) {
+            remaining_tries -= 1;
+            assert!(
+                remaining_tries > 0,
+                "Ran out of patience waiting for markers to converge"
+            );
+            reactor.turn(Some(Duration::from_millis(0)));
+        }
+
+        buffer_1
+            .borrow_mut()
+            .remove_marker_layer(replicated_layer_id)
+            .unwrap();
+        buffer_1_updates.wait_next(&mut reactor).unwrap();
+        buffer_2_updates.wait_next(&mut reactor).unwrap();
+        assert_eq!(replicated_markers(&buffer_2), replicated_markers(&buffer_1));
+
+        drop(buffer_2);
+        buffer_1_updates.wait_next(&mut reactor).unwrap();
+        assert_eq!(buffer_1.borrow().marker_layers.len(), 1);
+    }
+
     struct RandomCharIter<T: Rng>(T);
 
     impl<T: Rng> Iterator for RandomCharIter<T> {
@@ -2603,6 +4474,17 @@ mod tests {
         }
     }
 
+    fn offset_in(text: &[u16], point: Point) -> usize {
+        let mut offset = 0;
+        for _ in 0..point.row {
+            offset += text[offset..]
+                .iter()
+                .position(|c| *c == u16::from(b'\n'))
+                .unwrap() + 1;
+        }
+        offset + point.column as usize
+    }
+
     fn selections(buffer: &Rc<RefCell<Buffer>>) -> Vec<(ReplicaId, SelectionSetId, Selection)> {
         let buffer = buffer.borrow();
 
@@ -2620,6 +4502,73 @@ mod tests {
         selections
     }
 
+    fn add_marker(
+        buffer: &mut Buffer,
+        layer_id: MarkerLayerId,
+        range: Range<usize>,
+        properties: serde_json::Value,
+    ) -> MarkerId {
+        let start = buffer.anchor_before_offset(range.start).unwrap();
+        let end = buffer.anchor_after_offset(range.end).unwrap();
+        buffer.add_marker(layer_id, start..end, properties).unwrap()
+    }
+
+    fn marker_offsets(
+        buffer: &Buffer,
+        layer_id: MarkerLayerId,
+        range: Range<Point>,
+    ) -> Vec<(MarkerId, usize, usize)> {
+        buffer
+            .markers_in_range(layer_id, range)
+            .unwrap()
+            .into_iter()
+            .map(|marker| {
+                (
+                    marker.id,
+                    buffer.offset_for_anchor(&marker.start).unwrap(),
+                    buffer.offset_for_anchor(&marker.end).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    fn replicated_markers(
+        buffer: &Rc<RefCell<Buffer>>,
+    ) -> Vec<(ReplicaId, MarkerLayerId, MarkerId, usize, usize, serde_json::Value)> {
+        let buffer = buffer.borrow();
+        let mut markers = Vec::new();
+        for ((replica_id, layer_id), layer) in &buffer.marker_layers {
+            if layer.replicated {
+                for marker in &layer.markers {
+                    markers.push((
+                        *replica_id,
+                        *layer_id,
+                        marker.id,
+                        buffer.offset_for_anchor(&marker.start).unwrap(),
+                        buffer.offset_for_anchor(&marker.end).unwrap(),
+                        marker.properties.clone(),
+                    ));
+                }
+            }
+        }
+        markers.sort_by_key(|marker| (marker.0, marker.1, marker.2));
+        markers
+    }
+
+    fn selection_offsets(buffer: &Buffer, set_id: SelectionSetId) -> Vec<(usize, usize)> {
+        buffer
+            .selections(set_id)
+            .unwrap()
+            .iter()
+            .map(|selection| {
+                (
+                    buffer.offset_for_anchor(&selection.start).unwrap(),
+                    buffer.offset_for_anchor(&selection.end).unwrap(),
+                )
+            })
+            .collect()
+    }
+
     fn empty_selection(buffer: &Buffer, offset: usize) -> Selection {
         let anchor = buffer.anchor_before_offset(offset).unwrap();
         Selection {
@@ -1,18 +1,37 @@
-use buffer::{self, Buffer, BufferId, Point, Selection, SelectionSetId};
-use futures::{Poll, Stream};
+use buffer::{self, Buffer, BufferId, MarkerLayerId, Point, Selection, SelectionSetId};
+use clipboard::{Clipboard, ClipboardSelection};
+use display_map::{DisplayMap, DisplayPoint, Segment};
+use futures::sync::mpsc;
+use futures::{Async, Poll, Stream};
 use movement;
-use notify_cell::NotifyCell;
+use notify_cell::{NotifyCell, NotifyCellObserver};
+use presence::{Presence, UserProfile, Viewport};
+use project::{self, TextSearchOptions};
+use regex::Regex;
 use serde_json;
 use std::cell::Ref;
 use std::cell::RefCell;
 use std::cmp::{self, Ordering};
+use std::collections::HashMap;
 use std::ops::Range;
 use std::rc::Rc;
+use std::sync::Arc;
+use syntax::{HighlightRequest, Highlighter, Highlights, Language, Token};
 use window::{View, WeakViewHandle, Window};
 use UserId;
 
+const FOLD_PLACEHOLDER: &str = "⋯";
+const BRACKET_PAIRS: &[(&str, &str)] = &[
+    ("(", ")"),
+    ("[", "]"),
+    ("{", "}"),
+    ("\"", "\""),
+    ("'", "'"),
+];
+
 pub trait BufferViewDelegate {
     fn set_active_buffer_view(&mut self, buffer_view: WeakViewHandle<BufferView>);
+    fn save_buffer(&self, buffer_id: BufferId);
 }
 
 pub struct BufferView {
@@ -24,20 +43,96 @@ pub struct BufferView {
     selection_set_id: SelectionSetId,
     height: Option<f64>,
     width: Option<f64>,
+    char_width: Option<f64>,
     line_height: f64,
     scroll_top: f64,
     vertical_margin: u32,
     pending_autoscroll: Option<AutoScrollRequest>,
+    search_matches: Vec<Range<buffer::Anchor>>,
+    // The regex behind the current search matches and the buffer version they were found at.
+    // Edits can break or create matches anywhere, so they're found again once the buffer changes.
+    search_regex: Option<(Regex, buffer::Version)>,
+    search_match_counts: NotifyCell<usize>,
+    gutter_layers: Vec<MarkerLayerId>,
+    last_occurrence: Option<Range<buffer::Anchor>>,
+    // The selections left by the most recent occurrence command, if the occurrences being
+    // selected started out as words under the cursors.
+    word_occurrences: Option<Vec<Range<buffer::Anchor>>>,
+    mouse_drag: Option<MouseDrag>,
+    bracket_matches: RefCell<BracketMatches>,
+    display_map: RefCell<DisplayMap>,
+    clipboard: Rc<RefCell<Clipboard>>,
+    presence: Rc<RefCell<Presence>>,
+    language: Option<Language>,
+    syntax: Option<SyntaxState>,
     delegate: Option<WeakViewHandle<BufferViewDelegate>>,
 }
 
+struct SyntaxState {
+    requests: mpsc::UnboundedSender<HighlightRequest>,
+    edits: buffer::EditSubscription,
+    last_sent_version: buffer::Version,
+    highlight_updates: NotifyCellObserver<Option<Arc<Highlights>>>,
+    highlights: Option<Arc<Highlights>>,
+}
+
+// The selection created or modified by the most recent mouse down, which dragging the mouse
+// extends without disturbing the other selections.
+struct MouseDrag {
+    granularity: DragGranularity,
+    // The range selected when the mouse went down, which remains selected as the drag extends
+    // the selection in either direction.
+    origin: Range<buffer::Anchor>,
+    // The current range of the dragged selection, which distinguishes it from the others.
+    selection: Range<buffer::Anchor>,
+}
+
+// The brackets matched at each cursor, which are cached until the buffer or the selections change
+// since finding a match can scan many rows.
+#[derive(Default)]
+struct BracketMatches {
+    versions: Option<(buffer::Version, buffer::SelectionSetVersion)>,
+    by_cursor: HashMap<Point, Option<(Point, Point)>>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum DragGranularity {
+    Character,
+    Word,
+    Line,
+}
+
 #[derive(Debug, Eq, PartialEq, Serialize)]
 struct SelectionProps {
     pub user_id: UserId,
-    pub start: Point,
-    pub end: Point,
+    pub start: DisplayPoint,
+    pub end: DisplayPoint,
     pub reversed: bool,
     pub remote: bool,
+    pub user: Option<UserProfile>,
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize)]
+struct MarkerProps {
+    pub layer_id: MarkerLayerId,
+    pub start: DisplayPoint,
+    pub end: DisplayPoint,
+    pub properties: serde_json::Value,
+    pub remote: bool,
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize)]
+struct GutterProps {
+    pub line_numbers: Vec<Option<u32>>,
+    pub cursor_rows: Vec<u32>,
+    pub decorations: Vec<GutterDecorationProps>,
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize)]
+struct GutterDecorationProps {
+    pub row: u32,
+    pub layer_id: MarkerLayerId,
+    pub properties: serde_json::Value,
 }
 
 #[derive(Debug, Deserialize)]
@@ -45,7 +140,17 @@ struct SelectionProps {
 enum BufferViewAction {
     UpdateScrollTop { delta: f64 },
     SetDimensions { width: u64, height: u64 },
+    SetCharWidth { char_width: f64 },
+    Click { x: f64, y: f64 },
+    ShiftClick { x: f64, y: f64 },
+    CmdClick { x: f64, y: f64 },
+    DoubleClick { x: f64, y: f64 },
+    TripleClick { x: f64, y: f64 },
+    Drag { x: f64, y: f64 },
     Edit { text: String },
+    Tab,
+    Indent,
+    Outdent,
     Backspace,
     Delete,
     MoveUp,
@@ -56,8 +161,46 @@ enum BufferViewAction {
     SelectDown,
     SelectLeft,
     SelectRight,
+    MoveToPreviousWordStart,
+    MoveToNextWordEnd,
+    MoveToBeginningOfLine,
+    MoveToEndOfLine,
+    MoveToTop,
+    MoveToBottom,
+    SelectToPreviousWordStart,
+    SelectToNextWordEnd,
+    SelectToBeginningOfLine,
+    SelectToEndOfLine,
+    SelectToTop,
+    SelectToBottom,
+    DeleteToPreviousWordStart,
+    DeleteToNextWordEnd,
+    DeleteToBeginningOfLine,
+    DeleteToEndOfLine,
+    PageUp,
+    PageDown,
+    SelectPageUp,
+    SelectPageDown,
     AddSelectionAbove,
     AddSelectionBelow,
+    DuplicateLines,
+    DeleteLines,
+    MoveLinesUp,
+    MoveLinesDown,
+    JoinLines,
+    SelectNextOccurrence,
+    SelectAllOccurrences,
+    SkipOccurrence,
+    GoToMatchingBracket,
+    Fold,
+    Unfold,
+    FoldAll,
+    Copy,
+    Cut,
+    Paste,
+    Undo,
+    Redo,
+    Save,
 }
 
 struct AutoScrollRequest {
@@ -86,6 +229,7 @@ impl BufferView {
             )
         };
 
+        let display_map = RefCell::new(DisplayMap::new(&mut buffer.borrow_mut()));
         let updates_tx = NotifyCell::new(());
         let updates_rx = Box::new(updates_tx.observe().select(buffer.borrow().updates()));
         Self {
@@ -97,14 +241,75 @@ impl BufferView {
             dropped: NotifyCell::new(false),
             height: None,
             width: None,
+            char_width: None,
             line_height: 10.0,
             scroll_top: 0.0,
             vertical_margin: 2,
             pending_autoscroll: None,
+            search_matches: Vec::new(),
+            search_regex: None,
+            search_match_counts: NotifyCell::new(0),
+            gutter_layers: Vec::new(),
+            last_occurrence: None,
+            word_occurrences: None,
+            mouse_drag: None,
+            bracket_matches: RefCell::new(BracketMatches::default()),
+            display_map,
+            clipboard: Rc::new(RefCell::new(Clipboard::new())),
+            presence: Rc::new(RefCell::new(Presence::new())),
+            language: None,
+            syntax: None,
             delegate,
         }
     }
 
+    /// Decorates the gutter next to every row spanned by a marker in the given local marker
+    /// layer with that marker's properties. This allows subsystems such as diagnostics or version
+    /// control to annotate rows without the view knowing about them.
+    pub fn add_gutter_layer(&mut self, layer_id: MarkerLayerId) -> &mut Self {
+        if !self.gutter_layers.contains(&layer_id) {
+            self.gutter_layers.push(layer_id);
+            self.updated();
+        }
+        self
+    }
+
+    pub fn remove_gutter_layer(&mut self, layer_id: MarkerLayerId) -> &mut Self {
+        if let Some(index) = self.gutter_layers.iter().position(|id| *id == layer_id) {
+            self.gutter_layers.remove(index);
+            self.updated();
+        }
+        self
+    }
+
+    /// Sets the language used to highlight the buffer. Highlighting starts once the view is
+    /// mounted, since the buffer is tokenized on the window's background executor.
+    pub fn set_language(&mut self, language: Option<Language>) -> &mut Self {
+        self.language = language;
+        self
+    }
+
+    /// Sets the clipboard used to copy and paste text, which can be shared between views. Each
+    /// view starts out with a clipboard of its own.
+    pub fn set_clipboard(&mut self, clipboard: Rc<RefCell<Clipboard>>) -> &mut Self {
+        self.clipboard = clipboard;
+        self
+    }
+
+    /// Sets the users connected to the workspace, whose profiles label the remote selections
+    /// rendered by this view. The view re-renders whenever somebody joins or leaves.
+    pub fn set_presence(&mut self, presence: Rc<RefCell<Presence>>) -> &mut Self {
+        self.updates_rx = Box::new(
+            self.updates_tx
+                .observe()
+                .select(self.buffer.borrow().updates())
+                .select(presence.borrow().updates()),
+        );
+        self.presence = presence;
+        self.updated();
+        self
+    }
+
     pub fn set_height(&mut self, height: f64) -> &mut Self {
         debug_assert!(height >= 0_f64);
         self.height = Some(height);
@@ -116,6 +321,18 @@ impl BufferView {
     pub fn set_width(&mut self, width: f64) -> &mut Self {
         debug_assert!(width >= 0_f64);
         self.width = Some(width);
+        self.update_wrap_width();
+        self.autoscroll_to_cursor(false);
+        self.updated();
+        self
+    }
+
+    /// Sets the width of a character in the font used to render the buffer. Rows are soft
+    /// wrapped once both this and the width of the view are known.
+    pub fn set_char_width(&mut self, char_width: f64) -> &mut Self {
+        debug_assert!(char_width > 0_f64);
+        self.char_width = Some(char_width);
+        self.update_wrap_width();
         self.autoscroll_to_cursor(false);
         self.updated();
         self
@@ -133,52 +350,391 @@ impl BufferView {
         debug_assert!(scroll_top >= 0_f64);
         self.scroll_top = scroll_top;
         self.pending_autoscroll = None;
+        self.report_viewport();
         self.updated();
         self
     }
 
+    /// Scrolls to where another user is looking and then makes sure their cursor is visible,
+    /// since this view may be shorter than theirs.
+    pub fn scroll_to_user(&mut self, user_id: UserId, scroll_top: f64) -> &mut Self {
+        self.set_scroll_top(scroll_top);
+        let cursor = self.buffer
+            .borrow()
+            .remote_selections()
+            .filter(|&(selection_user_id, _)| selection_user_id == user_id)
+            .filter_map(|(_, selections)| selections.last())
+            .map(|selection| {
+                if selection.reversed {
+                    selection.start.clone()
+                } else {
+                    selection.end.clone()
+                }
+            })
+            .last();
+        if let Some(cursor) = cursor {
+            self.autoscroll_to_range(cursor.clone()..cursor, false)
+                .unwrap();
+        }
+        self
+    }
+
     fn scroll_top(&self) -> f64 {
-        let max_scroll_top = f64::from(self.buffer.borrow().max_point().row) * self.line_height;
-        self.scroll_top.min(max_scroll_top)
+        self.scroll_top.min(self.max_scroll_top())
+    }
+
+    // The last row can be scrolled to the top of the view, but no further.
+    fn max_scroll_top(&self) -> f64 {
+        f64::from(self.display_map().max_row()) * self.line_height
+    }
+
+    fn rows_per_page(&self) -> u32 {
+        let rows = (self.height.unwrap_or(0.0) / self.line_height).floor() as u32;
+        cmp::max(rows, 1)
+    }
+
+    fn scroll_up_by_page(&mut self) -> u32 {
+        let row_count = self.rows_per_page();
+        let scroll_top = self.scroll_top() - f64::from(row_count) * self.line_height;
+        self.set_scroll_top(scroll_top.max(0.0));
+        row_count
+    }
+
+    fn scroll_down_by_page(&mut self) -> u32 {
+        let row_count = self.rows_per_page();
+        let scroll_top = self.scroll_top() + f64::from(row_count) * self.line_height;
+        let max_scroll_top = self.max_scroll_top();
+        self.set_scroll_top(scroll_top.min(max_scroll_top));
+        row_count
     }
 
     fn scroll_bottom(&self) -> f64 {
         self.scroll_top() + self.height.unwrap_or(0.0)
     }
 
+    /// Replaces each selection with the given text. A newline is followed by the leading
+    /// whitespace of the row it splits, up to the position of the selection.
+    ///
+    /// Typing an opening bracket or quote wraps non-empty selections in the pair and inserts the
+    /// closing character after empty ones when the cursor isn't followed by other text. Typing a
+    /// closing character that already follows every cursor moves past it instead of inserting it.
     pub fn edit(&mut self, text: &str) {
+        if self.skip_closing_brackets(text) {
+            return;
+        }
+
+        let closing_bracket = BRACKET_PAIRS
+            .iter()
+            .find(|&&(open, _)| text == open)
+            .map(|&(_, close)| close);
+        let edits = {
+            let buffer = self.buffer.borrow();
+            self.selected_offset_ranges()
+                .into_iter()
+                .zip(self.selected_point_ranges())
+                .map(|(range, point_range)| {
+                    let text_len = text.encode_utf16().count();
+                    if text == "\n" {
+                        let whitespace = leading_whitespace(&buffer, point_range.start.row);
+                        let len = cmp::min(whitespace.len(), point_range.start.column as usize);
+                        let text = format!("\n{}", &whitespace[..len]);
+                        let text_len = text.len();
+                        (range, text, text_len..text_len)
+                    } else if let Some(close) = closing_bracket {
+                        let selected_len = range.end - range.start;
+                        if selected_len > 0 {
+                            let selected_text = String::from_utf16_lossy(&buffer
+                                .iter_starting_at_point(point_range.start)
+                                .take(selected_len)
+                                .collect::<Vec<_>>());
+                            let text = format!("{}{}{}", text, selected_text, close);
+                            (range, text, text_len..text_len + selected_len)
+                        } else if can_auto_close(&buffer, point_range.start, text == close) {
+                            (range, format!("{}{}", text, close), text_len..text_len)
+                        } else {
+                            (range, text.to_string(), text_len..text_len)
+                        }
+                    } else {
+                        (range, text.to_string(), text_len..text_len)
+                    }
+                })
+                .collect()
+        };
+        self.splice_selections(edits);
+    }
+
+    // Moves every cursor past the given closing bracket or quote if all of them are followed by
+    // it, returning whether they moved.
+    fn skip_closing_brackets(&mut self, text: &str) -> bool {
+        if !BRACKET_PAIRS.iter().any(|&(_, close)| text == close) {
+            return false;
+        }
+
+        let closing_char = text.encode_utf16().next();
+        let cursors = self.selected_point_ranges();
+        {
+            let buffer = self.buffer.borrow();
+            for range in &cursors {
+                if range.start != range.end
+                    || buffer.iter_starting_at_point(range.start).next() != closing_char
+                {
+                    return false;
+                }
+            }
+        }
+
+        self.buffer
+            .borrow_mut()
+            .mutate_selections(self.selection_set_id, |buffer, selections| {
+                for (selection, range) in selections.iter_mut().zip(cursors) {
+                    let point = Point::new(range.start.row, range.start.column + 1);
+                    let anchor = buffer.anchor_before_point(point).unwrap();
+                    selection.start = anchor.clone();
+                    selection.end = anchor;
+                    selection.goal_column = None;
+                }
+            })
+            .unwrap();
+        self.autoscroll_to_cursor(false);
+        self.updated();
+        true
+    }
+
+    /// Indents the selected rows if any selection spans multiple rows. Otherwise each selection is
+    /// replaced with a tab, or with enough spaces to reach the next tab stop when using soft tabs.
+    pub fn tab(&mut self) {
+        let point_ranges = self.selected_point_ranges();
+        if point_ranges
+            .iter()
+            .any(|range| range.start.row != range.end.row)
         {
-            let mut offset_ranges = Vec::new();
+            self.indent_selected_rows();
+            return;
+        }
+
+        let edits = {
+            let settings = self.buffer.borrow().indent_settings();
+            self.selected_offset_ranges()
+                .into_iter()
+                .zip(point_ranges)
+                .map(|(range, point_range)| {
+                    let text = if settings.hard_tabs {
+                        String::from("\t")
+                    } else {
+                        let column = point_range.start.column;
+                        " ".repeat((settings.tab_width - column % settings.tab_width) as usize)
+                    };
+                    let text_len = text.len();
+                    (range, text, text_len..text_len)
+                })
+                .collect()
+        };
+        self.splice_selections(edits);
+    }
+
+    /// Inserts a level of indentation at the start of every non-empty row spanned by a selection.
+    pub fn indent_selected_rows(&mut self) {
+        let edits = {
+            let buffer = self.buffer.borrow();
+            let indentation = buffer.indent_settings().unit();
+            let mut edits = Vec::new();
+            for rows in self.selected_row_ranges() {
+                for row in rows {
+                    if buffer.len_for_row(row).unwrap() > 0 {
+                        let start = Point::new(row, 0);
+                        edits.push((start..start, indentation.clone()));
+                    }
+                }
+            }
+            edits
+        };
+        self.edit_rows(edits, Vec::new());
+    }
+
+    /// Removes a level of indentation from the start of every row spanned by a selection. That is
+    /// either a single tab or up to a tab width's worth of spaces.
+    pub fn outdent_selected_rows(&mut self) {
+        let edits = {
+            let buffer = self.buffer.borrow();
+            let tab_width = buffer.indent_settings().tab_width as usize;
+            let mut edits = Vec::new();
+            for rows in self.selected_row_ranges() {
+                for row in rows {
+                    let mut chars = buffer.iter_starting_at_row(row);
+                    let len = match chars.next() {
+                        Some(c) if c == u16::from(b'\t') => 1,
+                        Some(c) if c == u16::from(b' ') => {
+                            1 + chars
+                                .take(tab_width.saturating_sub(1))
+                                .take_while(|c| *c == u16::from(b' '))
+                                .count()
+                        }
+                        _ => 0,
+                    };
+                    if len > 0 {
+                        edits.push((
+                            Point::new(row, 0)..Point::new(row, len as u32),
+                            String::new(),
+                        ));
+                    }
+                }
+            }
+            edits
+        };
+        self.edit_rows(edits, Vec::new());
+    }
+
+    /// Copies the text of each selection to the clipboard. Empty selections copy their entire
+    /// line.
+    pub fn copy(&mut self) {
+        let (_, selections) = self.clipboard_selections();
+        self.clipboard.borrow_mut().write(selections);
+    }
+
+    /// Copies the text of each selection to the clipboard and deletes it. Empty selections cut
+    /// their entire line.
+    pub fn cut(&mut self) {
+        let (ranges, selections) = self.clipboard_selections();
+        self.clipboard.borrow_mut().write(selections);
+        let edits = ranges
+            .into_iter()
+            .map(|range| (range, String::new(), 0..0))
+            .collect();
+        self.splice_selections(edits);
+    }
+
+    /// Replaces each selection with the text on the clipboard. If the clipboard holds text from
+    /// as many selections as there are now, each selection receives the text of one of them.
+    /// Entire lines are pasted above the line of an empty selection.
+    pub fn paste(&mut self) {
+        let clipboard = self.clipboard.borrow().clone();
+        if clipboard.selections().is_empty() {
+            return;
+        }
+
+        let edits = {
+            let buffer = self.buffer.borrow();
+            let ranges = self.selected_offset_ranges();
+            let text = clipboard.text();
+            let entire_line = clipboard
+                .selections()
+                .iter()
+                .all(|selection| selection.entire_line);
+            let distribute = clipboard.selections().len() == ranges.len();
+            let mut edits: Vec<(Range<usize>, String, Range<usize>)> =
+                Vec::with_capacity(ranges.len());
+            let selections = self.selections();
+            for (i, (range, selection)) in ranges.into_iter().zip(selections.iter()).enumerate() {
+                let (text, entire_line) = if distribute {
+                    let selection = &clipboard.selections()[i];
+                    (selection.text.clone(), selection.entire_line)
+                } else {
+                    (text.clone(), entire_line)
+                };
+                let text_len = text.encode_utf16().count();
+                let column = buffer.point_for_anchor(&selection.start).unwrap().column as usize;
+                let row_start = range.start - column;
+                let previous_end = edits.last().map_or(0, |edit| edit.0.end);
+                if entire_line && range.start == range.end && row_start >= previous_end {
+                    let cursor_offset = text_len + column;
+                    edits.push((row_start..row_start, text, cursor_offset..cursor_offset));
+                } else {
+                    edits.push((range, text, text_len..text_len));
+                }
+            }
+            edits
+        };
+        self.splice_selections(edits);
+    }
+
+    fn selected_offset_ranges(&self) -> Vec<Range<usize>> {
+        let buffer = self.buffer.borrow();
+        self.selections()
+            .iter()
+            .map(|selection| {
+                let start = buffer.offset_for_anchor(&selection.start).unwrap();
+                let end = buffer.offset_for_anchor(&selection.end).unwrap();
+                start..end
+            })
+            .collect()
+    }
+
+    // Returns the ranges to copy for each selection along with their text. Empty selections are
+    // expanded to their entire line, and ranges that overlap as a result are merged.
+    fn clipboard_selections(&self) -> (Vec<Range<usize>>, Vec<ClipboardSelection>) {
+        let buffer = self.buffer.borrow();
+        let max_row = buffer.max_point().row;
+        let mut ranges: Vec<(Range<usize>, Point, bool)> = Vec::new();
+        for (selection, range) in self.selections().iter().zip(self.selected_offset_ranges()) {
+            let start = buffer.point_for_anchor(&selection.start).unwrap();
+            let (range, start, entire_line) = if range.start == range.end {
+                let row_start = range.start - start.column as usize;
+                let mut row_end = row_start + buffer.len_for_row(start.row).unwrap() as usize;
+                if start.row < max_row {
+                    row_end += 1;
+                }
+                (row_start..row_end, Point::new(start.row, 0), true)
+            } else {
+                (range, start, false)
+            };
+
+            if let Some(&mut (ref mut last_range, _, ref mut last_entire_line)) = ranges.last_mut()
             {
-                let buffer = self.buffer.borrow();
-                for selection in self.selections().iter() {
-                    let start = buffer.offset_for_anchor(&selection.start).unwrap();
-                    let end = buffer.offset_for_anchor(&selection.end).unwrap();
-                    offset_ranges.push((start, end));
+                if range.start < last_range.end {
+                    last_range.end = cmp::max(last_range.end, range.end);
+                    *last_entire_line = *last_entire_line && entire_line;
+                    continue;
                 }
             }
+            ranges.push((range, start, entire_line));
+        }
+
+        let selections = ranges
+            .iter()
+            .map(|&(ref range, start, entire_line)| {
+                let mut text = String::from_utf16_lossy(&buffer
+                    .iter_starting_at_point(start)
+                    .take(range.end - range.start)
+                    .collect::<Vec<_>>());
+                if entire_line && !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                ClipboardSelection { text, entire_line }
+            })
+            .collect();
+        let ranges = ranges.into_iter().map(|(range, _, _)| range).collect();
+        (ranges, selections)
+    }
 
+    // Replaces each range with the corresponding text in a single transaction, replacing the
+    // selections with the given range of offsets relative to the start of each inserted text. The
+    // ranges must be sorted and must not overlap.
+    fn splice_selections(&mut self, edits: Vec<(Range<usize>, String, Range<usize>)>) {
+        {
             let mut buffer = self.buffer.borrow_mut();
-            for &(start, end) in offset_ranges.iter().rev() {
-                buffer.edit(start..end, text);
+            buffer
+                .start_transaction(Some(self.selection_set_id))
+                .unwrap();
+            for &(ref range, ref text, _) in edits.iter().rev() {
+                buffer.edit(range.clone(), text.as_str());
             }
 
             let mut delta = 0_isize;
             buffer
                 .mutate_selections(self.selection_set_id, |buffer, selections| {
-                    *selections = offset_ranges
-                        .into_iter()
-                        .map(|(start, end)| {
-                            let start = start as isize;
-                            let end = end as isize;
-                            let anchor = buffer
-                                .anchor_before_offset((start + delta) as usize + text.len())
-                                .unwrap();
-                            let deleted_count = end - start;
-                            delta += text.len() as isize - deleted_count;
+                    *selections = edits
+                        .iter()
+                        .map(|&(ref range, ref text, ref selected_range)| {
+                            let start = (range.start as isize + delta) as usize;
+                            let deleted_count = (range.end - range.start) as isize;
+                            delta += text.encode_utf16().count() as isize - deleted_count;
                             Selection {
-                                start: anchor.clone(),
-                                end: anchor,
+                                start: buffer
+                                    .anchor_before_offset(start + selected_range.start)
+                                    .unwrap(),
+                                end: buffer
+                                    .anchor_before_offset(start + selected_range.end)
+                                    .unwrap(),
                                 reversed: false,
                                 goal_column: None,
                             }
@@ -186,15 +742,37 @@ impl BufferView {
                         .collect();
                 })
                 .unwrap();
+            buffer.end_transaction(Some(self.selection_set_id)).unwrap();
         }
 
         self.autoscroll_to_cursor(false);
         self.updated();
     }
 
+    /// Deletes the character before each cursor, along with the character after it if the two
+    /// form an empty bracket pair.
     pub fn backspace(&mut self) {
         if self.all_selections_are_empty() {
             self.select_left();
+            self.buffer
+                .borrow_mut()
+                .mutate_selections(self.selection_set_id, |buffer, selections| {
+                    for selection in selections.iter_mut() {
+                        let start = buffer.point_for_anchor(&selection.start).unwrap();
+                        let mut chars = buffer.iter_starting_at_point(start);
+                        let (previous, next) = (chars.next(), chars.next());
+                        let empty_pair = BRACKET_PAIRS.iter().any(|&(open, close)| {
+                            previous == open.encode_utf16().next()
+                                && next == close.encode_utf16().next()
+                        });
+                        let end = buffer.point_for_anchor(&selection.end).unwrap();
+                        if empty_pair && end == Point::new(start.row, start.column + 1) {
+                            let end = Point::new(end.row, end.column + 1);
+                            selection.end = buffer.anchor_before_point(end).unwrap();
+                        }
+                    }
+                })
+                .unwrap();
         }
         self.edit("");
     }
@@ -206,89 +784,627 @@ impl BufferView {
         self.edit("");
     }
 
-    fn all_selections_are_empty(&self) -> bool {
-        let buffer = self.buffer.borrow();
-        self.selections()
-            .iter()
-            .all(|selection| selection.is_empty(&buffer))
-    }
-
-    pub fn set_selected_anchor_range(
-        &mut self,
-        range: Range<buffer::Anchor>,
-    ) -> Result<(), buffer::Error> {
+    /// Inserts a copy of the rows spanned by each selection below them, moving the selections
+    /// onto the copy.
+    pub fn duplicate_lines(&mut self) {
+        let row_ranges = self.selected_row_ranges();
+        let mut edits = Vec::new();
+        let mut moved_rows = Vec::new();
         {
-            let mut buffer = self.buffer.borrow_mut();
-            // Ensure the supplied anchors are valid to preserve invariants.
-            buffer.offset_for_anchor(&range.start)?;
-            buffer.offset_for_anchor(&range.end)?;
-            buffer.mutate_selections(self.selection_set_id, |_, selections| {
-                selections.clear();
-                selections.push(Selection {
-                    start: range.start,
-                    end: range.end,
-                    reversed: false,
-                    goal_column: None,
-                });
-            })?;
+            let buffer = self.buffer.borrow();
+            let mut inserted_row_count = 0;
+            for rows in row_ranges {
+                let end = Point::new(rows.end - 1, buffer.len_for_row(rows.end - 1).unwrap());
+                edits.push((end..end, format!("\n{}", rows_text(&buffer, &rows))));
+                inserted_row_count += rows.end - rows.start;
+                moved_rows.push((rows, inserted_row_count as i64));
+            }
         }
-        self.autoscroll_to_selection(true);
-        self.updated();
-        Ok(())
+        self.edit_rows(edits, moved_rows);
     }
 
-    pub fn add_selection(&mut self, start: Point, end: Point) {
-        debug_assert!(start <= end); // TODO: Reverse selection if end < start
+    pub fn delete_lines(&mut self) {
+        let edits = {
+            let buffer = self.buffer.borrow();
+            let max_point = buffer.max_point();
+            self.selected_row_ranges()
+                .into_iter()
+                .map(|rows| {
+                    let range = if rows.end <= max_point.row {
+                        Point::new(rows.start, 0)..Point::new(rows.end, 0)
+                    } else if rows.start > 0 {
+                        let start_row = rows.start - 1;
+                        Point::new(start_row, buffer.len_for_row(start_row).unwrap())..max_point
+                    } else {
+                        Point::new(0, 0)..max_point
+                    };
+                    (range, String::new())
+                })
+                .collect()
+        };
+        self.edit_rows(edits, Vec::new());
+    }
 
-        self.buffer
-            .borrow_mut()
-            .mutate_selections(self.selection_set_id, |buffer, selections| {
-                // TODO: Clip points or return a result.
-                let start_anchor = buffer.anchor_before_point(start).unwrap();
-                let end_anchor = buffer.anchor_before_point(end).unwrap();
+    /// Swaps the rows spanned by each selection with the row above them. Nothing moves if any
+    /// selection already spans the first row.
+    pub fn move_lines_up(&mut self) {
+        let row_ranges = self.selected_row_ranges();
+        if row_ranges.first().map_or(true, |rows| rows.start == 0) {
+            return;
+        }
 
-                let index = match selections.binary_search_by(|probe| {
-                    buffer.cmp_anchors(&probe.start, &start_anchor).unwrap()
-                }) {
-                    Ok(index) => index,
-                    Err(index) => index,
-                };
-                selections.insert(
-                    index,
-                    Selection {
-                        start: start_anchor,
-                        end: end_anchor,
-                        reversed: false,
-                        goal_column: None,
-                    },
+        let mut edits = Vec::new();
+        let mut moved_rows = Vec::new();
+        {
+            let buffer = self.buffer.borrow();
+            for rows in row_ranges {
+                let start = Point::new(rows.start - 1, 0);
+                let end = Point::new(rows.end - 1, buffer.len_for_row(rows.end - 1).unwrap());
+                let text = format!(
+                    "{}\n{}",
+                    rows_text(&buffer, &rows),
+                    rows_text(&buffer, &(rows.start - 1..rows.start))
                 );
-            })
-            .unwrap();
-        self.autoscroll_to_cursor(false);
+                edits.push((start..end, text));
+                moved_rows.push((rows, -1));
+            }
+        }
+        self.edit_rows(edits, moved_rows);
     }
 
-    pub fn add_selection_above(&mut self) {
-        self.buffer
-            .borrow_mut()
-            .mutate_selections(self.selection_set_id, |buffer, selections| {
-                let mut new_selections = Vec::new();
-                for selection in selections.iter() {
-                    let selection_start = buffer.point_for_anchor(&selection.start).unwrap();
-                    let selection_end = buffer.point_for_anchor(&selection.end).unwrap();
-                    if selection_start.row != selection_end.row {
-                        continue;
-                    }
+    /// Swaps the rows spanned by each selection with the row below them. Nothing moves if any
+    /// selection already spans the last row.
+    pub fn move_lines_down(&mut self) {
+        let row_ranges = self.selected_row_ranges();
+        let max_row = self.buffer.borrow().max_point().row;
+        if row_ranges.last().map_or(true, |rows| rows.end > max_row) {
+            return;
+        }
 
-                    let goal_column = selection.goal_column.unwrap_or(selection_end.column);
-                    let mut row = selection_start.row;
-                    while row > 0 {
-                        row -= 1;
-                        let max_column = buffer.len_for_row(row).unwrap();
+        let mut edits = Vec::new();
+        let mut moved_rows = Vec::new();
+        {
+            let buffer = self.buffer.borrow();
+            for rows in row_ranges {
+                let start = Point::new(rows.start, 0);
+                let end = Point::new(rows.end, buffer.len_for_row(rows.end).unwrap());
+                let text = format!(
+                    "{}\n{}",
+                    rows_text(&buffer, &(rows.end..rows.end + 1)),
+                    rows_text(&buffer, &rows)
+                );
+                edits.push((start..end, text));
+                moved_rows.push((rows, 1));
+            }
+        }
+        self.edit_rows(edits, moved_rows);
+    }
 
-                        let start_column;
-                        let end_column;
-                        let add_selection;
-                        if selection_start == selection_end {
+    /// Joins the rows spanned by each selection into a single row, or joins the row containing
+    /// the selection with the one below if it only spans one row. Each line break is replaced
+    /// along with the indentation that follows it by a single space.
+    pub fn join_lines(&mut self) {
+        let edits = {
+            let buffer = self.buffer.borrow();
+            let max_row = buffer.max_point().row;
+            let mut edits = Vec::new();
+            for rows in self.selected_row_ranges() {
+                let end_row = cmp::min(cmp::max(rows.end - 1, rows.start + 1), max_row);
+                for row in rows.start..end_row {
+                    let row_len = buffer.len_for_row(row).unwrap();
+                    let (next_row_start, separator) = match indentation_for_row(&buffer, row + 1)
+                    {
+                        Some(indentation) if row_len > 0 => (indentation, " "),
+                        Some(indentation) => (indentation, ""),
+                        None => (buffer.len_for_row(row + 1).unwrap(), ""),
+                    };
+                    edits.push((
+                        Point::new(row, row_len)..Point::new(row + 1, next_row_start),
+                        String::from(separator),
+                    ));
+                }
+            }
+            edits
+        };
+        self.edit_rows(edits, Vec::new());
+    }
+
+    // Returns the rows spanned by the selections, merging ranges that overlap or are adjacent. A
+    // non-empty selection ending at the start of a row doesn't span that row.
+    fn selected_row_ranges(&self) -> Vec<Range<u32>> {
+        let mut row_ranges: Vec<Range<u32>> = Vec::new();
+        for range in self.selected_point_ranges() {
+            let mut end_row = range.end.row;
+            if range.end.column == 0 && range.end.row > range.start.row {
+                end_row -= 1;
+            }
+
+            let rows = range.start.row..end_row + 1;
+            if let Some(last_rows) = row_ranges.last_mut() {
+                if rows.start <= last_rows.end {
+                    last_rows.end = cmp::max(last_rows.end, rows.end);
+                    continue;
+                }
+            }
+            row_ranges.push(rows);
+        }
+        row_ranges
+    }
+
+    // Applies the given edits in a single transaction. Selections starting within one of the
+    // moved row ranges are then shifted by the corresponding number of rows, while all other
+    // selections stay wherever their anchors ended up. The edits must be sorted and must not
+    // overlap.
+    fn edit_rows(
+        &mut self,
+        edits: Vec<(Range<Point>, String)>,
+        moved_rows: Vec<(Range<u32>, i64)>,
+    ) {
+        if edits.is_empty() {
+            return;
+        }
+
+        let selected_ranges = self.selected_point_ranges();
+        {
+            let mut buffer = self.buffer.borrow_mut();
+            buffer
+                .start_transaction(Some(self.selection_set_id))
+                .unwrap();
+            let offset_ranges = edits
+                .iter()
+                .map(|&(ref range, _)| {
+                    let start = buffer.offset_for_point(range.start).unwrap();
+                    let end = buffer.offset_for_point(range.end).unwrap();
+                    start..end
+                })
+                .collect::<Vec<_>>();
+            for (range, &(_, ref text)) in offset_ranges.into_iter().zip(edits.iter()).rev() {
+                buffer.edit(range, text.as_str());
+            }
+
+            buffer
+                .mutate_selections(self.selection_set_id, |buffer, selections| {
+                    for (selection, range) in selections.iter_mut().zip(selected_ranges) {
+                        let delta = moved_rows.iter().find(|&&(ref rows, _)| {
+                            rows.start <= range.start.row && range.start.row < rows.end
+                        });
+                        if let Some(&(_, delta)) = delta {
+                            let shift = |point: Point| {
+                                Point::new((point.row as i64 + delta) as u32, point.column)
+                            };
+                            selection.start =
+                                buffer.anchor_before_point(shift(range.start)).unwrap();
+                            selection.end = buffer.anchor_before_point(shift(range.end)).unwrap();
+                        }
+                    }
+                })
+                .unwrap();
+            buffer.end_transaction(Some(self.selection_set_id)).unwrap();
+        }
+
+        self.autoscroll_to_cursor(false);
+        self.updated();
+    }
+
+    pub fn undo(&mut self) {
+        let op = self.buffer.borrow_mut().undo();
+        if op.is_some() {
+            self.autoscroll_to_cursor(false);
+            self.updated();
+        }
+    }
+
+    pub fn redo(&mut self) {
+        let op = self.buffer.borrow_mut().redo();
+        if op.is_some() {
+            self.autoscroll_to_cursor(false);
+            self.updated();
+        }
+    }
+
+    pub fn save(&self) {
+        if let Some(ref delegate) = self.delegate {
+            let buffer_id = self.buffer_id();
+            delegate.map(|delegate| delegate.save_buffer(buffer_id));
+        }
+    }
+
+    /// Finds all matches of the given query and highlights them, replacing any previous matches.
+    /// The matches are found again whenever the buffer changes, so they stay accurate as it is
+    /// edited.
+    pub fn search(&mut self, query: &str, options: TextSearchOptions) -> Result<usize, String> {
+        self.search_regex = None;
+        let result = if query.is_empty() {
+            Ok(())
+        } else {
+            options.build_regex(query).map(|regex| {
+                let version = self.buffer.borrow().version.clone();
+                self.search_regex = Some((regex, version));
+            })
+        };
+        self.find_search_matches();
+        result
+            .map(|_| self.search_matches.len())
+            .map_err(|error| error.to_string())
+    }
+
+    pub fn clear_search(&mut self) {
+        self.search_regex = None;
+        if !self.search_matches.is_empty() {
+            self.find_search_matches();
+        }
+    }
+
+    pub fn search_match_count(&self) -> usize {
+        self.search_matches.len()
+    }
+
+    /// Returns a stream of the number of search matches, which changes as the buffer is edited.
+    pub fn search_match_counts(&self) -> NotifyCellObserver<usize> {
+        self.search_match_counts.observe()
+    }
+
+    fn refresh_search_matches(&mut self) {
+        let stale = match self.search_regex {
+            Some((_, ref version)) => *version != self.buffer.borrow().version,
+            None => false,
+        };
+        if stale {
+            self.find_search_matches();
+        }
+    }
+
+    fn find_search_matches(&mut self) {
+        self.search_matches.clear();
+        if let Some((ref regex, ref mut version)) = self.search_regex {
+            let buffer = self.buffer.borrow();
+            for range in project::find_ranges(regex, &buffer.to_u16_chars()) {
+                let start = buffer.anchor_after_point(range.start).unwrap();
+                let end = buffer.anchor_before_point(range.end).unwrap();
+                self.search_matches.push(start..end);
+            }
+            *version = buffer.version.clone();
+        }
+        self.search_match_counts.set(self.search_matches.len());
+        self.updated();
+    }
+
+    pub fn select_next_search_match(&mut self) {
+        self.refresh_search_matches();
+        let index = {
+            let buffer = self.buffer.borrow();
+            let selections = self.selections();
+            let cursor = buffer
+                .offset_for_anchor(&selections.last().unwrap().end)
+                .unwrap();
+            match self.search_matches.binary_search_by(|probe| {
+                let start = buffer.offset_for_anchor(&probe.start).unwrap();
+                start.cmp(&cursor).then(Ordering::Greater)
+            }) {
+                Ok(index) | Err(index) => index,
+            }
+        };
+        self.select_search_match(index);
+    }
+
+    pub fn select_previous_search_match(&mut self) {
+        self.refresh_search_matches();
+        let index = {
+            let buffer = self.buffer.borrow();
+            let selections = self.selections();
+            let cursor = buffer
+                .offset_for_anchor(&selections.last().unwrap().start)
+                .unwrap();
+            match self.search_matches.binary_search_by(|probe| {
+                let end = buffer.offset_for_anchor(&probe.end).unwrap();
+                end.cmp(&cursor).then(Ordering::Less)
+            }) {
+                Ok(index) | Err(index) => index,
+            }
+        };
+        let len = self.search_matches.len();
+        self.select_search_match((index + len).saturating_sub(1));
+    }
+
+    /// Replaces the selected match, if any, and then selects the next one. If no match is
+    /// selected, this only selects the next match so it can be reviewed before replacing it.
+    pub fn replace_search_match(&mut self, replacement: &str) {
+        self.refresh_search_matches();
+        let selected_match = {
+            let buffer = self.buffer.borrow();
+            let selections = self.selections();
+            if selections.len() == 1 {
+                let start = buffer.offset_for_anchor(&selections[0].start).unwrap();
+                let end = buffer.offset_for_anchor(&selections[0].end).unwrap();
+                self.search_matches.iter().position(|range| {
+                    buffer.offset_for_anchor(&range.start).unwrap() == start
+                        && buffer.offset_for_anchor(&range.end).unwrap() == end
+                })
+            } else {
+                None
+            }
+        };
+
+        if let Some(index) = selected_match {
+            self.search_matches.remove(index);
+            self.edit(replacement);
+        }
+        self.select_next_search_match();
+    }
+
+    pub fn replace_all_search_matches(&mut self, replacement: &str) {
+        self.refresh_search_matches();
+        if self.search_matches.is_empty() {
+            return;
+        }
+
+        {
+            let mut buffer = self.buffer.borrow_mut();
+            buffer
+                .start_transaction(Some(self.selection_set_id))
+                .unwrap();
+            for range in self.search_matches.drain(..).rev() {
+                let start = buffer.offset_for_anchor(&range.start).unwrap();
+                let end = buffer.offset_for_anchor(&range.end).unwrap();
+                buffer.edit(start..end, replacement);
+            }
+            buffer.end_transaction(Some(self.selection_set_id)).unwrap();
+        }
+        self.refresh_search_matches();
+        self.autoscroll_to_cursor(false);
+        self.updated();
+    }
+
+    fn select_search_match(&mut self, index: usize) {
+        if !self.search_matches.is_empty() {
+            let range = self.search_matches[index % self.search_matches.len()].clone();
+            self.set_selected_anchor_range(range).unwrap();
+        }
+    }
+
+    fn all_selections_are_empty(&self) -> bool {
+        let buffer = self.buffer.borrow();
+        self.selections()
+            .iter()
+            .all(|selection| selection.is_empty(&buffer))
+    }
+
+    pub fn set_selected_anchor_range(
+        &mut self,
+        range: Range<buffer::Anchor>,
+    ) -> Result<(), buffer::Error> {
+        self.replace_selections(range, false)?;
+        self.autoscroll_to_selection(true);
+        Ok(())
+    }
+
+    fn replace_selections(
+        &mut self,
+        range: Range<buffer::Anchor>,
+        reversed: bool,
+    ) -> Result<(), buffer::Error> {
+        {
+            let mut buffer = self.buffer.borrow_mut();
+            // Ensure the supplied anchors are valid to preserve invariants.
+            buffer.offset_for_anchor(&range.start)?;
+            buffer.offset_for_anchor(&range.end)?;
+            buffer.mutate_selections(self.selection_set_id, |_, selections| {
+                selections.clear();
+                selections.push(Selection {
+                    start: range.start,
+                    end: range.end,
+                    reversed,
+                    goal_column: None,
+                });
+            })?;
+        }
+        self.updated();
+        Ok(())
+    }
+
+    /// Returns the buffer point displayed nearest to the given position, which is measured in
+    /// pixels from the top left corner of the visible text.
+    pub fn point_for_position(&self, x: f64, y: f64) -> Point {
+        let row = ((self.scroll_top() + y) / self.line_height).max(0.0).floor() as u32;
+        let column = self.char_width
+            .map_or(0, |char_width| (x / char_width).max(0.0).round() as u32);
+        let buffer = self.buffer.borrow();
+        let display_map = self.display_map();
+        let point = display_map.to_buffer_point(DisplayPoint::new(row, column), &buffer);
+        clip_to_folds(&buffer, &display_map, point)
+    }
+
+    /// Replaces the selections with a cursor at the given point, as when clicking.
+    pub fn select_point(&mut self, point: Point) {
+        let anchor = self.buffer.borrow().anchor_before_point(point).unwrap();
+        self.replace_selections(anchor.clone()..anchor, false)
+            .unwrap();
+    }
+
+    /// Replaces the selections with one extending from the tail of the last selection to the
+    /// given point, as when shift-clicking or dragging.
+    pub fn select_to_point(&mut self, point: Point) {
+        let (range, reversed) = {
+            let buffer = self.buffer.borrow();
+            let tail = {
+                let selections = self.selections();
+                let selection = selections.last().unwrap();
+                if selection.reversed {
+                    selection.end.clone()
+                } else {
+                    selection.start.clone()
+                }
+            };
+            let head = buffer.anchor_before_point(point).unwrap();
+            if buffer.cmp_anchors(&head, &tail).unwrap() == Ordering::Less {
+                (head..tail, true)
+            } else {
+                (tail..head, false)
+            }
+        };
+        self.replace_selections(range, reversed).unwrap();
+        self.autoscroll_to_cursor(false);
+    }
+
+    /// Selects the word containing the given point, as when double-clicking.
+    pub fn select_word_at_point(&mut self, point: Point) {
+        let range = {
+            let buffer = self.buffer.borrow();
+            let range = movement::word_range(&buffer, point);
+            buffer.anchor_before_point(range.start).unwrap()
+                ..buffer.anchor_before_point(range.end).unwrap()
+        };
+        self.replace_selections(range, false).unwrap();
+    }
+
+    /// Selects the entire row containing the given point along with its trailing newline, as
+    /// when triple-clicking.
+    pub fn select_line_at_point(&mut self, point: Point) {
+        let range = {
+            let buffer = self.buffer.borrow();
+            let range = line_range(&buffer, point.row);
+            buffer.anchor_before_point(range.start).unwrap()
+                ..buffer.anchor_before_point(range.end).unwrap()
+        };
+        self.replace_selections(range, false).unwrap();
+    }
+
+    // Remembers the selection containing the point where the mouse went down, so that dragging
+    // extends it by the given granularity.
+    fn start_drag(&mut self, point: Point, granularity: DragGranularity) {
+        let mouse_drag = {
+            let buffer = self.buffer.borrow();
+            self.selections()
+                .iter()
+                .find(|selection| {
+                    buffer.point_for_anchor(&selection.start).unwrap() <= point
+                        && point <= buffer.point_for_anchor(&selection.end).unwrap()
+                })
+                .map(|selection| {
+                    let origin = if granularity == DragGranularity::Character {
+                        selection.tail().clone()..selection.tail().clone()
+                    } else {
+                        selection.anchor_range()
+                    };
+                    MouseDrag {
+                        granularity,
+                        origin,
+                        selection: selection.anchor_range(),
+                    }
+                })
+        };
+        self.mouse_drag = mouse_drag;
+    }
+
+    /// Extends the selection created by the most recent mouse down to the given point, by whole
+    /// words or rows if the selection was created by double- or triple-clicking. The other
+    /// selections are left as they are.
+    pub fn drag_to_point(&mut self, point: Point) {
+        let (origin, granularity, dragged) = match self.mouse_drag {
+            Some(ref drag) => (drag.origin.clone(), drag.granularity, drag.selection.clone()),
+            None => return self.select_to_point(point),
+        };
+
+        let selection = {
+            let buffer = self.buffer.borrow();
+            let origin_start = buffer.point_for_anchor(&origin.start).unwrap();
+            let origin_end = buffer.point_for_anchor(&origin.end).unwrap();
+            let head = match granularity {
+                DragGranularity::Character => point..point,
+                DragGranularity::Word => movement::word_range(&buffer, point),
+                DragGranularity::Line => line_range(&buffer, point.row),
+            };
+            let (range, reversed) = if head.start < origin_start {
+                (head.start..origin_end, true)
+            } else {
+                (origin_start..cmp::max(head.end, origin_end), false)
+            };
+            Selection {
+                start: buffer.anchor_before_point(range.start).unwrap(),
+                end: buffer.anchor_before_point(range.end).unwrap(),
+                reversed,
+                goal_column: None,
+            }
+        };
+
+        self.mouse_drag.as_mut().unwrap().selection = selection.anchor_range();
+        self.buffer
+            .borrow_mut()
+            .mutate_selections(self.selection_set_id, |buffer, selections| {
+                let dragged_start = buffer.point_for_anchor(&dragged.start).unwrap();
+                let dragged_end = buffer.point_for_anchor(&dragged.end).unwrap();
+                if let Some(index) = selections.iter().position(|selection| {
+                    buffer.point_for_anchor(&selection.start).unwrap() == dragged_start
+                        && buffer.point_for_anchor(&selection.end).unwrap() == dragged_end
+                }) {
+                    selections.remove(index);
+                }
+
+                let index = match selections.binary_search_by(|probe| {
+                    buffer.cmp_anchors(&probe.start, &selection.start).unwrap()
+                }) {
+                    Ok(index) => index,
+                    Err(index) => index,
+                };
+                selections.insert(index, selection);
+            })
+            .unwrap();
+        self.autoscroll_to_cursor(false);
+        self.updated();
+    }
+
+    pub fn add_selection(&mut self, start: Point, end: Point) {
+        debug_assert!(start <= end); // TODO: Reverse selection if end < start
+
+        self.buffer
+            .borrow_mut()
+            .mutate_selections(self.selection_set_id, |buffer, selections| {
+                // TODO: Clip points or return a result.
+                let start_anchor = buffer.anchor_before_point(start).unwrap();
+                let end_anchor = buffer.anchor_before_point(end).unwrap();
+
+                let index = match selections.binary_search_by(|probe| {
+                    buffer.cmp_anchors(&probe.start, &start_anchor).unwrap()
+                }) {
+                    Ok(index) => index,
+                    Err(index) => index,
+                };
+                selections.insert(
+                    index,
+                    Selection {
+                        start: start_anchor,
+                        end: end_anchor,
+                        reversed: false,
+                        goal_column: None,
+                    },
+                );
+            })
+            .unwrap();
+        self.autoscroll_to_cursor(false);
+    }
+
+    pub fn add_selection_above(&mut self) {
+        self.buffer
+            .borrow_mut()
+            .mutate_selections(self.selection_set_id, |buffer, selections| {
+                let mut new_selections = Vec::new();
+                for selection in selections.iter() {
+                    let selection_start = buffer.point_for_anchor(&selection.start).unwrap();
+                    let selection_end = buffer.point_for_anchor(&selection.end).unwrap();
+                    if selection_start.row != selection_end.row {
+                        continue;
+                    }
+
+                    let goal_column = selection.goal_column.unwrap_or(selection_end.column);
+                    let mut row = selection_start.row;
+                    while row > 0 {
+                        row -= 1;
+                        let max_column = buffer.len_for_row(row).unwrap();
+
+                        let start_column;
+                        let end_column;
+                        let add_selection;
+                        if selection_start == selection_end {
                             start_column = cmp::min(goal_column, max_column);
                             end_column = cmp::min(goal_column, max_column);
                             add_selection = selection_end.column == 0 || end_column > 0;
@@ -391,164 +1507,736 @@ impl BufferView {
         self.autoscroll_to_cursor(false);
     }
 
-    pub fn move_left(&mut self) {
+    /// Expands empty selections to the word under each cursor. Once every selection is
+    /// non-empty, adds a selection at the next occurrence of the most recently selected text,
+    /// wrapping around to the start of the buffer. Occurrences of text that was selected by
+    /// expanding a cursor to a word must be whole words themselves.
+    pub fn select_next_occurrence(&mut self) {
+        if !self.select_words_under_cursors() {
+            let index = self.last_occurrence_index();
+            if let Some(range) = self.next_occurrence(index, false) {
+                self.add_occurrence(range, None);
+            }
+        }
+    }
+
+    /// Like `select_next_occurrence`, but drops the most recently added occurrence from the
+    /// selections in favor of the next one.
+    pub fn skip_occurrence(&mut self) {
+        if !self.select_words_under_cursors() {
+            let index = self.last_occurrence_index();
+            if let Some(range) = self.next_occurrence(index, true) {
+                self.add_occurrence(range, Some(index));
+            }
+        }
+    }
+
+    pub fn select_all_occurrences(&mut self) {
+        self.select_words_under_cursors();
+        let index = self.last_occurrence_index();
+        let occurrences = self.occurrences(index);
+        self.word_occurrences = None;
+        if occurrences.is_empty() {
+            return;
+        }
+
+        let selected_ranges = self.selected_point_ranges();
         self.buffer
             .borrow_mut()
             .mutate_selections(self.selection_set_id, |buffer, selections| {
-                for selection in selections.iter_mut() {
-                    let start = buffer.point_for_anchor(&selection.start).unwrap();
-                    let end = buffer.point_for_anchor(&selection.end).unwrap();
-
-                    if start != end {
-                        selection.end = selection.start.clone();
-                    } else {
-                        let cursor = buffer
-                            .anchor_before_point(movement::left(&buffer, start))
-                            .unwrap();
-                        selection.start = cursor.clone();
-                        selection.end = cursor;
+                for range in occurrences {
+                    if selected_ranges.iter().any(|selected| overlaps(selected, &range)) {
+                        continue;
                     }
-                    selection.reversed = false;
-                    selection.goal_column = None;
+
+                    let start = buffer.anchor_before_point(range.start).unwrap();
+                    let end = buffer.anchor_before_point(range.end).unwrap();
+                    let index = match selections.binary_search_by(|probe| {
+                        buffer.cmp_anchors(&probe.start, &start).unwrap()
+                    }) {
+                        Ok(index) => index,
+                        Err(index) => index,
+                    };
+                    selections.insert(
+                        index,
+                        Selection {
+                            start,
+                            end,
+                            reversed: false,
+                            goal_column: None,
+                        },
+                    );
                 }
             })
             .unwrap();
+        self.last_occurrence = None;
         self.autoscroll_to_cursor(false);
+        self.updated();
     }
 
-    pub fn select_left(&mut self) {
+    // Expands each empty selection to the word under its cursor, returning false if there were
+    // no empty selections to expand.
+    fn select_words_under_cursors(&mut self) -> bool {
+        if self.selected_point_ranges()
+            .iter()
+            .all(|range| range.start != range.end)
+        {
+            return false;
+        }
+
         self.buffer
             .borrow_mut()
             .mutate_selections(self.selection_set_id, |buffer, selections| {
                 for selection in selections.iter_mut() {
-                    let head = buffer.point_for_anchor(selection.head()).unwrap();
-                    let cursor = buffer
-                        .anchor_before_point(movement::left(&buffer, head))
-                        .unwrap();
-                    selection.set_head(&buffer, cursor);
-                    selection.goal_column = None;
+                    if selection.is_empty(buffer) {
+                        let cursor = buffer.point_for_anchor(&selection.start).unwrap();
+                        let range = movement::word_range(buffer, cursor);
+                        selection.start = buffer.anchor_before_point(range.start).unwrap();
+                        selection.end = buffer.anchor_before_point(range.end).unwrap();
+                        selection.reversed = false;
+                        selection.goal_column = None;
+                    }
                 }
             })
             .unwrap();
+        self.last_occurrence = None;
+        self.word_occurrences = Some(self.selected_anchor_ranges());
         self.autoscroll_to_cursor(false);
+        self.updated();
+        true
     }
 
-    pub fn move_right(&mut self) {
-        self.buffer
-            .borrow_mut()
-            .mutate_selections(self.selection_set_id, |buffer, selections| {
-                for selection in selections.iter_mut() {
-                    let start = buffer.point_for_anchor(&selection.start).unwrap();
-                    let end = buffer.point_for_anchor(&selection.end).unwrap();
+    // Returns whether the selections are still the ones left by the most recent occurrence
+    // command after expanding cursors to words, in which case only whole words are matched.
+    fn selecting_word_occurrences(&self) -> bool {
+        self.word_occurrences.as_ref().map_or(false, |ranges| {
+            let buffer = self.buffer.borrow();
+            let selected_ranges = self.selected_point_ranges();
+            ranges.len() == selected_ranges.len()
+                && ranges.iter().zip(selected_ranges).all(|(range, selected)| {
+                    buffer.point_for_anchor(&range.start).unwrap() == selected.start
+                        && buffer.point_for_anchor(&range.end).unwrap() == selected.end
+                })
+        })
+    }
 
-                    if start != end {
-                        selection.start = selection.end.clone();
-                    } else {
-                        let cursor = buffer
-                            .anchor_before_point(movement::right(&buffer, end))
-                            .unwrap();
-                        selection.start = cursor.clone();
-                        selection.end = cursor;
-                    }
-                    selection.reversed = false;
-                    selection.goal_column = None;
-                }
+    // Returns the index of the most recently added occurrence, falling back to the last
+    // selection if that occurrence is no longer selected.
+    fn last_occurrence_index(&self) -> usize {
+        let selected_ranges = self.selected_point_ranges();
+        self.last_occurrence
+            .as_ref()
+            .and_then(|range| {
+                let buffer = self.buffer.borrow();
+                let start = buffer.point_for_anchor(&range.start).unwrap();
+                let end = buffer.point_for_anchor(&range.end).unwrap();
+                selected_ranges
+                    .iter()
+                    .position(|selected| selected.start == start && selected.end == end)
             })
-            .unwrap();
-        self.autoscroll_to_cursor(false);
+            .unwrap_or(selected_ranges.len() - 1)
     }
 
-    pub fn select_right(&mut self) {
+    // Finds every case-sensitive match of the text covered by the selection at the given index,
+    // only matching whole words if the selections were expanded to words under the cursors.
+    fn occurrences(&self, index: usize) -> Vec<Range<Point>> {
+        let whole_word = self.selecting_word_occurrences();
+        let buffer = self.buffer.borrow();
+        let range = self.selected_offset_ranges()[index].clone();
+        let start = buffer.point_for_anchor(&self.selections()[index].start).unwrap();
+        let text = String::from_utf16_lossy(&buffer
+            .iter_starting_at_point(start)
+            .take(range.end - range.start)
+            .collect::<Vec<_>>());
+        if text.is_empty() {
+            return Vec::new();
+        }
+
+        let options = TextSearchOptions {
+            case_sensitive: true,
+            whole_word,
+            ..TextSearchOptions::default()
+        };
+        let regex = options.build_regex(&text).unwrap();
+        project::find_ranges(&regex, &buffer.to_u16_chars())
+    }
+
+    // Returns the first occurrence following the selection at the given index that doesn't
+    // overlap any selection, wrapping around to the start of the buffer. When skipping, the
+    // selection at the index is about to be removed and so doesn't count as an overlap.
+    fn next_occurrence(&self, index: usize, skipping: bool) -> Option<Range<Point>> {
+        let occurrences = self.occurrences(index);
+        let selected_ranges = self.selected_point_ranges();
+        let reference_end = selected_ranges[index].end;
+        let split_index = occurrences
+            .iter()
+            .position(|range| range.start >= reference_end)
+            .unwrap_or(occurrences.len());
+        let (before, after) = occurrences.split_at(split_index);
+        after
+            .iter()
+            .chain(before.iter())
+            .find(|range| {
+                selected_ranges
+                    .iter()
+                    .enumerate()
+                    .all(|(i, selected)| skipping && i == index || !overlaps(selected, range))
+            })
+            .cloned()
+    }
+
+    // Adds a selection for the given occurrence, removing the selection at the given index first
+    // if one is supplied.
+    fn add_occurrence(&mut self, range: Range<Point>, replaced_index: Option<usize>) {
+        let word_occurrences = self.selecting_word_occurrences();
+        let anchor_range = {
+            let buffer = self.buffer.borrow();
+            buffer.anchor_before_point(range.start).unwrap()
+                ..buffer.anchor_before_point(range.end).unwrap()
+        };
         self.buffer
             .borrow_mut()
             .mutate_selections(self.selection_set_id, |buffer, selections| {
-                for selection in selections.iter_mut() {
-                    let head = buffer.point_for_anchor(selection.head()).unwrap();
-                    let cursor = buffer
-                        .anchor_before_point(movement::right(&buffer, head))
-                        .unwrap();
-                    selection.set_head(&buffer, cursor);
-                    selection.goal_column = None;
+                if let Some(index) = replaced_index {
+                    selections.remove(index);
                 }
+                let index = match selections.binary_search_by(|probe| {
+                    buffer.cmp_anchors(&probe.start, &anchor_range.start).unwrap()
+                }) {
+                    Ok(index) => index,
+                    Err(index) => index,
+                };
+                selections.insert(
+                    index,
+                    Selection {
+                        start: anchor_range.start.clone(),
+                        end: anchor_range.end.clone(),
+                        reversed: false,
+                        goal_column: None,
+                    },
+                );
             })
             .unwrap();
+        self.last_occurrence = Some(anchor_range.clone());
+        self.word_occurrences = if word_occurrences {
+            Some(self.selected_anchor_ranges())
+        } else {
+            None
+        };
+        self.autoscroll_to_range(anchor_range, false).unwrap();
+        self.updated();
+    }
+
+    fn selected_anchor_ranges(&self) -> Vec<Range<buffer::Anchor>> {
+        self.selections()
+            .iter()
+            .map(|selection| selection.start.clone()..selection.end.clone())
+            .collect()
+    }
+
+    fn selected_point_ranges(&self) -> Vec<Range<Point>> {
+        let buffer = self.buffer.borrow();
+        self.selections()
+            .iter()
+            .map(|selection| {
+                let start = buffer.point_for_anchor(&selection.start).unwrap();
+                let end = buffer.point_for_anchor(&selection.end).unwrap();
+                start..end
+            })
+            .collect()
+    }
+
+    pub fn move_left(&mut self) {
+        {
+            let display_map = self.display_map();
+            self.buffer
+                .borrow_mut()
+                .mutate_selections(self.selection_set_id, |buffer, selections| {
+                    for selection in selections.iter_mut() {
+                        let start = buffer.point_for_anchor(&selection.start).unwrap();
+                        let end = buffer.point_for_anchor(&selection.end).unwrap();
+
+                        if start != end {
+                            selection.end = selection.start.clone();
+                        } else {
+                            let cursor = buffer
+                                .anchor_before_point(movement::left(&buffer, &display_map, start))
+                                .unwrap();
+                            selection.start = cursor.clone();
+                            selection.end = cursor;
+                        }
+                        selection.reversed = false;
+                        selection.goal_column = None;
+                    }
+                })
+                .unwrap();
+        }
         self.autoscroll_to_cursor(false);
     }
 
-    pub fn move_up(&mut self) {
-        self.buffer
-            .borrow_mut()
-            .mutate_selections(self.selection_set_id, |buffer, selections| {
-                for selection in selections.iter_mut() {
-                    let start = buffer.point_for_anchor(&selection.start).unwrap();
-                    let end = buffer.point_for_anchor(&selection.end).unwrap();
-                    if start != end {
+    pub fn select_left(&mut self) {
+        {
+            let display_map = self.display_map();
+            self.buffer
+                .borrow_mut()
+                .mutate_selections(self.selection_set_id, |buffer, selections| {
+                    for selection in selections.iter_mut() {
+                        let head = buffer.point_for_anchor(selection.head()).unwrap();
+                        let cursor = buffer
+                            .anchor_before_point(movement::left(&buffer, &display_map, head))
+                            .unwrap();
+                        selection.set_head(&buffer, cursor);
                         selection.goal_column = None;
                     }
+                })
+                .unwrap();
+        }
+        self.autoscroll_to_cursor(false);
+    }
 
-                    let (start, goal_column) = movement::up(&buffer, start, selection.goal_column);
-                    let cursor = buffer.anchor_before_point(start).unwrap();
-                    selection.start = cursor.clone();
-                    selection.end = cursor;
-                    selection.goal_column = goal_column;
-                    selection.reversed = false;
-                }
-            })
-            .unwrap();
+    pub fn move_right(&mut self) {
+        {
+            let display_map = self.display_map();
+            self.buffer
+                .borrow_mut()
+                .mutate_selections(self.selection_set_id, |buffer, selections| {
+                    for selection in selections.iter_mut() {
+                        let start = buffer.point_for_anchor(&selection.start).unwrap();
+                        let end = buffer.point_for_anchor(&selection.end).unwrap();
+
+                        if start != end {
+                            selection.start = selection.end.clone();
+                        } else {
+                            let cursor = buffer
+                                .anchor_before_point(movement::right(&buffer, &display_map, end))
+                                .unwrap();
+                            selection.start = cursor.clone();
+                            selection.end = cursor;
+                        }
+                        selection.reversed = false;
+                        selection.goal_column = None;
+                    }
+                })
+                .unwrap();
+        }
+        self.autoscroll_to_cursor(false);
+    }
+
+    pub fn select_right(&mut self) {
+        {
+            let display_map = self.display_map();
+            self.buffer
+                .borrow_mut()
+                .mutate_selections(self.selection_set_id, |buffer, selections| {
+                    for selection in selections.iter_mut() {
+                        let head = buffer.point_for_anchor(selection.head()).unwrap();
+                        let cursor = buffer
+                            .anchor_before_point(movement::right(&buffer, &display_map, head))
+                            .unwrap();
+                        selection.set_head(&buffer, cursor);
+                        selection.goal_column = None;
+                    }
+                })
+                .unwrap();
+        }
+        self.autoscroll_to_cursor(false);
+    }
+
+    pub fn move_up(&mut self) {
+        self.move_up_by(1);
+    }
+
+    fn move_up_by(&mut self, row_count: u32) {
+        {
+            let display_map = self.display_map();
+            self.buffer
+                .borrow_mut()
+                .mutate_selections(self.selection_set_id, |buffer, selections| {
+                    for selection in selections.iter_mut() {
+                        let start = buffer.point_for_anchor(&selection.start).unwrap();
+                        let end = buffer.point_for_anchor(&selection.end).unwrap();
+                        if start != end {
+                            selection.goal_column = None;
+                        }
+
+                        let (start, goal_column) = movement::up(
+                            &buffer,
+                            &display_map,
+                            start,
+                            selection.goal_column,
+                            row_count,
+                        );
+                        let cursor = buffer.anchor_before_point(start).unwrap();
+                        selection.start = cursor.clone();
+                        selection.end = cursor;
+                        selection.goal_column = goal_column;
+                        selection.reversed = false;
+                    }
+                })
+                .unwrap();
+        }
         self.autoscroll_to_cursor(false);
     }
 
     pub fn select_up(&mut self) {
-        self.buffer
-            .borrow_mut()
-            .mutate_selections(self.selection_set_id, |buffer, selections| {
-                for selection in selections.iter_mut() {
-                    let head = buffer.point_for_anchor(selection.head()).unwrap();
-                    let (head, goal_column) = movement::up(&buffer, head, selection.goal_column);
-                    selection.set_head(&buffer, buffer.anchor_before_point(head).unwrap());
-                    selection.goal_column = goal_column;
-                }
-            })
-            .unwrap();
+        self.select_up_by(1);
+    }
+
+    fn select_up_by(&mut self, row_count: u32) {
+        {
+            let display_map = self.display_map();
+            self.buffer
+                .borrow_mut()
+                .mutate_selections(self.selection_set_id, |buffer, selections| {
+                    for selection in selections.iter_mut() {
+                        let head = buffer.point_for_anchor(selection.head()).unwrap();
+                        let (head, goal_column) = movement::up(
+                            &buffer,
+                            &display_map,
+                            head,
+                            selection.goal_column,
+                            row_count,
+                        );
+                        selection.set_head(&buffer, buffer.anchor_before_point(head).unwrap());
+                        selection.goal_column = goal_column;
+                    }
+                })
+                .unwrap();
+        }
         self.autoscroll_to_cursor(false);
     }
 
     pub fn move_down(&mut self) {
-        self.buffer
-            .borrow_mut()
-            .mutate_selections(self.selection_set_id, |buffer, selections| {
-                for selection in selections.iter_mut() {
-                    let start = buffer.point_for_anchor(&selection.start).unwrap();
-                    let end = buffer.point_for_anchor(&selection.end).unwrap();
-                    if start != end {
+        self.move_down_by(1);
+    }
+
+    fn move_down_by(&mut self, row_count: u32) {
+        {
+            let display_map = self.display_map();
+            self.buffer
+                .borrow_mut()
+                .mutate_selections(self.selection_set_id, |buffer, selections| {
+                    for selection in selections.iter_mut() {
+                        let start = buffer.point_for_anchor(&selection.start).unwrap();
+                        let end = buffer.point_for_anchor(&selection.end).unwrap();
+                        if start != end {
+                            selection.goal_column = None;
+                        }
+
+                        let (start, goal_column) = movement::down(
+                            &buffer,
+                            &display_map,
+                            end,
+                            selection.goal_column,
+                            row_count,
+                        );
+                        let cursor = buffer.anchor_before_point(start).unwrap();
+                        selection.start = cursor.clone();
+                        selection.end = cursor;
+                        selection.goal_column = goal_column;
+                        selection.reversed = false;
+                    }
+                })
+                .unwrap();
+        }
+        self.autoscroll_to_cursor(false);
+    }
+
+    pub fn select_down(&mut self) {
+        self.select_down_by(1);
+    }
+
+    fn select_down_by(&mut self, row_count: u32) {
+        {
+            let display_map = self.display_map();
+            self.buffer
+                .borrow_mut()
+                .mutate_selections(self.selection_set_id, |buffer, selections| {
+                    for selection in selections.iter_mut() {
+                        let head = buffer.point_for_anchor(selection.head()).unwrap();
+                        let (head, goal_column) = movement::down(
+                            &buffer,
+                            &display_map,
+                            head,
+                            selection.goal_column,
+                            row_count,
+                        );
+                        selection.set_head(&buffer, buffer.anchor_before_point(head).unwrap());
+                        selection.goal_column = goal_column;
+                    }
+                })
+                .unwrap();
+        }
+        self.autoscroll_to_cursor(false);
+    }
+
+    /// Moves each cursor up by the number of rows that fit in the view, scrolling by the same
+    /// amount.
+    pub fn page_up(&mut self) {
+        let row_count = self.scroll_up_by_page();
+        self.move_up_by(row_count);
+    }
+
+    pub fn page_down(&mut self) {
+        let row_count = self.scroll_down_by_page();
+        self.move_down_by(row_count);
+    }
+
+    pub fn select_page_up(&mut self) {
+        let row_count = self.scroll_up_by_page();
+        self.select_up_by(row_count);
+    }
+
+    pub fn select_page_down(&mut self) {
+        let row_count = self.scroll_down_by_page();
+        self.select_down_by(row_count);
+    }
+
+    pub fn move_to_previous_word_start(&mut self) {
+        self.move_cursors(movement::previous_word_start);
+    }
+
+    pub fn move_to_next_word_end(&mut self) {
+        self.move_cursors(movement::next_word_end);
+    }
+
+    pub fn move_to_beginning_of_line(&mut self) {
+        self.move_cursors(movement::beginning_of_line);
+    }
+
+    pub fn move_to_end_of_line(&mut self) {
+        self.move_cursors(movement::end_of_line);
+    }
+
+    pub fn move_to_top(&mut self) {
+        self.move_cursors(|_, _| movement::top());
+    }
+
+    pub fn move_to_bottom(&mut self) {
+        self.move_cursors(|buffer, _| movement::bottom(buffer));
+    }
+
+    pub fn go_to_matching_bracket(&mut self) {
+        self.move_cursors(|buffer, point| match movement::matching_bracket(buffer, point) {
+            Some((bracket, matching)) if matching > bracket => {
+                Point::new(matching.row, matching.column + 1)
+            }
+            Some((_, matching)) => matching,
+            None => point,
+        });
+    }
+
+    pub fn select_to_previous_word_start(&mut self) {
+        self.select_to(movement::previous_word_start);
+    }
+
+    pub fn select_to_next_word_end(&mut self) {
+        self.select_to(movement::next_word_end);
+    }
+
+    pub fn select_to_beginning_of_line(&mut self) {
+        self.select_to(movement::beginning_of_line);
+    }
+
+    pub fn select_to_end_of_line(&mut self) {
+        self.select_to(movement::end_of_line);
+    }
+
+    pub fn select_to_top(&mut self) {
+        self.select_to(|_, _| movement::top());
+    }
+
+    pub fn select_to_bottom(&mut self) {
+        self.select_to(|buffer, _| movement::bottom(buffer));
+    }
+
+    pub fn delete_to_previous_word_start(&mut self) {
+        self.delete_to(movement::previous_word_start);
+    }
+
+    pub fn delete_to_next_word_end(&mut self) {
+        self.delete_to(movement::next_word_end);
+    }
+
+    pub fn delete_to_beginning_of_line(&mut self) {
+        self.delete_to(movement::beginning_of_line);
+    }
+
+    pub fn delete_to_end_of_line(&mut self) {
+        self.delete_to(movement::end_of_line);
+    }
+
+    // Moves each cursor to the point computed from its head, collapsing its selection. Points in
+    // folded rows are moved to the end of the row preceding the fold.
+    fn move_cursors<F>(&mut self, f: F)
+    where
+        F: Fn(&Buffer, Point) -> Point,
+    {
+        {
+            let display_map = self.display_map();
+            self.buffer
+                .borrow_mut()
+                .mutate_selections(self.selection_set_id, |buffer, selections| {
+                    for selection in selections.iter_mut() {
+                        let head = buffer.point_for_anchor(selection.head()).unwrap();
+                        let cursor = clip_to_folds(buffer, &display_map, f(buffer, head));
+                        let cursor = buffer.anchor_before_point(cursor).unwrap();
+                        selection.start = cursor.clone();
+                        selection.end = cursor;
+                        selection.reversed = false;
                         selection.goal_column = None;
                     }
+                })
+                .unwrap();
+        }
+        self.autoscroll_to_cursor(false);
+    }
 
-                    let (start, goal_column) = movement::down(&buffer, end, selection.goal_column);
-                    let cursor = buffer.anchor_before_point(start).unwrap();
-                    selection.start = cursor.clone();
-                    selection.end = cursor;
-                    selection.goal_column = goal_column;
-                    selection.reversed = false;
-                }
-            })
-            .unwrap();
+    // Moves the head of each selection to the point computed from it, keeping its tail in place.
+    fn select_to<F>(&mut self, f: F)
+    where
+        F: Fn(&Buffer, Point) -> Point,
+    {
+        {
+            let display_map = self.display_map();
+            self.buffer
+                .borrow_mut()
+                .mutate_selections(self.selection_set_id, |buffer, selections| {
+                    for selection in selections.iter_mut() {
+                        let head = buffer.point_for_anchor(selection.head()).unwrap();
+                        let head = clip_to_folds(buffer, &display_map, f(buffer, head));
+                        selection.set_head(buffer, buffer.anchor_before_point(head).unwrap());
+                        selection.goal_column = None;
+                    }
+                })
+                .unwrap();
+        }
         self.autoscroll_to_cursor(false);
     }
 
-    pub fn select_down(&mut self) {
+    // Deletes the text between each cursor and the point computed from it. Non-empty selections
+    // are deleted as they are.
+    fn delete_to<F>(&mut self, f: F)
+    where
+        F: Fn(&Buffer, Point) -> Point,
+    {
         self.buffer
             .borrow_mut()
             .mutate_selections(self.selection_set_id, |buffer, selections| {
                 for selection in selections.iter_mut() {
-                    let head = buffer.point_for_anchor(selection.head()).unwrap();
-                    let (head, goal_column) = movement::down(&buffer, head, selection.goal_column);
-                    selection.set_head(&buffer, buffer.anchor_before_point(head).unwrap());
-                    selection.goal_column = goal_column;
+                    if selection.is_empty(buffer) {
+                        let head = buffer.point_for_anchor(selection.head()).unwrap();
+                        let head = buffer.anchor_before_point(f(buffer, head)).unwrap();
+                        selection.set_head(buffer, head);
+                    }
                 }
             })
             .unwrap();
+        self.edit("");
+    }
+
+    /// Folds the rows spanned by each selection. Selections within a single row fold the
+    /// innermost syntax node containing them instead, or the indented block of rows containing
+    /// them if the buffer isn't highlighted.
+    pub fn fold_selected_rows(&mut self) {
+        {
+            let buffer = self.buffer.borrow();
+            let mut display_map = self.display_map.borrow_mut();
+            let syntax_folds = self.syntax_folds(&buffer);
+            for selection in self.selections().iter() {
+                let start = buffer.point_for_anchor(&selection.start).unwrap();
+                let end = buffer.point_for_anchor(&selection.end).unwrap();
+                let rows = match syntax_folds {
+                    Some(ref folds) => innermost_fold_rows(folds, start.row),
+                    None => indentation_fold_rows(&buffer, start.row),
+                };
+                if start.row < end.row {
+                    display_map.fold(start..end, &buffer);
+                } else if let Some(rows) = rows {
+                    display_map.fold(row_range(&buffer, rows), &buffer);
+                }
+            }
+        }
+        self.clip_selections_to_folds();
+    }
+
+    /// Removes the folds spanning any of the rows of each selection.
+    pub fn unfold_selected_rows(&mut self) {
+        {
+            let buffer = self.buffer.borrow();
+            let mut display_map = self.display_map.borrow_mut();
+            for selection in self.selections().iter() {
+                let start = buffer.point_for_anchor(&selection.start).unwrap();
+                let end = buffer.point_for_anchor(&selection.end).unwrap();
+                display_map.unfold(start.row..end.row + 1, &buffer);
+            }
+        }
         self.autoscroll_to_cursor(false);
+        self.updated();
+    }
+
+    /// Folds every syntax node spanning several rows, or every block of rows that is indented
+    /// beneath the row preceding it if the buffer isn't highlighted.
+    pub fn fold_all(&mut self) {
+        {
+            let buffer = self.buffer.borrow();
+            let mut display_map = self.display_map.borrow_mut();
+            if let Some(folds) = self.syntax_folds(&buffer) {
+                for rows in folds {
+                    display_map.fold(row_range(&buffer, rows), &buffer);
+                }
+            } else {
+                for row in 0..buffer.max_point().row {
+                    if let Some(end_row) = indented_block_end_row(&buffer, row) {
+                        display_map.fold(row_range(&buffer, row..end_row), &buffer);
+                    }
+                }
+            }
+        }
+        self.clip_selections_to_folds();
+    }
+
+    // Returns the rows that folding each syntax node would span, leaving the row containing the
+    // end of the node visible. Folding by syntax requires highlights that are up to date with the
+    // buffer, since the rows of stale highlights may no longer line up with its text.
+    fn syntax_folds(&self, buffer: &Buffer) -> Option<Vec<Range<u32>>> {
+        let highlights = self.syntax.as_ref()?.highlights.as_ref()?;
+        if highlights.version() == Some(&buffer.version) {
+            Some(
+                highlights
+                    .folds()
+                    .into_iter()
+                    .filter(|rows| rows.start + 1 < rows.end)
+                    .map(|rows| rows.start..rows.end - 1)
+                    .collect(),
+            )
+        } else {
+            None
+        }
+    }
+
+    // Moves selection endpoints that were hidden by folds to the end of the row preceding the
+    // fold.
+    fn clip_selections_to_folds(&mut self) {
+        {
+            let display_map = self.display_map();
+            self.buffer
+                .borrow_mut()
+                .mutate_selections(self.selection_set_id, |buffer, selections| {
+                    for selection in selections.iter_mut() {
+                        for anchor in &mut [&mut selection.start, &mut selection.end] {
+                            let point = buffer.point_for_anchor(anchor).unwrap();
+                            let clipped_point = clip_to_folds(buffer, &display_map, point);
+                            if clipped_point != point {
+                                **anchor = buffer.anchor_before_point(clipped_point).unwrap();
+                            }
+                        }
+                    }
+                })
+                .unwrap();
+        }
+        self.autoscroll_to_cursor(false);
+        self.updated();
     }
 
     pub fn selections(&self) -> Ref<[Selection]> {
@@ -561,18 +2249,27 @@ impl BufferView {
         self.buffer.borrow().id()
     }
 
-    fn render_selections(&self, range: Range<Point>) -> Vec<SelectionProps> {
+    fn render_selections(
+        &self,
+        range: Range<Point>,
+        display_map: &DisplayMap,
+    ) -> Vec<SelectionProps> {
         let buffer = self.buffer.borrow();
+        let display_point = |anchor| {
+            display_map.to_display_point(buffer.point_for_anchor(anchor).unwrap(), &buffer)
+        };
+        let presence = self.presence.borrow();
         let mut rendered_selections = Vec::new();
 
         for (user_id, selections) in buffer.remote_selections() {
             for selection in self.query_selections(selections, &range) {
                 rendered_selections.push(SelectionProps {
                     user_id,
-                    start: buffer.point_for_anchor(&selection.start).unwrap(),
-                    end: buffer.point_for_anchor(&selection.end).unwrap(),
+                    start: display_point(&selection.start),
+                    end: display_point(&selection.end),
                     reversed: selection.reversed,
                     remote: true,
+                    user: presence.user(user_id).cloned(),
                 });
             }
         }
@@ -582,14 +2279,247 @@ impl BufferView {
         {
             rendered_selections.push(SelectionProps {
                 user_id: self.user_id,
-                start: buffer.point_for_anchor(&selection.start).unwrap(),
-                end: buffer.point_for_anchor(&selection.end).unwrap(),
+                start: display_point(&selection.start),
+                end: display_point(&selection.end),
                 reversed: selection.reversed,
                 remote: false,
+                user: None,
             });
         }
 
-        rendered_selections
+        rendered_selections
+    }
+
+    fn render_search_matches(
+        &self,
+        range: Range<Point>,
+        display_map: &DisplayMap,
+    ) -> Vec<Range<DisplayPoint>> {
+        let buffer = self.buffer.borrow();
+        let start_index = match self.search_matches.binary_search_by(|probe| {
+            let end = buffer.point_for_anchor(&probe.end).unwrap();
+            end.cmp(&range.start).then(Ordering::Less)
+        }) {
+            Ok(index) | Err(index) => index,
+        };
+
+        self.search_matches[start_index..]
+            .iter()
+            .map(|match_range| {
+                let start = buffer.point_for_anchor(&match_range.start).unwrap();
+                let end = buffer.point_for_anchor(&match_range.end).unwrap();
+                start..end
+            })
+            .take_while(|match_range| match_range.start < range.end)
+            .map(|match_range| {
+                display_map.to_display_point(match_range.start, &buffer)
+                    ..display_map.to_display_point(match_range.end, &buffer)
+            })
+            .collect()
+    }
+
+    // Returns the brackets adjacent to each visible cursor along with the brackets matching them.
+    fn render_bracket_matches(
+        &self,
+        range: Range<Point>,
+        display_map: &DisplayMap,
+    ) -> Vec<Range<DisplayPoint>> {
+        let buffer = self.buffer.borrow();
+        let mut cache = self.bracket_matches.borrow_mut();
+        let versions = (
+            buffer.version.clone(),
+            buffer.selections_version(self.selection_set_id).unwrap(),
+        );
+        if cache.versions.as_ref() != Some(&versions) {
+            cache.versions = Some(versions);
+            cache.by_cursor.clear();
+        }
+
+        let mut bracket_matches = Vec::new();
+        for selection in
+            self.query_selections(&buffer.selections(self.selection_set_id).unwrap(), &range)
+        {
+            if !selection.is_empty(&buffer) {
+                continue;
+            }
+
+            let cursor = buffer.point_for_anchor(&selection.start).unwrap();
+            let brackets = *cache
+                .by_cursor
+                .entry(cursor)
+                .or_insert_with(|| movement::matching_bracket(&buffer, cursor));
+            if let Some((bracket, matching)) = brackets {
+                for point in &[bracket, matching] {
+                    let end = Point::new(point.row, point.column + 1);
+                    bracket_matches.push(
+                        display_map.to_display_point(*point, &buffer)
+                            ..display_map.to_display_point(end, &buffer),
+                    );
+                }
+            }
+        }
+        bracket_matches.sort_by(|a, b| a.start.cmp(&b.start));
+        bracket_matches.dedup();
+        bracket_matches
+    }
+
+    fn render_markers(&self, range: Range<Point>, display_map: &DisplayMap) -> Vec<MarkerProps> {
+        let buffer = self.buffer.borrow();
+        let mut rendered_markers = buffer
+            .all_markers_in_range(range)
+            .into_iter()
+            .map(|(replica_id, layer_id, marker)| MarkerProps {
+                layer_id,
+                start: display_map
+                    .to_display_point(buffer.point_for_anchor(&marker.start).unwrap(), &buffer),
+                end: display_map
+                    .to_display_point(buffer.point_for_anchor(&marker.end).unwrap(), &buffer),
+                properties: marker.properties.clone(),
+                remote: replica_id != buffer.replica_id,
+            })
+            .collect::<Vec<_>>();
+        rendered_markers.sort_by(|a, b| a.start.cmp(&b.start).then(a.end.cmp(&b.end)));
+        rendered_markers
+    }
+
+    // The highlights may lag behind the buffer while the background tokenizer catches up, so we
+    // clip tokens that extend beyond the current contents of their line. Tokens are also clipped
+    // to the segment of their buffer row that is displayed on each line.
+    fn render_tokens(&self, segments: &[Segment], lines: &[String]) -> Vec<Vec<Token>> {
+        let highlights = self.syntax
+            .as_ref()
+            .and_then(|syntax| syntax.highlights.as_ref());
+        segments
+            .iter()
+            .zip(lines)
+            .map(|(segment, line)| {
+                let segment_start = segment.start_column;
+                let segment_end = segment_start + line.encode_utf16().count() as u32;
+                highlights
+                    .map(|highlights| highlights.tokens(segment.buffer_row))
+                    .unwrap_or(&[])
+                    .iter()
+                    .filter(|token| token.start < segment_end && token.end > segment_start)
+                    .map(|token| Token {
+                        start: cmp::max(token.start, segment_start) - segment_start,
+                        end: cmp::min(token.end, segment_end) - segment_start,
+                        scope: token.scope,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn render_gutter(
+        &self,
+        start_row: u32,
+        segments: &[Segment],
+        range: Range<Point>,
+        display_map: &DisplayMap,
+    ) -> GutterProps {
+        let buffer = self.buffer.borrow();
+        let visible_rows = start_row..start_row + segments.len() as u32;
+
+        // Rows that continue a soft-wrapped line aren't numbered.
+        let line_numbers = segments
+            .iter()
+            .map(|segment| {
+                if segment.start_column == 0 {
+                    Some(segment.buffer_row + 1)
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        let mut cursor_rows = Vec::new();
+        for selection in
+            self.query_selections(&buffer.selections(self.selection_set_id).unwrap(), &range)
+        {
+            let head = if selection.reversed {
+                &selection.start
+            } else {
+                &selection.end
+            };
+            let row = display_map
+                .to_display_point(buffer.point_for_anchor(head).unwrap(), &buffer)
+                .row;
+            if visible_rows.start <= row && row < visible_rows.end
+                && cursor_rows.last() != Some(&row)
+            {
+                cursor_rows.push(row);
+            }
+        }
+
+        let max_row = buffer.max_point().row;
+        let mut decorations = Vec::new();
+        for layer_id in &self.gutter_layers {
+            // Layers may be removed from the buffer without being removed from the gutter first.
+            let markers = match buffer.markers_in_range(*layer_id, range.clone()) {
+                Ok(markers) => markers,
+                Err(_) => continue,
+            };
+            for marker in markers {
+                let start = buffer.point_for_anchor(&marker.start).unwrap();
+                let end = buffer.point_for_anchor(&marker.end).unwrap();
+                let mut end_row = end.row;
+                if end.column == 0 && end.row > start.row {
+                    end_row -= 1;
+                }
+
+                let start_row = cmp::max(start.row, range.start.row);
+                let end_row = cmp::min(end_row, cmp::min(range.end.row, max_row));
+                for row in start_row..end_row + 1 {
+                    if display_map.folded_rows(row).is_some() {
+                        continue;
+                    }
+
+                    let display_row = display_map
+                        .to_display_point(Point::new(row, 0), &buffer)
+                        .row;
+                    if visible_rows.start <= display_row && display_row < visible_rows.end {
+                        decorations.push(GutterDecorationProps {
+                            row: display_row,
+                            layer_id: *layer_id,
+                            properties: marker.properties.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        decorations.sort_by_key(|decoration| decoration.row);
+
+        GutterProps {
+            line_numbers,
+            cursor_rows,
+            decorations,
+        }
+    }
+
+    fn poll_highlights(&mut self) -> bool {
+        let mut updated = false;
+        if let Some(ref mut syntax) = self.syntax {
+            while let Ok(Async::Ready(Some(highlights))) = syntax.highlight_updates.poll() {
+                if highlights.is_some() {
+                    syntax.highlights = highlights;
+                    updated = true;
+                }
+            }
+        }
+        updated
+    }
+
+    fn request_highlights(&mut self) {
+        if let Some(ref mut syntax) = self.syntax {
+            let buffer = self.buffer.borrow();
+            if buffer.version != syntax.last_sent_version {
+                syntax.last_sent_version = buffer.version.clone();
+                let _ = syntax.requests.unbounded_send(HighlightRequest {
+                    snapshot: buffer.snapshot(),
+                    edits: syntax.edits.take(),
+                });
+            }
+        }
     }
 
     fn query_selections<'a>(
@@ -672,8 +2602,10 @@ impl BufferView {
         // flush_pending_autoscroll_to_selection unwraps.
         let (start, end) = {
             let buffer = self.buffer.borrow();
-            let start = buffer.point_for_anchor(&range.start)?;
-            let end = buffer.point_for_anchor(&range.end)?;
+            let display_map = self.display_map();
+            let start =
+                display_map.to_display_point(buffer.point_for_anchor(&range.start)?, &buffer);
+            let end = display_map.to_display_point(buffer.point_for_anchor(&range.end)?, &buffer);
             (start, end)
         };
         if let Some(height) = self.height {
@@ -702,6 +2634,34 @@ impl BufferView {
         Ok(())
     }
 
+    fn update_wrap_width(&mut self) {
+        let wrap_width = match (self.width, self.char_width) {
+            (Some(width), Some(char_width)) => Some((width / char_width).floor() as u32),
+            _ => None,
+        };
+        self.display_map.borrow_mut().set_wrap_width(wrap_width);
+    }
+
+    // The display map is brought up to date with the buffer before it is returned, unless it is
+    // already borrowed, in which case the outstanding borrow already brought it up to date.
+    fn display_map(&self) -> Ref<DisplayMap> {
+        if let Ok(mut display_map) = self.display_map.try_borrow_mut() {
+            display_map.sync(&self.buffer.borrow());
+        }
+        self.display_map.borrow()
+    }
+
+    // Lets collaborators who are following this view's user see what it is showing.
+    fn report_viewport(&self) {
+        let viewport = Viewport {
+            buffer_id: self.buffer_id(),
+            scroll_top: self.scroll_top(),
+        };
+        self.presence
+            .borrow_mut()
+            .set_viewport(self.user_id, viewport);
+    }
+
     fn updated(&mut self) {
         self.updates_tx.set(());
     }
@@ -714,7 +2674,27 @@ impl View for BufferView {
 
     fn will_mount(&mut self, window: &mut Window, self_handle: WeakViewHandle<Self>) {
         self.height = Some(window.height());
+        window.spawn(self.display_map.borrow_mut().wrapper());
+        if let Some(language) = self.language {
+            let (highlighter, requests, highlight_updates) = Highlighter::new(language);
+            window.spawn(highlighter);
+            let edits = self.buffer.borrow_mut().subscribe_edits();
+            let snapshot = self.buffer.borrow().snapshot();
+            let last_sent_version = snapshot.version().clone();
+            let _ = requests.unbounded_send(HighlightRequest {
+                snapshot,
+                edits: Vec::new(),
+            });
+            self.syntax = Some(SyntaxState {
+                requests,
+                edits,
+                last_sent_version,
+                highlight_updates,
+                highlights: None,
+            });
+        }
         self.flush_pending_autoscroll_to_selection();
+        self.report_viewport();
         if let Some(ref delegate) = self.delegate {
             delegate.map(|delegate| delegate.set_active_buffer_view(self_handle));
         }
@@ -722,36 +2702,66 @@ impl View for BufferView {
 
     fn render(&self) -> serde_json::Value {
         let buffer = self.buffer.borrow();
-        let start = Point::new((self.scroll_top() / self.line_height).floor() as u32, 0);
-        let end = Point::new((self.scroll_bottom() / self.line_height).ceil() as u32, 0);
+        let display_map = self.display_map();
+        let start_row = (self.scroll_top() / self.line_height).floor() as u32;
+        let end_row = (self.scroll_bottom() / self.line_height).ceil() as u32;
+        let start = display_map.to_buffer_point(DisplayPoint::new(start_row, 0), &buffer);
+        let end = if end_row > display_map.max_row() {
+            Point::new(buffer.max_point().row + 1, 0)
+        } else {
+            display_map.to_buffer_point(DisplayPoint::new(end_row, 0), &buffer)
+        };
 
+        let segments = display_map.segments(start_row..end_row);
         let mut lines = Vec::new();
-        let mut cur_line = Vec::new();
-        let mut cur_row = start.row;
-        for c in buffer.iter_starting_at_row(start.row) {
-            if c == u16::from(b'\n') {
-                lines.push(String::from_utf16_lossy(&cur_line));
-                cur_line = Vec::new();
-                cur_row += 1;
-                if cur_row >= end.row {
-                    break;
+        if let Some(first_segment) = segments.first() {
+            let mut chars = buffer.iter_starting_at_row(first_segment.buffer_row);
+            let mut cur_line = Vec::new();
+            let mut cur_row = None;
+            for segment in &segments {
+                if cur_row != Some(segment.buffer_row) {
+                    // Rows hidden by folds are skipped.
+                    if cur_row.map_or(false, |row| row + 1 != segment.buffer_row) {
+                        chars = buffer.iter_starting_at_row(segment.buffer_row);
+                    }
+                    cur_line.clear();
+                    for c in &mut chars {
+                        if c == u16::from(b'\n') {
+                            break;
+                        }
+                        cur_line.push(c);
+                    }
+                    cur_row = Some(segment.buffer_row);
                 }
-            } else {
-                cur_line.push(c);
+
+                let end_column = segment
+                    .end_column
+                    .map_or(cur_line.len(), |column| column as usize);
+                let end_column = cmp::min(end_column, cur_line.len());
+                let start_column = cmp::min(segment.start_column as usize, end_column);
+                let mut line = String::from_utf16_lossy(&cur_line[start_column..end_column]);
+                if segment.folded {
+                    line.push_str(FOLD_PLACEHOLDER);
+                }
+                lines.push(line);
             }
         }
-        if cur_row < end.row {
-            lines.push(String::from_utf16_lossy(&cur_line));
-        }
 
         json!({
-            "first_visible_row": start.row,
+            "first_visible_row": start_row,
             "lines": lines,
             "scroll_top": self.scroll_top,
             "height": self.height,
             "width": self.width,
             "line_height": self.line_height,
-            "selections": self.render_selections(start..end),
+            "selections": self.render_selections(start..end, &display_map),
+            "search_matches": self.render_search_matches(start..end, &display_map),
+            "bracket_matches": self.render_bracket_matches(start..end, &display_map),
+            "markers": self.render_markers(start..end, &display_map),
+            "gutter": self.render_gutter(start_row, &segments, start..end, &display_map),
+            "tokens": self.render_tokens(&segments, &lines),
+            "modified": buffer.is_modified(),
+            "conflict": buffer.has_conflict(),
         })
     }
 
@@ -768,7 +2778,42 @@ impl View for BufferView {
                 self.set_width(width as f64);
                 self.set_height(height as f64);
             }
+            Ok(BufferViewAction::SetCharWidth { char_width }) => {
+                self.set_char_width(char_width);
+            }
+            Ok(BufferViewAction::Click { x, y }) => {
+                let point = self.point_for_position(x, y);
+                self.select_point(point);
+                self.start_drag(point, DragGranularity::Character);
+            }
+            Ok(BufferViewAction::ShiftClick { x, y }) => {
+                let point = self.point_for_position(x, y);
+                self.select_to_point(point);
+                self.start_drag(point, DragGranularity::Character);
+            }
+            Ok(BufferViewAction::CmdClick { x, y }) => {
+                let point = self.point_for_position(x, y);
+                self.add_selection(point, point);
+                self.start_drag(point, DragGranularity::Character);
+            }
+            Ok(BufferViewAction::DoubleClick { x, y }) => {
+                let point = self.point_for_position(x, y);
+                self.select_word_at_point(point);
+                self.start_drag(point, DragGranularity::Word);
+            }
+            Ok(BufferViewAction::TripleClick { x, y }) => {
+                let point = self.point_for_position(x, y);
+                self.select_line_at_point(point);
+                self.start_drag(point, DragGranularity::Line);
+            }
+            Ok(BufferViewAction::Drag { x, y }) => {
+                let point = self.point_for_position(x, y);
+                self.drag_to_point(point);
+            }
             Ok(BufferViewAction::Edit { text }) => self.edit(text.as_str()),
+            Ok(BufferViewAction::Tab) => self.tab(),
+            Ok(BufferViewAction::Indent) => self.indent_selected_rows(),
+            Ok(BufferViewAction::Outdent) => self.outdent_selected_rows(),
             Ok(BufferViewAction::Backspace) => self.backspace(),
             Ok(BufferViewAction::Delete) => self.delete(),
             Ok(BufferViewAction::MoveUp) => self.move_up(),
@@ -779,8 +2824,50 @@ impl View for BufferView {
             Ok(BufferViewAction::SelectDown) => self.select_down(),
             Ok(BufferViewAction::SelectLeft) => self.select_left(),
             Ok(BufferViewAction::SelectRight) => self.select_right(),
+            Ok(BufferViewAction::PageUp) => self.page_up(),
+            Ok(BufferViewAction::PageDown) => self.page_down(),
+            Ok(BufferViewAction::SelectPageUp) => self.select_page_up(),
+            Ok(BufferViewAction::SelectPageDown) => self.select_page_down(),
+            Ok(BufferViewAction::MoveToPreviousWordStart) => self.move_to_previous_word_start(),
+            Ok(BufferViewAction::MoveToNextWordEnd) => self.move_to_next_word_end(),
+            Ok(BufferViewAction::MoveToBeginningOfLine) => self.move_to_beginning_of_line(),
+            Ok(BufferViewAction::MoveToEndOfLine) => self.move_to_end_of_line(),
+            Ok(BufferViewAction::MoveToTop) => self.move_to_top(),
+            Ok(BufferViewAction::MoveToBottom) => self.move_to_bottom(),
+            Ok(BufferViewAction::SelectToPreviousWordStart) => {
+                self.select_to_previous_word_start()
+            }
+            Ok(BufferViewAction::SelectToNextWordEnd) => self.select_to_next_word_end(),
+            Ok(BufferViewAction::SelectToBeginningOfLine) => self.select_to_beginning_of_line(),
+            Ok(BufferViewAction::SelectToEndOfLine) => self.select_to_end_of_line(),
+            Ok(BufferViewAction::SelectToTop) => self.select_to_top(),
+            Ok(BufferViewAction::SelectToBottom) => self.select_to_bottom(),
+            Ok(BufferViewAction::DeleteToPreviousWordStart) => {
+                self.delete_to_previous_word_start()
+            }
+            Ok(BufferViewAction::DeleteToNextWordEnd) => self.delete_to_next_word_end(),
+            Ok(BufferViewAction::DeleteToBeginningOfLine) => self.delete_to_beginning_of_line(),
+            Ok(BufferViewAction::DeleteToEndOfLine) => self.delete_to_end_of_line(),
             Ok(BufferViewAction::AddSelectionAbove) => self.add_selection_above(),
             Ok(BufferViewAction::AddSelectionBelow) => self.add_selection_below(),
+            Ok(BufferViewAction::DuplicateLines) => self.duplicate_lines(),
+            Ok(BufferViewAction::DeleteLines) => self.delete_lines(),
+            Ok(BufferViewAction::MoveLinesUp) => self.move_lines_up(),
+            Ok(BufferViewAction::MoveLinesDown) => self.move_lines_down(),
+            Ok(BufferViewAction::JoinLines) => self.join_lines(),
+            Ok(BufferViewAction::SelectNextOccurrence) => self.select_next_occurrence(),
+            Ok(BufferViewAction::SelectAllOccurrences) => self.select_all_occurrences(),
+            Ok(BufferViewAction::SkipOccurrence) => self.skip_occurrence(),
+            Ok(BufferViewAction::GoToMatchingBracket) => self.go_to_matching_bracket(),
+            Ok(BufferViewAction::Fold) => self.fold_selected_rows(),
+            Ok(BufferViewAction::Unfold) => self.unfold_selected_rows(),
+            Ok(BufferViewAction::FoldAll) => self.fold_all(),
+            Ok(BufferViewAction::Copy) => self.copy(),
+            Ok(BufferViewAction::Cut) => self.cut(),
+            Ok(BufferViewAction::Paste) => self.paste(),
+            Ok(BufferViewAction::Undo) => self.undo(),
+            Ok(BufferViewAction::Redo) => self.redo(),
+            Ok(BufferViewAction::Save) => self.save(),
             action @ _ => eprintln!("Unrecognized action {:?}", action),
         }
     }
@@ -791,7 +2878,20 @@ impl Stream for BufferView {
     type Error = ();
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        self.updates_rx.poll()
+        let highlights_updated = self.poll_highlights();
+        let wraps_updated = self.display_map
+            .borrow_mut()
+            .poll_wraps(&self.buffer.borrow());
+        match self.updates_rx.poll()? {
+            Async::Ready(Some(())) => {
+                self.refresh_search_matches();
+                self.request_highlights();
+                Ok(Async::Ready(Some(())))
+            }
+            Async::Ready(None) => Ok(Async::Ready(None)),
+            Async::NotReady if highlights_updated || wraps_updated => Ok(Async::Ready(Some(()))),
+            Async::NotReady => Ok(Async::NotReady),
+        }
     }
 }
 
@@ -805,9 +2905,153 @@ impl Drop for BufferView {
     }
 }
 
+// Returns whether a closing bracket or quote can be inserted automatically at the given point,
+// which is the case when it isn't followed by other text. Quotes also mustn't follow a word
+// character, so that apostrophes aren't closed.
+fn can_auto_close(buffer: &Buffer, point: Point, quote: bool) -> bool {
+    let next = buffer.iter_starting_at_point(point).next();
+    let next_is_boundary = next.map_or(true, |c| {
+        c == u16::from(b' ')
+            || c == u16::from(b'\t')
+            || c == u16::from(b'\n')
+            || BRACKET_PAIRS
+                .iter()
+                .any(|&(open, close)| open != close && close.encode_utf16().next() == Some(c))
+    });
+    if !next_is_boundary {
+        return false;
+    }
+
+    if quote && point.column > 0 {
+        let previous = buffer
+            .iter_starting_at_point(Point::new(point.row, point.column - 1))
+            .next()
+            .unwrap();
+        let word_char = previous > 127 || previous == u16::from(b'_')
+            || (previous as u8 as char).is_ascii_alphanumeric();
+        !word_char
+    } else {
+        true
+    }
+}
+
+// Returns the spaces and tabs at the start of the given row.
+fn leading_whitespace(buffer: &Buffer, row: u32) -> String {
+    let whitespace = buffer
+        .iter_starting_at_row(row)
+        .take_while(|c| *c == u16::from(b' ') || *c == u16::from(b'\t'))
+        .collect::<Vec<_>>();
+    String::from_utf16_lossy(&whitespace)
+}
+
+fn rows_text(buffer: &Buffer, rows: &Range<u32>) -> String {
+    let start = Point::new(rows.start, 0);
+    let end = Point::new(rows.end - 1, buffer.len_for_row(rows.end - 1).unwrap());
+    let len = buffer.offset_for_point(end).unwrap() - buffer.offset_for_point(start).unwrap();
+    String::from_utf16_lossy(&buffer
+        .iter_starting_at_point(start)
+        .take(len)
+        .collect::<Vec<_>>())
+}
+
+fn overlaps(a: &Range<Point>, b: &Range<Point>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+fn clip_to_folds(buffer: &Buffer, display_map: &DisplayMap, point: Point) -> Point {
+    if display_map.folded_rows(point.row).is_some() {
+        display_map.to_buffer_point(display_map.to_display_point(point, buffer), buffer)
+    } else {
+        point
+    }
+}
+
+// Returns the range of the given row along with its trailing newline, if it has one.
+fn line_range(buffer: &Buffer, row: u32) -> Range<Point> {
+    let end = if row < buffer.max_point().row {
+        Point::new(row + 1, 0)
+    } else {
+        buffer.max_point()
+    };
+    Point::new(row, 0)..end
+}
+
+// Returns the range from the end of the first row to the end of the last row.
+fn row_range(buffer: &Buffer, rows: Range<u32>) -> Range<Point> {
+    let start = Point::new(rows.start, buffer.len_for_row(rows.start).unwrap());
+    let end = Point::new(rows.end, buffer.len_for_row(rows.end).unwrap());
+    start..end
+}
+
+// Returns the innermost of the given folds that spans the given row. Folds are ordered by their
+// first row, with enclosing folds preceding the folds they contain.
+fn innermost_fold_rows(folds: &[Range<u32>], row: u32) -> Option<Range<u32>> {
+    folds
+        .iter()
+        .filter(|rows| rows.start <= row && row <= rows.end)
+        .last()
+        .cloned()
+}
+
+// Returns the rows spanned by the innermost indented block containing the given row, including
+// the row preceding the block.
+fn indentation_fold_rows(buffer: &Buffer, row: u32) -> Option<Range<u32>> {
+    if let Some(end_row) = indented_block_end_row(buffer, row) {
+        return Some(row..end_row);
+    }
+
+    let mut indentation = None;
+    for start_row in (0..row + 1).rev() {
+        if let Some(start_indentation) = indentation_for_row(buffer, start_row) {
+            match indentation {
+                None => indentation = Some(start_indentation),
+                Some(indentation) if start_indentation < indentation => {
+                    return indented_block_end_row(buffer, start_row)
+                        .map(|end_row| start_row..end_row);
+                }
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+// Returns the last row of the block of rows indented beneath the given row, if the row is
+// followed by one. Blank rows are included in the block unless they trail it.
+fn indented_block_end_row(buffer: &Buffer, row: u32) -> Option<u32> {
+    let indentation = indentation_for_row(buffer, row)?;
+    let mut end_row = None;
+    for next_row in row + 1..buffer.max_point().row + 1 {
+        match indentation_for_row(buffer, next_row) {
+            Some(next_indentation) if next_indentation > indentation => end_row = Some(next_row),
+            Some(_) => break,
+            None => {}
+        }
+    }
+    end_row
+}
+
+// Returns the number of whitespace characters at the start of the given row, or `None` if the
+// row is blank.
+fn indentation_for_row(buffer: &Buffer, row: u32) -> Option<u32> {
+    let mut indentation = 0;
+    for c in buffer.iter_starting_at_row(row) {
+        if c == u16::from(b' ') || c == u16::from(b'\t') {
+            indentation += 1;
+        } else if c == u16::from(b'\n') {
+            return None;
+        } else {
+            return Some(indentation);
+        }
+    }
+    None
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use buffer::IndentSettings;
+    use std::time::Duration;
     use IntoShared;
 
     #[test]
@@ -982,6 +3226,512 @@ mod tests {
         assert_eq!(render_selections(&editor), vec![empty_selection(0, 1)]);
     }
 
+    #[test]
+    fn test_word_movement() {
+        let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+        editor
+            .buffer
+            .borrow_mut()
+            .edit(0..0, "abc_d.ef(g)  \n\n  hi");
+
+        editor.move_to_next_word_end();
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 5)]);
+        editor.move_to_next_word_end();
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 6)]);
+        editor.move_to_next_word_end();
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 8)]);
+
+        // Skips whitespace across lines
+        editor.move_to_next_word_end();
+        editor.move_to_next_word_end();
+        editor.move_to_next_word_end();
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 11)]);
+        editor.move_to_next_word_end();
+        assert_eq!(render_selections(&editor), vec![empty_selection(2, 4)]);
+
+        // Stops at end
+        editor.move_to_next_word_end();
+        assert_eq!(render_selections(&editor), vec![empty_selection(2, 4)]);
+
+        editor.move_to_previous_word_start();
+        assert_eq!(render_selections(&editor), vec![empty_selection(2, 2)]);
+        editor.move_to_previous_word_start();
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 10)]);
+        editor.move_to_previous_word_start();
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 9)]);
+        editor.move_to_previous_word_start();
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 8)]);
+        editor.move_to_previous_word_start();
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 6)]);
+        editor.move_to_previous_word_start();
+        editor.move_to_previous_word_start();
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 0)]);
+
+        // Stops at start
+        editor.move_to_previous_word_start();
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 0)]);
+
+        // Selects from the head of the selection
+        editor.select_to_next_word_end();
+        editor.select_to_next_word_end();
+        assert_eq!(render_selections(&editor), vec![selection((0, 0), (0, 6))]);
+        editor.select_to_previous_word_start();
+        assert_eq!(render_selections(&editor), vec![selection((0, 0), (0, 5))]);
+        editor.move_to_end_of_line();
+        editor.select_to_previous_word_start();
+        editor.select_to_previous_word_start();
+        assert_eq!(render_selections(&editor), vec![rev_selection((0, 9), (0, 13))]);
+
+        // Deletes from empty selections only
+        editor.delete_to_previous_word_start();
+        assert_eq!(editor.buffer.borrow().to_string(), "abc_d.ef(\n\n  hi");
+        editor.delete_to_previous_word_start();
+        assert_eq!(editor.buffer.borrow().to_string(), "abc_d.ef\n\n  hi");
+        editor.move_to_top();
+        editor.delete_to_next_word_end();
+        assert_eq!(editor.buffer.borrow().to_string(), ".ef\n\n  hi");
+    }
+
+    #[test]
+    fn test_line_movement() {
+        let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+        editor.buffer.borrow_mut().edit(0..0, "  abc\ndef\n  ");
+
+        editor.move_to_end_of_line();
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 5)]);
+
+        // Moves to the first non-whitespace character, then to the start of the line
+        editor.move_to_beginning_of_line();
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 2)]);
+        editor.move_to_beginning_of_line();
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 0)]);
+        editor.move_to_beginning_of_line();
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 2)]);
+
+        editor.move_to_bottom();
+        assert_eq!(render_selections(&editor), vec![empty_selection(2, 2)]);
+        editor.move_to_beginning_of_line();
+        assert_eq!(render_selections(&editor), vec![empty_selection(2, 0)]);
+        editor.move_to_top();
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 0)]);
+
+        editor.move_down();
+        editor.move_right();
+        editor.select_to_end_of_line();
+        assert_eq!(render_selections(&editor), vec![selection((1, 1), (1, 3))]);
+        editor.select_to_beginning_of_line();
+        assert_eq!(render_selections(&editor), vec![rev_selection((1, 0), (1, 1))]);
+        editor.select_to_bottom();
+        assert_eq!(render_selections(&editor), vec![selection((1, 1), (2, 2))]);
+        editor.select_to_top();
+        assert_eq!(render_selections(&editor), vec![rev_selection((0, 0), (1, 1))]);
+
+        editor.move_to_end_of_line();
+        editor.delete_to_beginning_of_line();
+        assert_eq!(editor.buffer.borrow().to_string(), "  \ndef\n  ");
+        editor.move_down();
+        editor.delete_to_end_of_line();
+        assert_eq!(editor.buffer.borrow().to_string(), "  \nde\n  ");
+        editor.delete_to_beginning_of_line();
+        assert_eq!(editor.buffer.borrow().to_string(), "  \n\n  ");
+    }
+
+    #[test]
+    fn test_select_occurrences() {
+        let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+        editor
+            .buffer
+            .borrow_mut()
+            .edit(0..0, "foo bar\nfoo_baz foo\nbar foo\nfoo");
+        editor.move_down();
+        for _ in 0..9 {
+            editor.move_right();
+        }
+
+        // The first occurrence is the word under the cursor
+        editor.select_next_occurrence();
+        assert_eq!(render_selections(&editor), vec![selection((1, 8), (1, 11))]);
+
+        // Skipping replaces the most recently added occurrence with the next one
+        editor.select_next_occurrence();
+        editor.skip_occurrence();
+        assert_eq!(
+            render_selections(&editor),
+            vec![selection((1, 8), (1, 11)), selection((3, 0), (3, 3))]
+        );
+
+        // Later occurrences wrap around to the start of the buffer, passing over occurrences that
+        // are already selected. Occurrences of a word under the cursor must be whole words.
+        editor.select_next_occurrence();
+        editor.select_next_occurrence();
+        editor.select_next_occurrence();
+        assert_eq!(
+            render_selections(&editor),
+            vec![
+                selection((0, 0), (0, 3)),
+                selection((1, 8), (1, 11)),
+                selection((2, 4), (2, 7)),
+                selection((3, 0), (3, 3)),
+            ]
+        );
+
+        // Occurrences of text that was selected explicitly can be part of a larger word
+        let range = buffer_anchor(&editor, 0, 0)..buffer_anchor(&editor, 0, 3);
+        editor.set_selected_anchor_range(range).unwrap();
+        editor.select_next_occurrence();
+        assert_eq!(
+            render_selections(&editor),
+            vec![selection((0, 0), (0, 3)), selection((1, 0), (1, 3))]
+        );
+
+        let cursor = buffer_anchor(&editor, 2, 1);
+        editor
+            .set_selected_anchor_range(cursor.clone()..cursor)
+            .unwrap();
+        editor.select_all_occurrences();
+        assert_eq!(
+            render_selections(&editor),
+            vec![selection((0, 4), (0, 7)), selection((2, 0), (2, 3))]
+        );
+    }
+
+    #[test]
+    fn test_line_commands() {
+        let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+        editor
+            .buffer
+            .borrow_mut()
+            .set_group_interval(Duration::from_millis(0));
+        editor.buffer.borrow_mut().edit(0..0, "a\nb\nc\nd\ne");
+        editor.add_selection(Point::new(1, 0), Point::new(2, 0));
+        editor.add_selection(Point::new(3, 0), Point::new(3, 1));
+
+        // Adjacent rows are duplicated together and the selections move onto the copies
+        editor.duplicate_lines();
+        assert_eq!(editor.buffer.borrow().to_string(), "a\nb\na\nb\nc\nd\nd\ne");
+        assert_eq!(
+            render_selections(&editor),
+            vec![
+                empty_selection(2, 0),
+                selection((3, 0), (4, 0)),
+                selection((6, 0), (6, 1)),
+            ]
+        );
+        editor.undo();
+        assert_eq!(editor.buffer.borrow().to_string(), "a\nb\nc\nd\ne");
+
+        // Rows can't move above the first row
+        editor.move_lines_up();
+        assert_eq!(editor.buffer.borrow().to_string(), "a\nb\nc\nd\ne");
+
+        editor.move_lines_down();
+        assert_eq!(editor.buffer.borrow().to_string(), "c\na\nb\ne\nd");
+        assert_eq!(
+            render_selections(&editor),
+            vec![
+                empty_selection(1, 0),
+                selection((2, 0), (3, 0)),
+                selection((4, 0), (4, 1)),
+            ]
+        );
+
+        // Rows can't move below the last row
+        editor.move_lines_down();
+        assert_eq!(editor.buffer.borrow().to_string(), "c\na\nb\ne\nd");
+
+        editor.move_lines_up();
+        assert_eq!(editor.buffer.borrow().to_string(), "a\nb\nc\nd\ne");
+        assert_eq!(
+            render_selections(&editor),
+            vec![
+                empty_selection(0, 0),
+                selection((1, 0), (2, 0)),
+                selection((3, 0), (3, 1)),
+            ]
+        );
+
+        // Each command is undone as a unit
+        editor.delete_lines();
+        assert_eq!(editor.buffer.borrow().to_string(), "c\ne");
+        assert_eq!(
+            render_selections(&editor),
+            vec![empty_selection(0, 0), empty_selection(1, 0)]
+        );
+        editor.undo();
+        assert_eq!(editor.buffer.borrow().to_string(), "a\nb\nc\nd\ne");
+        assert_eq!(
+            render_selections(&editor),
+            vec![
+                empty_selection(0, 0),
+                selection((1, 0), (2, 0)),
+                selection((3, 0), (3, 1)),
+            ]
+        );
+
+        // Deleting the last row removes the line break preceding it
+        editor.move_to_bottom();
+        editor.delete_lines();
+        assert_eq!(editor.buffer.borrow().to_string(), "a\nb\nc\nd");
+        assert_eq!(render_selections(&editor), vec![empty_selection(3, 1)]);
+    }
+
+    #[test]
+    fn test_join_lines() {
+        let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+        editor
+            .buffer
+            .borrow_mut()
+            .edit(0..0, "a\n  b\nc\n\nd\ne");
+
+        // A selection spanning a single row joins it with the row below
+        editor.move_right();
+        editor.join_lines();
+        assert_eq!(editor.buffer.borrow().to_string(), "a b\nc\n\nd\ne");
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 1)]);
+
+        // Blank rows are joined without a separator
+        editor.add_selection(Point::new(1, 0), Point::new(3, 0));
+        editor.join_lines();
+        assert_eq!(editor.buffer.borrow().to_string(), "a b c\nd\ne");
+        assert_eq!(
+            render_selections(&editor),
+            vec![empty_selection(0, 1), selection((0, 4), (1, 0))]
+        );
+
+        // Nothing is joined past the last row
+        editor.move_to_bottom();
+        editor.join_lines();
+        assert_eq!(editor.buffer.borrow().to_string(), "a b c\nd\ne");
+    }
+
+    #[test]
+    fn test_indentation() {
+        let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+        editor.buffer.borrow_mut().edit(0..0, "a\n  b\n\nc");
+
+        // Newlines copy the leading whitespace of the row they split
+        editor.move_down();
+        editor.move_to_end_of_line();
+        editor.edit("\n");
+        assert_eq!(editor.buffer.borrow().to_string(), "a\n  b\n  \n\nc");
+        assert_eq!(render_selections(&editor), vec![empty_selection(2, 2)]);
+        editor.move_to_beginning_of_line();
+        editor.move_right();
+        editor.edit("\n");
+        assert_eq!(editor.buffer.borrow().to_string(), "a\n  b\n \n  \n\nc");
+        assert_eq!(render_selections(&editor), vec![empty_selection(3, 1)]);
+
+        // Soft tabs insert spaces up to the next tab stop
+        editor.tab();
+        assert_eq!(editor.buffer.borrow().to_string(), "a\n  b\n \n     \n\nc");
+        assert_eq!(render_selections(&editor), vec![empty_selection(3, 4)]);
+
+        editor.buffer.borrow_mut().set_indent_settings(IndentSettings {
+            hard_tabs: true,
+            tab_width: 4,
+        });
+        editor.tab();
+        assert_eq!(editor.buffer.borrow().to_string(), "a\n  b\n \n    \t \n\nc");
+
+        // Selections spanning multiple rows indent every non-empty row instead
+        editor.move_to_top();
+        editor.select_to_bottom();
+        editor.tab();
+        assert_eq!(
+            editor.buffer.borrow().to_string(),
+            "\ta\n\t  b\n\t \n\t    \t \n\n\tc"
+        );
+
+        editor.buffer.borrow_mut().set_indent_settings(IndentSettings {
+            hard_tabs: false,
+            tab_width: 2,
+        });
+        editor.indent_selected_rows();
+        assert_eq!(
+            editor.buffer.borrow().to_string(),
+            "  \ta\n  \t  b\n  \t \n  \t    \t \n\n  \tc"
+        );
+
+        // Outdenting removes a tab or up to a tab width of spaces
+        editor.outdent_selected_rows();
+        editor.outdent_selected_rows();
+        editor.outdent_selected_rows();
+        assert_eq!(editor.buffer.borrow().to_string(), "a\nb\n\n  \t \n\nc");
+
+        // A tab width of zero inserts a single space rather than dividing by zero
+        editor.buffer.borrow_mut().set_indent_settings(IndentSettings {
+            hard_tabs: false,
+            tab_width: 0,
+        });
+        editor.move_to_top();
+        editor.tab();
+        assert_eq!(editor.buffer.borrow().to_string(), " a\nb\n\n  \t \n\nc");
+    }
+
+    #[test]
+    fn test_mouse_selection() {
+        let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+        editor
+            .buffer
+            .borrow_mut()
+            .edit(0..0, "abc def\nghi\njkl mno");
+        editor
+            .set_height(20.0)
+            .set_line_height(10.0)
+            .set_char_width(5.0)
+            .set_scroll_top(10.0);
+
+        // Positions account for the scroll position and are clipped to the buffer, with positions
+        // below the last row mapping to the end of the buffer
+        assert_eq!(editor.point_for_position(12.0, 2.0), Point::new(1, 2));
+        assert_eq!(editor.point_for_position(100.0, 15.0), Point::new(2, 7));
+        assert_eq!(editor.point_for_position(-5.0, 15.0), Point::new(2, 0));
+        assert_eq!(editor.point_for_position(-5.0, 100.0), Point::new(2, 7));
+
+        editor.select_point(Point::new(1, 2));
+        assert_eq!(render_selections(&editor), vec![empty_selection(1, 2)]);
+        editor.select_to_point(Point::new(0, 1));
+        assert_eq!(render_selections(&editor), vec![rev_selection((0, 1), (1, 2))]);
+        editor.select_to_point(Point::new(2, 3));
+        assert_eq!(render_selections(&editor), vec![selection((1, 2), (2, 3))]);
+
+        editor.add_selection(Point::new(0, 0), Point::new(0, 0));
+        assert_eq!(
+            render_selections(&editor),
+            vec![empty_selection(0, 0), selection((1, 2), (2, 3))]
+        );
+
+        editor.select_word_at_point(Point::new(2, 5));
+        assert_eq!(render_selections(&editor), vec![selection((2, 4), (2, 7))]);
+        editor.select_line_at_point(Point::new(0, 5));
+        assert_eq!(render_selections(&editor), vec![selection((0, 0), (1, 0))]);
+        editor.select_line_at_point(Point::new(2, 1));
+        assert_eq!(render_selections(&editor), vec![selection((2, 0), (2, 7))]);
+    }
+
+    #[test]
+    fn test_mouse_drag() {
+        let mut window = Window::new(None, 100.0);
+        let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+        editor
+            .buffer
+            .borrow_mut()
+            .edit(0..0, "abc def\nghi\njkl mno");
+        editor
+            .set_height(100.0)
+            .set_line_height(10.0)
+            .set_char_width(5.0);
+
+        // Dragging after cmd-clicking only extends the added cursor
+        editor.dispatch_action(json!({"type": "Click", "x": 5.0, "y": 5.0}), &mut window);
+        editor.dispatch_action(json!({"type": "CmdClick", "x": 5.0, "y": 25.0}), &mut window);
+        editor.dispatch_action(json!({"type": "Drag", "x": 15.0, "y": 25.0}), &mut window);
+        assert_eq!(
+            render_selections(&editor),
+            vec![empty_selection(0, 1), selection((2, 1), (2, 3))]
+        );
+        editor.dispatch_action(json!({"type": "Drag", "x": 0.0, "y": 15.0}), &mut window);
+        assert_eq!(
+            render_selections(&editor),
+            vec![empty_selection(0, 1), rev_selection((1, 0), (2, 1))]
+        );
+
+        // Dragging after double-clicking extends the selection by words
+        editor.dispatch_action(json!({"type": "DoubleClick", "x": 25.0, "y": 25.0}), &mut window);
+        editor.dispatch_action(json!({"type": "Drag", "x": 5.0, "y": 5.0}), &mut window);
+        assert_eq!(render_selections(&editor), vec![rev_selection((0, 0), (2, 7))]);
+
+        // Dragging after triple-clicking extends the selection by rows
+        editor.dispatch_action(json!({"type": "TripleClick", "x": 5.0, "y": 15.0}), &mut window);
+        editor.dispatch_action(json!({"type": "Drag", "x": 10.0, "y": 25.0}), &mut window);
+        assert_eq!(render_selections(&editor), vec![selection((1, 0), (2, 7))]);
+    }
+
+    #[test]
+    fn test_brackets() {
+        let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+
+        // Opening brackets are closed automatically and typing the closer skips over it
+        editor.edit("(");
+        editor.edit("[");
+        assert_eq!(editor.buffer.borrow().to_string(), "([])");
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 2)]);
+        editor.edit("a");
+        editor.edit("]");
+        assert_eq!(editor.buffer.borrow().to_string(), "([a])");
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 4)]);
+
+        // Brackets aren't closed before other text, and apostrophes aren't closed after words
+        editor.move_to_beginning_of_line();
+        editor.edit("{");
+        editor.move_to_end_of_line();
+        editor.edit(" don");
+        editor.edit("'");
+        editor.edit("t ");
+        editor.edit("'");
+        assert_eq!(editor.buffer.borrow().to_string(), "{([a]) don't ''");
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 14)]);
+
+        // Backspace deletes empty pairs
+        editor.backspace();
+        assert_eq!(editor.buffer.borrow().to_string(), "{([a]) don't ");
+        editor.backspace();
+        assert_eq!(editor.buffer.borrow().to_string(), "{([a]) don't");
+
+        // Selections are wrapped in pairs
+        let start = buffer_anchor(&editor, 0, 7);
+        let end = buffer_anchor(&editor, 0, 10);
+        editor.set_selected_anchor_range(start..end).unwrap();
+        editor.edit("\"");
+        assert_eq!(editor.buffer.borrow().to_string(), "{([a]) \"don\"'t");
+        assert_eq!(render_selections(&editor), vec![selection((0, 8), (0, 11))]);
+    }
+
+    #[test]
+    fn test_bracket_matching() {
+        let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+        editor
+            .buffer
+            .borrow_mut()
+            .edit(0..0, "fn a() {\n    b(c[0]);\n}");
+        editor.set_height(50.0).set_line_height(10.0);
+
+        editor.move_to_end_of_line();
+        assert_eq!(
+            editor.render()["bracket_matches"],
+            json!([
+                {"start": {"row": 0, "column": 7}, "end": {"row": 0, "column": 8}},
+                {"start": {"row": 2, "column": 0}, "end": {"row": 2, "column": 1}},
+            ])
+        );
+
+        editor.go_to_matching_bracket();
+        assert_eq!(render_selections(&editor), vec![empty_selection(2, 1)]);
+        editor.go_to_matching_bracket();
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 7)]);
+
+        // Nested brackets are skipped when scanning for a match
+        editor.move_down();
+        editor.move_to_end_of_line();
+        editor.move_left();
+        assert_eq!(render_selections(&editor), vec![empty_selection(1, 11)]);
+        editor.go_to_matching_bracket();
+        assert_eq!(render_selections(&editor), vec![empty_selection(1, 5)]);
+        assert_eq!(
+            editor.render()["bracket_matches"],
+            json!([
+                {"start": {"row": 1, "column": 5}, "end": {"row": 1, "column": 6}},
+                {"start": {"row": 1, "column": 10}, "end": {"row": 1, "column": 11}},
+            ])
+        );
+
+        // Cursors that aren't next to a bracket don't move
+        editor.move_to_beginning_of_line();
+        editor.go_to_matching_bracket();
+        assert_eq!(render_selections(&editor), vec![empty_selection(1, 4)]);
+        assert_eq!(editor.render()["bracket_matches"], json!([]));
+    }
+
     #[test]
     fn test_backspace() {
         let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
@@ -1006,6 +3756,139 @@ mod tests {
         assert_eq!(editor.buffer.borrow().to_string(), "bcfghi");
     }
 
+    #[test]
+    fn test_copy_cut_paste() {
+        let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+        editor.buffer.borrow_mut().edit(0..0, "abc\ndef\nghi");
+        editor
+            .buffer
+            .borrow_mut()
+            .set_group_interval(Duration::from_millis(0));
+        editor.move_right();
+        editor.select_right();
+        editor.add_selection(Point::new(1, 1), Point::new(1, 2));
+
+        // Pastes one chunk into each selection when their counts match
+        editor.cut();
+        assert_eq!(editor.buffer.borrow().to_string(), "ac\ndf\nghi");
+        assert_eq!(
+            render_selections(&editor),
+            vec![empty_selection(0, 1), empty_selection(1, 1)]
+        );
+        editor.move_right();
+        editor.paste();
+        assert_eq!(editor.buffer.borrow().to_string(), "acb\ndfe\nghi");
+        assert_eq!(
+            render_selections(&editor),
+            vec![empty_selection(0, 3), empty_selection(1, 3)]
+        );
+
+        // Pastes all chunks into each selection otherwise, sharing the clipboard between views
+        let mut other_editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+        other_editor.set_clipboard(editor.clipboard.clone());
+        other_editor.buffer.borrow_mut().edit(0..0, "xy");
+        other_editor.paste();
+        assert_eq!(other_editor.buffer.borrow().to_string(), "b\nexy");
+        assert_eq!(render_selections(&other_editor), vec![empty_selection(1, 1)]);
+
+        // Cuts entire lines from empty selections and pastes them above the cursor
+        editor.cut();
+        assert_eq!(editor.buffer.borrow().to_string(), "ghi");
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 0)]);
+        editor.move_right();
+        editor.paste();
+        assert_eq!(editor.buffer.borrow().to_string(), "acb\ndfe\nghi");
+        assert_eq!(render_selections(&editor), vec![empty_selection(2, 1)]);
+
+        editor.copy();
+        assert_eq!(editor.buffer.borrow().to_string(), "acb\ndfe\nghi");
+        editor.paste();
+        assert_eq!(editor.buffer.borrow().to_string(), "acb\ndfe\nghi\nghi");
+        assert_eq!(render_selections(&editor), vec![empty_selection(3, 1)]);
+
+        // Cutting and pasting are undone in a single step
+        editor.undo();
+        editor.undo();
+        assert_eq!(editor.buffer.borrow().to_string(), "ghi");
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+        editor
+            .buffer
+            .borrow_mut()
+            .set_group_interval(Duration::from_millis(0));
+        editor.buffer.borrow_mut().edit(0..0, "abc\ndef");
+        editor.move_right();
+        editor.add_selection(Point::new(1, 1), Point::new(1, 2));
+
+        editor.edit("x");
+        editor.move_left();
+        editor.edit("y");
+        assert_eq!(editor.buffer.borrow().to_string(), "ayxbc\ndyxf");
+        assert_eq!(
+            render_selections(&editor),
+            vec![empty_selection(0, 2), empty_selection(1, 2)]
+        );
+
+        // Edits to multiple selections are undone as a unit, restoring the selections that
+        // preceded them.
+        editor.undo();
+        assert_eq!(editor.buffer.borrow().to_string(), "axbc\ndxf");
+        assert_eq!(
+            render_selections(&editor),
+            vec![empty_selection(0, 1), empty_selection(1, 1)]
+        );
+        editor.undo();
+        assert_eq!(editor.buffer.borrow().to_string(), "abc\ndef");
+        assert_eq!(
+            render_selections(&editor),
+            vec![empty_selection(0, 1), selection((1, 1), (1, 2))]
+        );
+
+        editor.redo();
+        assert_eq!(editor.buffer.borrow().to_string(), "axbc\ndxf");
+        assert_eq!(
+            render_selections(&editor),
+            vec![empty_selection(0, 2), empty_selection(1, 2)]
+        );
+        editor.redo();
+        assert_eq!(editor.buffer.borrow().to_string(), "ayxbc\ndyxf");
+        assert_eq!(
+            render_selections(&editor),
+            vec![empty_selection(0, 2), empty_selection(1, 2)]
+        );
+    }
+
+    #[test]
+    fn test_undo_coalesces_consecutive_edits() {
+        let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+        editor
+            .buffer
+            .borrow_mut()
+            .set_group_interval(Duration::from_secs(60));
+        editor.edit("a");
+        editor.edit("b");
+        editor.edit("c");
+        editor
+            .buffer
+            .borrow_mut()
+            .set_group_interval(Duration::from_millis(0));
+        editor.edit("d");
+        assert_eq!(editor.buffer.borrow().to_string(), "abcd");
+
+        editor.undo();
+        assert_eq!(editor.buffer.borrow().to_string(), "abc");
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 3)]);
+        editor.undo();
+        assert_eq!(editor.buffer.borrow().to_string(), "");
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 0)]);
+        editor.redo();
+        assert_eq!(editor.buffer.borrow().to_string(), "abc");
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 3)]);
+    }
+
     #[test]
     fn test_add_selection() {
         let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
@@ -1212,6 +4095,96 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_search_and_replace() {
+        let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+        editor.buffer.borrow_mut().edit(0..0, "abc\nAbc abc\ndef");
+        editor
+            .buffer
+            .borrow_mut()
+            .set_group_interval(Duration::from_millis(0));
+        editor.set_height(100.0);
+        let options = TextSearchOptions::default();
+
+        assert_eq!(editor.search("abc", options), Ok(3));
+        editor.select_next_search_match();
+        assert_eq!(render_selections(&editor), vec![selection((0, 0), (0, 3))]);
+        editor.select_next_search_match();
+        assert_eq!(render_selections(&editor), vec![selection((1, 0), (1, 3))]);
+        editor.select_previous_search_match();
+        assert_eq!(render_selections(&editor), vec![selection((0, 0), (0, 3))]);
+        editor.select_previous_search_match();
+        assert_eq!(render_selections(&editor), vec![selection((1, 4), (1, 7))]);
+        editor.select_next_search_match();
+        assert_eq!(render_selections(&editor), vec![selection((0, 0), (0, 3))]);
+
+        let case_sensitive_regex = TextSearchOptions {
+            regex: true,
+            case_sensitive: true,
+            ..options
+        };
+        assert!(editor.search("(", case_sensitive_regex).is_err());
+        assert_eq!(editor.search_match_count(), 0);
+        assert_eq!(editor.search("[a-z]bc", case_sensitive_regex), Ok(2));
+        assert_eq!(
+            editor.render()["search_matches"],
+            json!([
+                {"start": {"row": 0, "column": 0}, "end": {"row": 0, "column": 3}},
+                {"start": {"row": 1, "column": 4}, "end": {"row": 1, "column": 7}},
+            ])
+        );
+
+        // Matches are anchored, so they stay in place when the buffer is edited.
+        editor.buffer.borrow_mut().edit(0..0, "xyz ");
+        assert_eq!(
+            editor.render()["search_matches"],
+            json!([
+                {"start": {"row": 0, "column": 4}, "end": {"row": 0, "column": 7}},
+                {"start": {"row": 1, "column": 4}, "end": {"row": 1, "column": 7}},
+            ])
+        );
+
+        // Replacing without a selected match only selects the next match.
+        editor.move_down();
+        editor.replace_search_match("123");
+        assert_eq!(editor.buffer.borrow().to_string(), "xyz abc\nAbc abc\ndef");
+        assert_eq!(render_selections(&editor), vec![selection((0, 4), (0, 7))]);
+        editor.replace_search_match("123");
+        assert_eq!(editor.buffer.borrow().to_string(), "xyz 123\nAbc abc\ndef");
+        assert_eq!(render_selections(&editor), vec![selection((1, 4), (1, 7))]);
+        assert_eq!(editor.search_match_count(), 1);
+
+        // Replacing all matches is undone in a single step.
+        assert_eq!(editor.search("[a-z]", case_sensitive_regex), Ok(11));
+        editor.replace_all_search_matches("_");
+        assert_eq!(editor.buffer.borrow().to_string(), "___ 123\nA__ ___\n___");
+        assert_eq!(editor.search_match_count(), 0);
+        editor.undo();
+        assert_eq!(editor.buffer.borrow().to_string(), "xyz 123\nAbc abc\ndef");
+
+        // Matches are found again after edits, so text that no longer matches isn't replaced.
+        assert_eq!(editor.search("abc", options), Ok(2));
+        editor.buffer.borrow_mut().edit(9..10, "x");
+        editor.buffer.borrow_mut().edit(0..3, "ABC");
+        editor.select_next_search_match();
+        assert_eq!(editor.search_match_count(), 2);
+        assert_eq!(
+            editor.render()["search_matches"],
+            json!([
+                {"start": {"row": 0, "column": 0}, "end": {"row": 0, "column": 3}},
+                {"start": {"row": 1, "column": 4}, "end": {"row": 1, "column": 7}},
+            ])
+        );
+        editor.buffer.borrow_mut().edit(13..14, "x");
+        editor.replace_all_search_matches("_");
+        assert_eq!(editor.buffer.borrow().to_string(), "_ 123\nAxc axc\ndef");
+        assert_eq!(editor.search_match_count(), 0);
+
+        editor.search("abc", options).unwrap();
+        editor.clear_search();
+        assert_eq!(editor.render()["search_matches"], json!([]));
+    }
+
     #[test]
     fn test_autoscroll() {
         let mut buffer = Buffer::new(0);
@@ -1237,6 +4210,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_scroll_to_user() {
+        use rpc;
+        use stream_ext::StreamExt;
+        use tokio_core::reactor;
+
+        let mut reactor = reactor::Core::new().unwrap();
+        let foreground = Rc::new(reactor.handle());
+        let mut buffer = Buffer::new(0);
+        buffer.edit(0..0, "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl");
+        let leader_buffer = buffer.into_shared();
+        let follower_buffer = Buffer::remote(
+            foreground,
+            rpc::tests::connect(&mut reactor, buffer::rpc::Service::new(leader_buffer.clone())),
+        ).unwrap();
+        let mut follower_buffer_updates = follower_buffer.borrow().updates();
+
+        // The leader's viewport is reported whenever it scrolls
+        let presence = Presence::new().into_shared();
+        let mut leader = BufferView::new(leader_buffer, 0, None);
+        leader
+            .set_presence(presence.clone())
+            .set_height(20.0)
+            .set_line_height(5.0)
+            .set_scroll_top(30.0);
+        leader.select_point(Point::new(10, 0));
+        let viewport = presence.borrow().viewport(0).cloned().unwrap();
+        assert_eq!(viewport.buffer_id, leader.buffer_id());
+        assert_eq!(viewport.scroll_top, 30.0);
+        follower_buffer_updates.wait_next(&mut reactor).unwrap();
+
+        // A shorter follower scrolls further so that the leader's cursor stays visible
+        let mut follower = BufferView::new(follower_buffer, 1, None);
+        follower.set_height(10.0).set_line_height(5.0);
+        follower.scroll_to_user(0, viewport.scroll_top);
+        assert_eq!(follower.scroll_top(), 50.0);
+    }
+
     #[test]
     fn test_render() {
         let buffer = Rc::new(RefCell::new(Buffer::new(0)));
@@ -1309,6 +4320,163 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_soft_wrap() {
+        let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+        editor.buffer.borrow_mut().edit(0..0, "abc def ghi\njk\nlmnopq");
+        let line_height = 5.0;
+        editor
+            .set_height(10.0 * line_height)
+            .set_line_height(line_height)
+            .set_width(45.0)
+            .set_char_width(10.0);
+
+        let frame = editor.render();
+        assert_eq!(
+            stringify_lines(&frame["lines"]),
+            vec!["abc ", "def ", "ghi", "jk", "lmno", "pq"]
+        );
+
+        editor.move_right();
+        editor.move_down();
+        assert_eq!(render_selections(&editor), vec![empty_selection(1, 1)]);
+        assert_eq!(editor.selections()[0].start, buffer_anchor(&editor, 0, 5));
+        editor.move_down();
+        editor.move_down();
+        assert_eq!(editor.selections()[0].start, buffer_anchor(&editor, 1, 1));
+        editor.move_down();
+        editor.move_down();
+        assert_eq!(editor.selections()[0].start, buffer_anchor(&editor, 2, 5));
+        editor.move_up();
+        editor.move_up();
+        editor.move_up();
+        assert_eq!(editor.selections()[0].start, buffer_anchor(&editor, 0, 9));
+
+        editor.select_up();
+        assert_eq!(render_selections(&editor), vec![rev_selection((1, 1), (2, 1))]);
+        assert_eq!(
+            editor.render()["selections"],
+            json!([rev_selection((1, 1), (2, 1))])
+        );
+
+        // Typing on a wrapped line rewraps it.
+        editor.move_down();
+        editor.move_up();
+        editor.edit("xyz ");
+        assert_eq!(
+            stringify_lines(&editor.render()["lines"]),
+            vec!["abc ", "def ", "gxyz ", "hi", "jk", "lmno", "pq"]
+        );
+
+        editor.set_width(100.0);
+        assert_eq!(
+            stringify_lines(&editor.render()["lines"]),
+            vec!["abc def ", "gxyz hi", "jk", "lmnopq"]
+        );
+    }
+
+    #[test]
+    fn test_folding() {
+        let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+        editor
+            .buffer
+            .borrow_mut()
+            .edit(0..0, "a {\n  b {\n    c\n  }\n\n  d\n}\ne");
+        editor.set_height(100.0).set_line_height(5.0);
+
+        // Folding with an empty selection folds the innermost indented block.
+        editor.move_down();
+        editor.move_down();
+        editor.fold_selected_rows();
+        assert_eq!(
+            stringify_lines(&editor.render()["lines"]),
+            vec!["a {", "  b {⋯", "  }", "", "  d", "}", "e"]
+        );
+        assert_eq!(render_selections(&editor), vec![empty_selection(1, 5)]);
+
+        // Movement skips over folded rows.
+        editor.move_right();
+        assert_eq!(render_selections(&editor), vec![empty_selection(2, 0)]);
+        editor.move_left();
+        assert_eq!(render_selections(&editor), vec![empty_selection(1, 5)]);
+        editor.move_down();
+        assert_eq!(editor.selections()[0].start, buffer_anchor(&editor, 3, 3));
+        editor.move_up();
+        editor.move_up();
+        assert_eq!(editor.selections()[0].start, buffer_anchor(&editor, 0, 3));
+
+        // Folding again from the outer block hides the inner fold along with it.
+        editor.move_down();
+        editor.move_down();
+        editor.fold_selected_rows();
+        assert_eq!(stringify_lines(&editor.render()["lines"]), vec!["a {⋯", "}", "e"]);
+
+        editor.unfold_selected_rows();
+        assert_eq!(
+            stringify_lines(&editor.render()["lines"]),
+            vec!["a {", "  b {⋯", "  }", "", "  d", "}", "e"]
+        );
+        editor.move_down();
+        editor.unfold_selected_rows();
+        assert_eq!(
+            stringify_lines(&editor.render()["lines"]),
+            vec!["a {", "  b {", "    c", "  }", "", "  d", "}", "e"]
+        );
+
+        // Folding a selection spanning several rows hides all but its first row.
+        editor.select_down();
+        editor.select_down();
+        editor.fold_selected_rows();
+        assert_eq!(
+            stringify_lines(&editor.render()["lines"]),
+            vec!["a {", "  b {⋯", "", "  d", "}", "e"]
+        );
+        assert_eq!(render_selections(&editor), vec![empty_selection(1, 5)]);
+
+        editor.fold_all();
+        assert_eq!(stringify_lines(&editor.render()["lines"]), vec!["a {⋯", "}", "e"]);
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 3)]);
+    }
+
+    #[test]
+    fn test_syntax_folding() {
+        let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+        editor
+            .buffer
+            .borrow_mut()
+            .edit(0..0, "fn a() {\nb(1,\n2,\n3);\n}\nc");
+        editor.set_height(100.0).set_line_height(5.0);
+        highlight(&mut editor, Language::Rust);
+
+        // Folding with an empty selection folds the innermost syntax node, even when the
+        // buffer isn't indented.
+        editor.move_down();
+        editor.move_down();
+        editor.fold_selected_rows();
+        assert_eq!(
+            stringify_lines(&editor.render()["lines"]),
+            vec!["fn a() {", "b(1,⋯", "3);", "}", "c"]
+        );
+
+        editor.fold_all();
+        assert_eq!(stringify_lines(&editor.render()["lines"]), vec!["fn a() {⋯", "}", "c"]);
+        editor.unfold_selected_rows();
+        editor.move_down();
+        editor.unfold_selected_rows();
+        assert_eq!(
+            stringify_lines(&editor.render()["lines"]),
+            vec!["fn a() {", "b(1,", "2,", "3);", "}", "c"]
+        );
+
+        // Highlights that lag behind the buffer aren't used for folding.
+        editor.buffer.borrow_mut().edit(0..0, "\n");
+        editor.fold_selected_rows();
+        assert_eq!(
+            stringify_lines(&editor.render()["lines"]),
+            vec!["", "fn a() {", "b(1,", "2,", "3);", "}", "c"]
+        );
+    }
+
     #[test]
     fn test_render_past_last_line() {
         let line_height = 4.0;
@@ -1332,6 +4500,171 @@ mod tests {
         assert_eq!(frame["selections"], json!([selection((2, 3), (2, 3))]));
     }
 
+    #[test]
+    fn test_page_movement() {
+        let mut editor = BufferView::new(Rc::new(RefCell::new(Buffer::new(0))), 0, None);
+        let mut lines = vec!["abcd"; 12];
+        lines[6] = "x";
+        editor.buffer.borrow_mut().edit(0..0, lines.join("\n").as_str());
+        let line_height = 5.0;
+        editor
+            .set_height(4.0 * line_height)
+            .set_line_height(line_height);
+        editor.move_down();
+        editor.move_down();
+        editor.move_right();
+        editor.move_right();
+        assert_eq!(render_selections(&editor), vec![empty_selection(2, 2)]);
+
+        // Maintains the goal column when paging across a shorter line
+        editor.page_down();
+        assert_eq!(render_selections(&editor), vec![empty_selection(6, 1)]);
+        assert_eq!(editor.scroll_top(), 4.0 * line_height);
+        editor.page_down();
+        assert_eq!(render_selections(&editor), vec![empty_selection(10, 2)]);
+        assert_eq!(editor.scroll_top(), 8.0 * line_height);
+
+        // Stops at the last line, which can't be scrolled past the top of the view
+        editor.page_down();
+        assert_eq!(render_selections(&editor), vec![empty_selection(11, 2)]);
+        assert_eq!(editor.scroll_top(), 9.0 * line_height);
+        editor.page_down();
+        assert_eq!(render_selections(&editor), vec![empty_selection(11, 4)]);
+        assert_eq!(editor.scroll_top(), 9.0 * line_height);
+        assert_eq!(editor.render()["first_visible_row"], 9);
+
+        editor.page_up();
+        assert_eq!(render_selections(&editor), vec![empty_selection(7, 2)]);
+        assert_eq!(editor.scroll_top(), 5.0 * line_height);
+        editor.page_up();
+        assert_eq!(render_selections(&editor), vec![empty_selection(3, 2)]);
+        assert_eq!(editor.scroll_top(), 1.0 * line_height);
+        editor.page_up();
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 2)]);
+        assert_eq!(editor.scroll_top(), 0.0);
+        editor.page_up();
+        assert_eq!(render_selections(&editor), vec![empty_selection(0, 0)]);
+
+        editor.select_page_down();
+        assert_eq!(render_selections(&editor), vec![selection((0, 0), (4, 2))]);
+        editor.select_page_up();
+        assert_eq!(render_selections(&editor), vec![selection((0, 0), (0, 2))]);
+    }
+
+    #[test]
+    fn test_render_markers() {
+        let buffer = Rc::new(RefCell::new(Buffer::new(0)));
+        buffer
+            .borrow_mut()
+            .edit(0..0, "abc\ndef\nghi\njkl\nmno\npqr\nstu\nvwx\nyz");
+        let line_height = 6.0;
+        let mut editor = BufferView::new(buffer.clone(), 0, None);
+        editor
+            .set_height(3.0 * line_height)
+            .set_line_height(line_height)
+            .set_scroll_top(2.5 * line_height);
+
+        let layer_id = {
+            let mut buffer = buffer.borrow_mut();
+            let layer_id = buffer.add_marker_layer(false);
+            let ranges = [
+                ((0, 1), (1, 0)),
+                ((4, 0), (4, 2)),
+                ((1, 2), (2, 1)),
+                ((6, 1), (7, 0)),
+            ];
+            for &(start, end) in &ranges {
+                let start = buffer
+                    .anchor_before_point(Point::new(start.0, start.1))
+                    .unwrap();
+                let end = buffer.anchor_after_point(Point::new(end.0, end.1)).unwrap();
+                buffer
+                    .add_marker(layer_id, start..end, json!({"class": "bookmark"}))
+                    .unwrap();
+            }
+            layer_id
+        };
+
+        let frame = editor.render();
+        assert_eq!(
+            frame["markers"],
+            json!([
+                marker(layer_id, (1, 2), (2, 1)),
+                marker(layer_id, (4, 0), (4, 2)),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_render_gutter() {
+        let buffer = Rc::new(RefCell::new(Buffer::new(0)));
+        buffer
+            .borrow_mut()
+            .edit(0..0, "abc\ndef\nghijkl\njkl\nmno\npqr");
+        let line_height = 6.0;
+        let mut editor = BufferView::new(buffer.clone(), 0, None);
+        editor
+            .set_width(20.0)
+            .set_char_width(5.0)
+            .set_height(4.0 * line_height)
+            .set_line_height(line_height);
+        editor.move_down();
+        editor.add_selection(Point::new(1, 1), Point::new(1, 2));
+        editor.add_selection(Point::new(3, 1), Point::new(3, 1));
+        editor.set_scroll_top(line_height);
+
+        let (diagnostics_layer_id, diff_layer_id) = {
+            let mut buffer = buffer.borrow_mut();
+            let diagnostics_layer_id = buffer.add_marker_layer(false);
+            let diff_layer_id = buffer.add_marker_layer(false);
+            let start = buffer.anchor_before_point(Point::new(2, 1)).unwrap();
+            let end = buffer.anchor_after_point(Point::new(3, 0)).unwrap();
+            buffer
+                .add_marker(diagnostics_layer_id, start..end, json!({"class": "error"}))
+                .unwrap();
+            let start = buffer.anchor_before_point(Point::new(0, 0)).unwrap();
+            let end = buffer.anchor_after_point(Point::new(5, 1)).unwrap();
+            buffer
+                .add_marker(diff_layer_id, start..end, json!({"class": "added"}))
+                .unwrap();
+            (diagnostics_layer_id, diff_layer_id)
+        };
+        editor.add_gutter_layer(diagnostics_layer_id);
+
+        // The third line wraps onto a second display row, which isn't numbered
+        let frame = editor.render();
+        assert_eq!(frame["gutter"]["line_numbers"], json!([2, 3, null, 4]));
+        assert_eq!(frame["gutter"]["cursor_rows"], json!([1, 4]));
+        assert_eq!(
+            frame["gutter"]["decorations"],
+            json!([
+                {"row": 2, "layer_id": diagnostics_layer_id, "properties": {"class": "error"}},
+            ])
+        );
+
+        editor.add_gutter_layer(diff_layer_id);
+        let frame = editor.render();
+        assert_eq!(
+            frame["gutter"]["decorations"],
+            json!([
+                {"row": 1, "layer_id": diff_layer_id, "properties": {"class": "added"}},
+                {"row": 2, "layer_id": diagnostics_layer_id, "properties": {"class": "error"}},
+                {"row": 2, "layer_id": diff_layer_id, "properties": {"class": "added"}},
+                {"row": 4, "layer_id": diff_layer_id, "properties": {"class": "added"}},
+            ])
+        );
+
+        // Layers removed from the buffer are no longer rendered
+        buffer.borrow_mut().remove_marker_layer(diff_layer_id).unwrap();
+        let frame = editor.render();
+        assert_eq!(
+            frame["gutter"]["decorations"],
+            json!([
+                {"row": 2, "layer_id": diagnostics_layer_id, "properties": {"class": "error"}},
+            ])
+        );
+    }
+
     #[test]
     fn test_dropping_view_removes_selection_set() {
         let buffer = Buffer::new(0).into_shared();
@@ -1343,6 +4676,23 @@ mod tests {
         assert!(buffer.borrow_mut().selections(selection_set_id).is_err());
     }
 
+    // Highlights the editor's buffer synchronously, instead of waiting for the view to be mounted
+    // and tokenize it in the background.
+    fn highlight(editor: &mut BufferView, language: Language) {
+        let (_, requests, highlight_updates) = Highlighter::new(language);
+        let buffer = editor.buffer.clone();
+        let mut buffer = buffer.borrow_mut();
+        let mut highlights = Highlights::new(language);
+        highlights.update(&buffer.snapshot(), &[]);
+        editor.syntax = Some(SyntaxState {
+            requests,
+            edits: buffer.subscribe_edits(),
+            last_sent_version: buffer.version.clone(),
+            highlight_updates,
+            highlights: Some(Arc::new(highlights)),
+        });
+    }
+
     fn stringify_lines(lines: &serde_json::Value) -> Vec<String> {
         lines
             .as_array()
@@ -1354,46 +4704,72 @@ mod tests {
 
     fn render_selections(editor: &BufferView) -> Vec<SelectionProps> {
         let buffer = editor.buffer.borrow();
+        let display_map = editor.display_map();
+        let display_point = |anchor| {
+            display_map.to_display_point(buffer.point_for_anchor(anchor).unwrap(), &buffer)
+        };
         editor
             .selections()
             .iter()
             .map(|s| SelectionProps {
                 user_id: 0,
-                start: buffer.point_for_anchor(&s.start).unwrap(),
-                end: buffer.point_for_anchor(&s.end).unwrap(),
+                start: display_point(&s.start),
+                end: display_point(&s.end),
                 reversed: s.reversed,
                 remote: false,
+                user: None,
             })
             .collect()
     }
 
+    fn buffer_anchor(editor: &BufferView, row: u32, column: u32) -> buffer::Anchor {
+        editor
+            .buffer
+            .borrow()
+            .anchor_before_point(Point::new(row, column))
+            .unwrap()
+    }
+
+    fn marker(layer_id: MarkerLayerId, start: (u32, u32), end: (u32, u32)) -> MarkerProps {
+        MarkerProps {
+            layer_id,
+            start: DisplayPoint::new(start.0, start.1),
+            end: DisplayPoint::new(end.0, end.1),
+            properties: json!({"class": "bookmark"}),
+            remote: false,
+        }
+    }
+
     fn empty_selection(row: u32, column: u32) -> SelectionProps {
         SelectionProps {
             user_id: 0,
-            start: Point::new(row, column),
-            end: Point::new(row, column),
+            start: DisplayPoint::new(row, column),
+            end: DisplayPoint::new(row, column),
             reversed: false,
             remote: false,
+            user: None,
         }
     }
 
     fn selection(start: (u32, u32), end: (u32, u32)) -> SelectionProps {
         SelectionProps {
             user_id: 0,
-            start: Point::new(start.0, start.1),
-            end: Point::new(end.0, end.1),
+            start: DisplayPoint::new(start.0, start.1),
+            end: DisplayPoint::new(end.0, end.1),
             reversed: false,
             remote: false,
+            user: None,
         }
     }
 
     fn rev_selection(start: (u32, u32), end: (u32, u32)) -> SelectionProps {
         SelectionProps {
             user_id: 0,
-            start: Point::new(start.0, start.1),
-            end: Point::new(end.0, end.1),
+            start: DisplayPoint::new(start.0, start.1),
+            end: DisplayPoint::new(end.0, end.1),
             reversed: true,
             remote: false,
+            user: None,
         }
     }
 }
@@ -0,0 +1,67 @@
+/// Holds the text most recently copied from a buffer view. The text of each selection is kept
+/// separately, so that pasting into the same number of selections can insert one selection's
+/// text into each of them.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Clipboard {
+    selections: Vec<ClipboardSelection>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClipboardSelection {
+    pub text: String,
+    /// Whether the text was copied from an empty selection, in which case the entire line
+    /// containing the selection was copied and it is pasted above the line of the cursor.
+    pub entire_line: bool,
+}
+
+impl Clipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, selections: Vec<ClipboardSelection>) {
+        self.selections = selections;
+    }
+
+    pub fn selections(&self) -> &[ClipboardSelection] {
+        &self.selections
+    }
+
+    /// Returns the text of all selections, each starting on a new line.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for (i, selection) in self.selections.iter().enumerate() {
+            if i > 0 && !text.ends_with('\n') {
+                text.push('\n');
+            }
+            text.push_str(&selection.text);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text() {
+        let mut clipboard = Clipboard::new();
+        assert_eq!(clipboard.text(), "");
+
+        clipboard.write(vec![
+            selection("abc", false),
+            selection("def\n", true),
+            selection("ghi", false),
+            selection("jkl", false),
+        ]);
+        assert_eq!(clipboard.text(), "abc\ndef\nghi\njkl");
+    }
+
+    fn selection(text: &str, entire_line: bool) -> ClipboardSelection {
+        ClipboardSelection {
+            text: text.into(),
+            entire_line,
+        }
+    }
+}
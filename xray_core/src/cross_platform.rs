@@ -68,6 +68,22 @@ impl Path {
         }
     }
 
+    pub fn extension(&self) -> Option<Cow<str>> {
+        if let Some(ref path) = self.0 {
+            match path {
+                &PathState::Unix(ref chars) => {
+                    let file_name = chars.rsplit(|c| *c == UNIX_MAIN_SEPARATOR).next()?;
+                    match file_name.iter().rposition(|c| *c == b'.') {
+                        Some(0) | None => None,
+                        Some(index) => Some(String::from_utf8_lossy(&file_name[index + 1..])),
+                    }
+                }
+            }
+        } else {
+            None
+        }
+    }
+
     #[cfg(unix)]
     pub fn to_path_buf(&self) -> PathBuf {
         use std::os::unix::ffi::OsStrExt;
@@ -0,0 +1,199 @@
+use std::ops::Range;
+
+// The number of inserted or deleted lines beyond which texts are considered too different to be
+// worth diffing precisely. This bounds the time and memory spent on the search, which otherwise
+// grow with the square of the number of changes.
+const MAX_EDIT_DISTANCE: isize = 1000;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Hunk {
+    pub old_range: Range<usize>,
+    pub new_range: Range<usize>,
+}
+
+impl Hunk {
+    /// Returns true if this hunk ends before the other hunk begins in the old text. Insertions at
+    /// the same position don't precede each other, since their order is ambiguous.
+    pub fn precedes(&self, other: &Hunk) -> bool {
+        self.old_range.end <= other.old_range.start && self.old_range != other.old_range
+    }
+
+    /// Returns the change in length caused by applying this hunk.
+    pub fn delta(&self) -> isize {
+        self.new_range.len() as isize - self.old_range.len() as isize
+    }
+}
+
+/// Computes the line-wise differences between two texts using Myers' algorithm. The ranges of
+/// each returned hunk are offsets into the old and new text respectively, and hunks are ordered
+/// by their position in both texts. Texts that differ by more than `MAX_EDIT_DISTANCE` lines are
+/// reported as a single hunk replacing all of the old text with the new one.
+pub fn diff(old: &[u16], new: &[u16]) -> Vec<Hunk> {
+    let old_lines = line_starts(old);
+    let new_lines = line_starts(new);
+    let old_line = |i: usize| &old[old_lines[i]..old_lines[i + 1]];
+    let new_line = |i: usize| &new[new_lines[i]..new_lines[i + 1]];
+
+    let n = (old_lines.len() - 1) as isize;
+    let m = (new_lines.len() - 1) as isize;
+    let max = n + m;
+    let index = |k: isize| (k + max + 1) as usize;
+
+    // Only the diagonals reachable in `d` steps are recorded for each step, so that the trace
+    // grows with the square of the edit distance rather than with the length of the texts.
+    let mut v = vec![0_isize; 2 * max as usize + 3];
+    let mut trace = Vec::new();
+    'search: for d in 0..max + 1 {
+        if d > MAX_EDIT_DISTANCE {
+            return vec![Hunk {
+                old_range: 0..old.len(),
+                new_range: 0..new.len(),
+            }];
+        }
+
+        trace.push(v[index(-d)..index(d) + 1].to_vec());
+        let mut k = -d;
+        while k <= d {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old_line(x as usize) == new_line(y as usize) {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+            k += 2;
+        }
+    }
+
+    // Walk the trace backward from the end of both texts to recover the lines they share.
+    let mut matches = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let index = |k: isize| (k + d) as usize;
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = if d == 0 { 0 } else { v[index(prev_k)] };
+        let prev_y = if d == 0 { 0 } else { prev_x - prev_k };
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    matches.reverse();
+    matches.push((n as usize, m as usize));
+
+    let mut hunks = Vec::new();
+    let (mut old_row, mut new_row) = (0, 0);
+    for (matched_old_row, matched_new_row) in matches {
+        if matched_old_row > old_row || matched_new_row > new_row {
+            hunks.push(Hunk {
+                old_range: old_lines[old_row]..old_lines[matched_old_row],
+                new_range: new_lines[new_row]..new_lines[matched_new_row],
+            });
+        }
+        old_row = matched_old_row + 1;
+        new_row = matched_new_row + 1;
+    }
+    hunks
+}
+
+// Returns the offset at which each line starts, followed by the length of the text. Each line
+// includes its trailing newline.
+fn line_starts(text: &[u16]) -> Vec<usize> {
+    let mut starts = vec![0];
+    for (offset, c) in text.iter().enumerate() {
+        if *c == u16::from(b'\n') && offset + 1 < text.len() {
+            starts.push(offset + 1);
+        }
+    }
+    if text.len() > 0 {
+        starts.push(text.len());
+    }
+    starts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(summarize("", ""), vec![]);
+        assert_eq!(summarize("a\nb\nc", "a\nb\nc"), vec![]);
+        assert_eq!(
+            summarize("", "a\nb\n"),
+            vec![("".to_string(), "a\nb\n".to_string())]
+        );
+        assert_eq!(
+            summarize("a\nb\nc\n", "a\nc\n"),
+            vec![("b\n".to_string(), "".to_string())]
+        );
+        assert_eq!(
+            summarize("a\nb\nc\nd\ne", "a\nB\nc\nd\ne\nf"),
+            vec![
+                ("b\n".to_string(), "B\n".to_string()),
+                ("e".to_string(), "e\nf".to_string()),
+            ]
+        );
+        assert_eq!(
+            summarize("a\nb\nc\n", "x\na\nc\ny\n"),
+            vec![
+                ("".to_string(), "x\n".to_string()),
+                ("b\n".to_string(), "".to_string()),
+                ("".to_string(), "y\n".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_large_texts() {
+        let old = (0..20_000)
+            .map(|i| format!("line {}\n", i))
+            .collect::<String>();
+
+        // A few changes in a long text are still found precisely.
+        let new = old
+            .replacen("line 10000\n", "LINE 10000\n", 1)
+            .replacen("line 15000\n", "", 1);
+        assert_eq!(
+            summarize(&old, &new),
+            vec![
+                ("line 10000\n".to_string(), "LINE 10000\n".to_string()),
+                ("line 15000\n".to_string(), "".to_string()),
+            ]
+        );
+
+        // A text that was rewritten entirely is replaced in a single hunk.
+        let new = old.replace("line", "row");
+        assert_eq!(summarize(&old, &new), vec![(old.clone(), new.clone())]);
+    }
+
+    fn summarize(old: &str, new: &str) -> Vec<(String, String)> {
+        let old = old.encode_utf16().collect::<Vec<_>>();
+        let new = new.encode_utf16().collect::<Vec<_>>();
+        diff(&old, &new)
+            .into_iter()
+            .map(|hunk| {
+                (
+                    String::from_utf16_lossy(&old[hunk.old_range]),
+                    String::from_utf16_lossy(&new[hunk.new_range]),
+                )
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,945 @@
+use buffer::{Anchor, Buffer, EditSubscription, Iter, Point, Snapshot, Version};
+use futures::sync::mpsc;
+use futures::{Async, Future, Poll, Stream};
+use notify_cell::{NotifyCell, NotifyCellObserver};
+use std::cmp;
+use std::mem;
+use std::ops::{Add, AddAssign, Range};
+use std::sync::Arc;
+use tree::{self, Item, SeekBias, Tree};
+
+// Wrapping more rows than this is deferred to the background wrapper when one is running.
+const MAX_FOREGROUND_WRAP_ROWS: u32 = 100;
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct DisplayPoint {
+    pub row: u32,
+    pub column: u32,
+}
+
+/// The columns of a buffer row that are displayed on a single display row. A missing end column
+/// means the segment extends to the end of the buffer row. Segments that are followed by folded
+/// rows are displayed with a placeholder at their end.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Segment {
+    pub buffer_row: u32,
+    pub start_column: u32,
+    pub end_column: Option<u32>,
+    pub folded: bool,
+}
+
+/// Maps points in a buffer to points on the screen, breaking rows that are longer than the wrap
+/// width into several display rows.
+///
+/// Edits are interpolated as soon as the map is synchronized with the buffer by displaying the
+/// edited rows unwrapped, which takes logarithmic time regardless of the size of the edit. The
+/// edited rows are then wrapped immediately if there are only a few of them, and on the
+/// background wrapper otherwise. Until the wrapped rows arrive, the map remains usable.
+///
+/// Folds are anchored in the buffer, so they survive edits from other replicas. The first row
+/// spanned by a fold remains visible and the rest of its rows are hidden.
+pub struct DisplayMap {
+    edits: EditSubscription,
+    chunks: Tree<Chunk>,
+    wrap_width: Option<u32>,
+    // Buffer rows whose chunks don't reflect their current text or the current wrap width.
+    invalid_rows: Vec<Range<u32>>,
+    wrapper: Option<WrapperHandle>,
+    folds: Vec<Range<Anchor>>,
+    // Whether chunks have been spliced since the folded rows were last hidden.
+    folds_invalid: bool,
+}
+
+struct WrapperHandle {
+    requests: mpsc::UnboundedSender<WrapRequest>,
+    results: NotifyCellObserver<Option<Arc<WrapResult>>>,
+    last_request: Option<(Version, u32)>,
+}
+
+/// Wraps rows of buffer snapshots on a background thread, publishing the resulting chunks after
+/// each request. If several requests arrive while we're busy, only the latest is serviced.
+pub struct Wrapper {
+    requests: mpsc::UnboundedReceiver<WrapRequest>,
+    results: NotifyCell<Option<Arc<WrapResult>>>,
+}
+
+struct WrapRequest {
+    snapshot: Snapshot,
+    wrap_width: u32,
+    rows: Vec<Range<u32>>,
+}
+
+struct WrapResult {
+    version: Version,
+    wrap_width: u32,
+    rows: Vec<Range<u32>>,
+    chunks: Vec<Tree<Chunk>>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Chunk {
+    // Consecutive buffer rows that each occupy a single display row.
+    Rows(u32),
+    // A single buffer row that is broken into several display rows at the given columns.
+    Wrapped(Vec<u32>),
+    // Consecutive buffer rows that are hidden by folds.
+    Folded(u32),
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct ChunkSummary {
+    buffer_rows: u32,
+    display_rows: u32,
+}
+
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+struct BufferRow(u32);
+
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+struct DisplayRow(u32);
+
+impl DisplayPoint {
+    pub fn new(row: u32, column: u32) -> Self {
+        DisplayPoint { row, column }
+    }
+}
+
+impl DisplayMap {
+    pub fn new(buffer: &mut Buffer) -> Self {
+        let row_count = buffer.max_point().row + 1;
+        DisplayMap {
+            edits: buffer.subscribe_edits(),
+            chunks: Tree::from_item(Chunk::Rows(row_count)),
+            wrap_width: None,
+            invalid_rows: Vec::new(),
+            wrapper: None,
+            folds: Vec::new(),
+            folds_invalid: false,
+        }
+    }
+
+    /// Returns a future that wraps large batches of rows once it is spawned. If the future is
+    /// never polled, requests accumulate in its channel, so it should only be requested when it
+    /// can actually be spawned.
+    pub fn wrapper(&mut self) -> Wrapper {
+        let (requests_tx, requests_rx) = mpsc::unbounded();
+        let results = NotifyCell::new(None);
+        self.wrapper = Some(WrapperHandle {
+            requests: requests_tx,
+            results: results.observe(),
+            last_request: None,
+        });
+        Wrapper {
+            requests: requests_rx,
+            results,
+        }
+    }
+
+    /// Sets the maximum number of columns on a display row, or disables wrapping if `None`.
+    /// Rows wrapped at the previous width keep being displayed until they are rewrapped.
+    pub fn set_wrap_width(&mut self, wrap_width: Option<u32>) {
+        let wrap_width = wrap_width.map(|wrap_width| cmp::max(wrap_width, 1));
+        if wrap_width != self.wrap_width {
+            self.wrap_width = wrap_width;
+            let row_count = self.chunks.len::<BufferRow>().0;
+            if wrap_width.is_some() {
+                self.invalid_rows = vec![0..row_count];
+            } else {
+                self.chunks = Tree::from_item(Chunk::Rows(row_count));
+                self.invalid_rows.clear();
+                self.folds_invalid = true;
+            }
+        }
+    }
+
+    /// Folds the rows spanned by the given range. Ranges within a single row are ignored.
+    pub fn fold(&mut self, range: Range<Point>, buffer: &Buffer) {
+        if range.start.row < range.end.row {
+            let start = buffer.anchor_before_point(range.start).unwrap();
+            let end = buffer.anchor_before_point(range.end).unwrap();
+            self.folds.push(start..end);
+            self.folds_invalid = true;
+        }
+    }
+
+    /// Removes all folds spanning any of the given buffer rows.
+    pub fn unfold(&mut self, rows: Range<u32>, buffer: &Buffer) {
+        self.interpolate_edits();
+        let mut unfolded_rows = Vec::new();
+        self.folds.retain(|fold| {
+            let start = buffer.point_for_anchor(&fold.start).unwrap().row;
+            let end = buffer.point_for_anchor(&fold.end).unwrap().row;
+            if start < rows.end && end >= rows.start {
+                unfolded_rows.push(start + 1..end + 1);
+                false
+            } else {
+                true
+            }
+        });
+
+        for rows in unfolded_rows {
+            if rows.start < rows.end {
+                let new_chunks = Tree::from_item(Chunk::Rows(rows.end - rows.start));
+                self.splice(rows.clone(), new_chunks);
+                self.invalidate(rows.clone(), rows);
+            }
+        }
+    }
+
+    /// Returns the range of buffer rows hidden by the folds containing the given row, if any.
+    pub fn folded_rows(&self, row: u32) -> Option<Range<u32>> {
+        let mut cursor = self.chunks.cursor();
+        cursor.seek(&BufferRow(row), SeekBias::Right);
+        if let Some(&Chunk::Folded(count)) = cursor.item() {
+            let start_row = cursor.start::<BufferRow>().0;
+            Some(start_row..start_row + count)
+        } else {
+            None
+        }
+    }
+
+    pub fn sync(&mut self, buffer: &Buffer) {
+        self.interpolate_edits();
+        self.wrap_invalid_rows(buffer);
+        self.hide_folded_rows(buffer);
+    }
+
+    /// Applies the latest rows wrapped in the background if they are still valid, requesting
+    /// them again otherwise. Returns whether the map changed.
+    pub fn poll_wraps(&mut self, buffer: &Buffer) -> bool {
+        let mut latest_result = None;
+        if let Some(ref mut wrapper) = self.wrapper {
+            while let Ok(Async::Ready(Some(result))) = wrapper.results.poll() {
+                if result.is_some() {
+                    latest_result = result;
+                }
+            }
+        }
+
+        self.interpolate_edits();
+        let mut updated = false;
+        if let Some(result) = latest_result {
+            if result.version == buffer.version && Some(result.wrap_width) == self.wrap_width
+                && result.rows == self.invalid_rows
+            {
+                for (rows, chunks) in result.rows.iter().zip(result.chunks.iter()) {
+                    self.splice(rows.clone(), chunks.clone());
+                }
+                self.invalid_rows.clear();
+                updated = true;
+            }
+        }
+        self.wrap_invalid_rows(buffer);
+        self.hide_folded_rows(buffer);
+        updated
+    }
+
+    pub fn max_row(&self) -> u32 {
+        self.chunks.len::<DisplayRow>().0 - 1
+    }
+
+    /// Converts a point in the buffer to a point on the screen. Points in folded rows are
+    /// displayed at the end of the row preceding the fold.
+    pub fn to_display_point(&self, point: Point, buffer: &Buffer) -> DisplayPoint {
+        let mut cursor = self.chunks.cursor();
+        cursor.seek(&BufferRow(point.row), SeekBias::Right);
+        let start_row = cursor.start::<BufferRow>().0;
+        let start_display_row = cursor.start::<DisplayRow>().0;
+        match cursor.item() {
+            Some(&Chunk::Rows(_)) => {
+                DisplayPoint::new(start_display_row + point.row - start_row, point.column)
+            }
+            Some(&Chunk::Wrapped(ref wraps)) => {
+                let index = match wraps.binary_search(&point.column) {
+                    Ok(index) => index + 1,
+                    Err(index) => index,
+                };
+                let segment_start = if index == 0 { 0 } else { wraps[index - 1] };
+                DisplayPoint::new(
+                    start_display_row + index as u32,
+                    point.column - segment_start,
+                )
+            }
+            Some(&Chunk::Folded(_)) => {
+                let row = start_row - 1;
+                self.to_display_point(Point::new(row, buffer.len_for_row(row).unwrap()), buffer)
+            }
+            None => DisplayPoint::new(self.max_row(), point.column),
+        }
+    }
+
+    /// Converts a display point to the nearest valid point in the buffer, clipping columns that
+    /// extend past the end of the display row and rows that extend past the last display row.
+    pub fn to_buffer_point(&self, mut point: DisplayPoint, buffer: &Buffer) -> Point {
+        if point.row > self.max_row() {
+            point = DisplayPoint::new(self.max_row(), u32::max_value());
+        }
+
+        let mut cursor = self.chunks.cursor();
+        cursor.seek(&DisplayRow(point.row), SeekBias::Right);
+        let start_row = cursor.start::<BufferRow>().0;
+        let start_display_row = cursor.start::<DisplayRow>().0;
+        let (row, segment_start, max_column) = match cursor.item() {
+            Some(&Chunk::Wrapped(ref wraps)) => {
+                let index = (point.row - start_display_row) as usize;
+                let segment_start = if index == 0 { 0 } else { wraps[index - 1] };
+                (start_row, segment_start, wraps.get(index).map(|end| end - 1))
+            }
+            _ => (start_row + point.row - start_display_row, 0, None),
+        };
+
+        let mut column = segment_start.saturating_add(point.column);
+        if let Some(max_column) = max_column {
+            column = cmp::min(column, max_column);
+        }
+        Point::new(row, cmp::min(column, buffer.len_for_row(row).unwrap()))
+    }
+
+    /// Returns the segment of the buffer displayed on each of the given display rows that
+    /// exists.
+    pub fn segments(&self, rows: Range<u32>) -> Vec<Segment> {
+        let mut segments: Vec<Segment> = Vec::new();
+        let mut cursor = self.chunks.cursor();
+        cursor.seek(&DisplayRow(rows.start), SeekBias::Right);
+        let mut buffer_row = cursor.start::<BufferRow>().0;
+        let mut display_row = cursor.start::<DisplayRow>().0;
+        while let Some(chunk) = cursor.item() {
+            match chunk {
+                &Chunk::Folded(_) => {
+                    if let Some(segment) = segments.last_mut() {
+                        if segment.buffer_row + 1 == buffer_row && segment.end_column.is_none() {
+                            segment.folded = true;
+                        }
+                    }
+                }
+                _ if display_row >= rows.end => break,
+                &Chunk::Rows(count) => {
+                    let start = rows.start.saturating_sub(display_row);
+                    let end = cmp::min(count, rows.end - display_row);
+                    for i in start..end {
+                        segments.push(Segment {
+                            buffer_row: buffer_row + i,
+                            start_column: 0,
+                            end_column: None,
+                            folded: false,
+                        });
+                    }
+                }
+                &Chunk::Wrapped(ref wraps) => {
+                    let start = rows.start.saturating_sub(display_row) as usize;
+                    let end = cmp::min(wraps.len() + 1, (rows.end - display_row) as usize);
+                    for i in start..end {
+                        segments.push(Segment {
+                            buffer_row,
+                            start_column: if i == 0 { 0 } else { wraps[i - 1] },
+                            end_column: wraps.get(i).cloned(),
+                            folded: false,
+                        });
+                    }
+                }
+            }
+
+            let summary = chunk.summarize();
+            buffer_row += summary.buffer_rows;
+            display_row += summary.display_rows;
+            cursor.next();
+        }
+        segments
+    }
+
+    fn interpolate_edits(&mut self) {
+        for edit in self.edits.take() {
+            let old_rows = edit.old_range.start.row..edit.old_range.end.row + 1;
+            let new_rows = edit.new_range.start.row..edit.new_range.end.row + 1;
+            let new_chunks = Tree::from_item(Chunk::Rows(new_rows.end - new_rows.start));
+            self.splice(old_rows.clone(), new_chunks);
+            self.invalidate(old_rows, new_rows);
+        }
+    }
+
+    fn wrap_invalid_rows(&mut self, buffer: &Buffer) {
+        let wrap_width = if let Some(wrap_width) = self.wrap_width {
+            wrap_width
+        } else {
+            self.invalid_rows.clear();
+            return;
+        };
+
+        if self.invalid_rows.is_empty() {
+            return;
+        }
+
+        let invalid_row_count: u32 = self.invalid_rows
+            .iter()
+            .map(|rows| rows.end - rows.start)
+            .sum();
+        if invalid_row_count > MAX_FOREGROUND_WRAP_ROWS && self.request_wrap(buffer, wrap_width)
+        {
+            return;
+        }
+
+        let invalid_rows = mem::replace(&mut self.invalid_rows, Vec::new());
+        for rows in invalid_rows {
+            let chunks = wrap_rows(
+                buffer.iter_starting_at_row(rows.start),
+                rows.end - rows.start,
+                wrap_width,
+            );
+            self.splice(rows, chunks);
+        }
+    }
+
+    fn request_wrap(&mut self, buffer: &Buffer, wrap_width: u32) -> bool {
+        let sent = if let Some(ref mut wrapper) = self.wrapper {
+            let request = (buffer.version.clone(), wrap_width);
+            if wrapper.last_request.as_ref() == Some(&request) {
+                return true;
+            }
+
+            let sent = wrapper
+                .requests
+                .unbounded_send(WrapRequest {
+                    snapshot: buffer.snapshot(),
+                    wrap_width,
+                    rows: self.invalid_rows.clone(),
+                })
+                .is_ok();
+            if sent {
+                wrapper.last_request = Some(request);
+            }
+            sent
+        } else {
+            false
+        };
+
+        if !sent {
+            self.wrapper = None;
+        }
+        sent
+    }
+
+    // Hides the rows spanned by each fold except the first, dropping folds whose rows have been
+    // joined by edits. Overlapping and adjacent folds are hidden by a single chunk.
+    fn hide_folded_rows(&mut self, buffer: &Buffer) {
+        if !self.folds_invalid {
+            return;
+        }
+
+        let mut folded_rows = Vec::with_capacity(self.folds.len());
+        self.folds.retain(|fold| {
+            let start = buffer.point_for_anchor(&fold.start).unwrap().row;
+            let end = buffer.point_for_anchor(&fold.end).unwrap().row;
+            if start < end {
+                folded_rows.push(start + 1..end + 1);
+                true
+            } else {
+                false
+            }
+        });
+        folded_rows.sort_by_key(|rows| rows.start);
+
+        let mut merged_rows: Vec<Range<u32>> = Vec::with_capacity(folded_rows.len());
+        for rows in folded_rows {
+            if let Some(last_rows) = merged_rows.last_mut() {
+                if rows.start <= last_rows.end {
+                    last_rows.end = cmp::max(last_rows.end, rows.end);
+                    continue;
+                }
+            }
+            merged_rows.push(rows);
+        }
+
+        for rows in merged_rows {
+            if self.folded_rows(rows.start) != Some(rows.clone()) {
+                let new_chunks = Tree::from_item(Chunk::Folded(rows.end - rows.start));
+                self.splice(rows, new_chunks);
+            }
+        }
+        self.folds_invalid = false;
+    }
+
+    // Replaces the chunks covering the given buffer rows. Runs of unwrapped or folded rows that
+    // straddle the edges of the range are split, and folded rows are hidden again on the next
+    // sync.
+    fn splice(&mut self, old_rows: Range<u32>, new_chunks: Tree<Chunk>) {
+        let mut new_tree = {
+            let mut cursor = self.chunks.cursor();
+            let mut new_tree = cursor.build_prefix(&BufferRow(old_rows.start), SeekBias::Right);
+            let prefix_end = cursor.start::<BufferRow>().0;
+            if prefix_end < old_rows.start {
+                new_tree.push(Chunk::Rows(old_rows.start - prefix_end));
+            }
+            new_tree.push_tree(new_chunks);
+
+            cursor.seek(&BufferRow(old_rows.end), SeekBias::Right);
+            if let Some(chunk) = cursor.item() {
+                let chunk_start = cursor.start::<BufferRow>().0;
+                if chunk_start < old_rows.end {
+                    let chunk_end = chunk_start + chunk.summarize().buffer_rows;
+                    new_tree.push(Chunk::Rows(chunk_end - old_rows.end));
+                    cursor.next();
+                }
+            }
+            new_tree.push_tree(cursor.build_suffix());
+            new_tree
+        };
+        mem::swap(&mut self.chunks, &mut new_tree);
+        self.folds_invalid = true;
+    }
+
+    // Replaces the old rows with the new rows in the list of invalid rows, shifting any
+    // subsequent ranges and merging ranges that touch the edited rows.
+    fn invalidate(&mut self, old_rows: Range<u32>, new_rows: Range<u32>) {
+        let delta = i64::from(new_rows.end) - i64::from(old_rows.end);
+        let shift = |row: u32| (i64::from(row) + delta) as u32;
+
+        let mut invalid_rows = Vec::with_capacity(self.invalid_rows.len() + 1);
+        let mut merged_rows = new_rows;
+        for rows in self.invalid_rows.drain(..) {
+            if rows.end < old_rows.start {
+                invalid_rows.push(rows);
+            } else if rows.start > old_rows.end {
+                if merged_rows.end > 0 {
+                    invalid_rows.push(merged_rows.clone());
+                    merged_rows = 0..0;
+                }
+                invalid_rows.push(shift(rows.start)..shift(rows.end));
+            } else {
+                merged_rows.start = cmp::min(merged_rows.start, rows.start);
+                if rows.end > old_rows.end {
+                    merged_rows.end = cmp::max(merged_rows.end, shift(rows.end));
+                }
+            }
+        }
+        if merged_rows.end > 0 {
+            invalid_rows.push(merged_rows);
+        }
+        self.invalid_rows = invalid_rows;
+    }
+}
+
+impl Future for Wrapper {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut latest_request = None;
+        loop {
+            match self.requests.poll()? {
+                Async::Ready(Some(request)) => latest_request = Some(request),
+                Async::Ready(None) => return Ok(Async::Ready(())),
+                Async::NotReady => break,
+            }
+        }
+
+        if let Some(request) = latest_request {
+            let chunks = request
+                .rows
+                .iter()
+                .map(|rows| {
+                    wrap_rows(
+                        request.snapshot.iter_starting_at_row(rows.start),
+                        rows.end - rows.start,
+                        request.wrap_width,
+                    )
+                })
+                .collect();
+            self.results.set(Some(Arc::new(WrapResult {
+                version: request.snapshot.version().clone(),
+                wrap_width: request.wrap_width,
+                rows: request.rows,
+                chunks,
+            })));
+        }
+
+        Ok(Async::NotReady)
+    }
+}
+
+impl Item for Chunk {
+    type Summary = ChunkSummary;
+
+    fn summarize(&self) -> Self::Summary {
+        match self {
+            &Chunk::Rows(count) => ChunkSummary {
+                buffer_rows: count,
+                display_rows: count,
+            },
+            &Chunk::Wrapped(ref wraps) => ChunkSummary {
+                buffer_rows: 1,
+                display_rows: wraps.len() as u32 + 1,
+            },
+            &Chunk::Folded(count) => ChunkSummary {
+                buffer_rows: count,
+                display_rows: 0,
+            },
+        }
+    }
+}
+
+impl<'a> AddAssign<&'a ChunkSummary> for ChunkSummary {
+    fn add_assign(&mut self, other: &Self) {
+        self.buffer_rows += other.buffer_rows;
+        self.display_rows += other.display_rows;
+    }
+}
+
+impl tree::Dimension for BufferRow {
+    type Summary = ChunkSummary;
+
+    fn from_summary(summary: &Self::Summary) -> Self {
+        BufferRow(summary.buffer_rows)
+    }
+}
+
+impl<'a> Add<&'a Self> for BufferRow {
+    type Output = BufferRow;
+
+    fn add(self, other: &'a Self) -> Self::Output {
+        BufferRow(self.0 + other.0)
+    }
+}
+
+impl tree::Dimension for DisplayRow {
+    type Summary = ChunkSummary;
+
+    fn from_summary(summary: &Self::Summary) -> Self {
+        DisplayRow(summary.display_rows)
+    }
+}
+
+impl<'a> Add<&'a Self> for DisplayRow {
+    type Output = DisplayRow;
+
+    fn add(self, other: &'a Self) -> Self::Output {
+        DisplayRow(self.0 + other.0)
+    }
+}
+
+fn wrap_rows(mut chars: Iter, row_count: u32, wrap_width: u32) -> Tree<Chunk> {
+    let mut chunks = Tree::new();
+    let mut unwrapped_rows = 0;
+    let mut line = Vec::new();
+    for _ in 0..row_count {
+        line.clear();
+        for c in &mut chars {
+            if c == u16::from(b'\n') {
+                break;
+            }
+            line.push(c);
+        }
+
+        let wraps = wrap_line(&line, wrap_width);
+        if wraps.is_empty() {
+            unwrapped_rows += 1;
+        } else {
+            if unwrapped_rows > 0 {
+                chunks.push(Chunk::Rows(unwrapped_rows));
+                unwrapped_rows = 0;
+            }
+            chunks.push(Chunk::Wrapped(wraps));
+        }
+    }
+    if unwrapped_rows > 0 {
+        chunks.push(Chunk::Rows(unwrapped_rows));
+    }
+    chunks
+}
+
+// Breaks the line after the last whitespace that fits within the wrap width, or in the middle of
+// a word that is too long to fit on its own. Whitespace is allowed to overhang the wrap width.
+fn wrap_line(line: &[u16], wrap_width: u32) -> Vec<u32> {
+    let mut wraps = Vec::new();
+    let mut row_start = 0;
+    let mut boundary = 0;
+    for (column, c) in line.iter().enumerate() {
+        let column = column as u32;
+        if *c == u16::from(b' ') || *c == u16::from(b'\t') {
+            boundary = column + 1;
+        } else if column - row_start >= wrap_width {
+            row_start = if boundary > row_start {
+                boundary
+            } else {
+                column
+            };
+            wraps.push(row_start);
+        }
+    }
+    wraps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use std::time::Duration;
+
+    #[test]
+    fn test_wrap_line() {
+        assert_eq!(wrap("abc def ghi", 4), vec!["abc ", "def ", "ghi"]);
+        assert_eq!(wrap("abc def ghi", 7), vec!["abc def ", "ghi"]);
+        assert_eq!(wrap("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        assert_eq!(wrap("ab     cd", 4), vec!["ab     ", "cd"]);
+        assert_eq!(wrap("ab cdefghi", 4), vec!["ab ", "cdef", "ghi"]);
+        assert_eq!(wrap("abcd", 4), vec!["abcd"]);
+        assert_eq!(wrap("", 4), vec![""]);
+    }
+
+    #[test]
+    fn test_display_points() {
+        let mut buffer = Buffer::new(0);
+        buffer.edit(0..0, "abc def ghi\njk\nlmnopqrstu");
+        let mut map = DisplayMap::new(&mut buffer);
+        map.set_wrap_width(Some(4));
+        map.sync(&buffer);
+
+        assert_eq!(map.max_row(), 6);
+        assert_eq!(
+            render(&map, &buffer),
+            vec!["abc ", "def ", "ghi", "jk", "lmno", "pqrs", "tu"]
+        );
+        assert_eq!(
+            map.to_display_point(Point::new(0, 2), &buffer),
+            DisplayPoint::new(0, 2)
+        );
+        assert_eq!(
+            map.to_display_point(Point::new(0, 4), &buffer),
+            DisplayPoint::new(1, 0)
+        );
+        assert_eq!(
+            map.to_display_point(Point::new(0, 11), &buffer),
+            DisplayPoint::new(2, 3)
+        );
+        assert_eq!(
+            map.to_display_point(Point::new(1, 1), &buffer),
+            DisplayPoint::new(3, 1)
+        );
+        assert_eq!(
+            map.to_display_point(Point::new(2, 9), &buffer),
+            DisplayPoint::new(6, 1)
+        );
+
+        assert_eq!(
+            map.to_buffer_point(DisplayPoint::new(1, 2), &buffer),
+            Point::new(0, 6)
+        );
+        assert_eq!(
+            map.to_buffer_point(DisplayPoint::new(1, 10), &buffer),
+            Point::new(0, 7)
+        );
+        assert_eq!(
+            map.to_buffer_point(DisplayPoint::new(2, 10), &buffer),
+            Point::new(0, 11)
+        );
+        assert_eq!(
+            map.to_buffer_point(DisplayPoint::new(3, 10), &buffer),
+            Point::new(1, 2)
+        );
+        assert_eq!(
+            map.to_buffer_point(DisplayPoint::new(9, 0), &buffer),
+            Point::new(2, 10)
+        );
+
+        map.set_wrap_width(None);
+        map.sync(&buffer);
+        assert_eq!(map.max_row(), 2);
+        assert_eq!(
+            map.to_display_point(Point::new(2, 9), &buffer),
+            DisplayPoint::new(2, 9)
+        );
+    }
+
+    #[test]
+    fn test_edits() {
+        let mut buffer = Buffer::new(0);
+        buffer.set_group_interval(Duration::from_millis(0));
+        buffer.edit(0..0, "the quick brown fox\njumps over\nthe lazy dog");
+        let mut map = DisplayMap::new(&mut buffer);
+        map.set_wrap_width(Some(6));
+        map.sync(&buffer);
+        assert_eq!(
+            render(&map, &buffer),
+            vec!["the ", "quick ", "brown ", "fox", "jumps ", "over", "the ", "lazy ", "dog"]
+        );
+
+        let edits: Vec<(Range<usize>, &str)> = vec![
+            (4..10, ""),
+            (0..0, "a\nb\n"),
+            (20..20, "and a very long paragraph\n\n"),
+            (5..40, "x"),
+            (0..buffer.len(), "short"),
+        ];
+        for (range, text) in edits {
+            let end = cmp::min(range.end, buffer.len());
+            buffer.edit(range.start..end, text);
+            map.sync(&buffer);
+            assert_eq!(render(&map, &buffer), render(&wrapped(&mut buffer, 6), &buffer));
+        }
+
+        while buffer.undo().is_some() {
+            map.sync(&buffer);
+            assert_eq!(render(&map, &buffer), render(&wrapped(&mut buffer, 6), &buffer));
+        }
+    }
+
+    #[test]
+    fn test_background_wrapping() {
+        let mut buffer = Buffer::new(0);
+        buffer.edit(0..0, "abc def\nghi");
+        let mut map = DisplayMap::new(&mut buffer);
+        let mut wrapper = map.wrapper();
+        map.set_wrap_width(Some(4));
+        map.sync(&buffer);
+        assert_eq!(render(&map, &buffer), vec!["abc ", "def", "ghi"]);
+
+        // Large edits are displayed unwrapped until the wrapper catches up.
+        let pasted_text = "jkl mno\n".repeat(MAX_FOREGROUND_WRAP_ROWS as usize);
+        buffer.edit(8..8, pasted_text.as_str());
+        map.sync(&buffer);
+        assert_eq!(map.max_row(), MAX_FOREGROUND_WRAP_ROWS + 2);
+        assert_eq!(
+            map.segments(1..3),
+            vec![
+                Segment {
+                    buffer_row: 0,
+                    start_column: 4,
+                    end_column: None,
+                    folded: false,
+                },
+                Segment {
+                    buffer_row: 1,
+                    start_column: 0,
+                    end_column: None,
+                    folded: false,
+                },
+            ]
+        );
+
+        future::lazy(|| {
+            wrapper.poll().unwrap();
+            assert!(map.poll_wraps(&buffer));
+            Ok::<_, ()>(())
+        }).wait()
+            .unwrap();
+        assert_eq!(map.max_row(), 2 * MAX_FOREGROUND_WRAP_ROWS + 2);
+        assert_eq!(render(&map, &buffer), render(&wrapped(&mut buffer, 4), &buffer));
+
+        // Results computed for a stale version of the buffer are discarded.
+        buffer.edit(0..0, pasted_text.as_str());
+        map.sync(&buffer);
+        buffer.edit(0..0, pasted_text.as_str());
+        future::lazy(|| {
+            wrapper.poll().unwrap();
+            assert!(!map.poll_wraps(&buffer));
+            wrapper.poll().unwrap();
+            assert!(map.poll_wraps(&buffer));
+            Ok::<_, ()>(())
+        }).wait()
+            .unwrap();
+        assert_eq!(render(&map, &buffer), render(&wrapped(&mut buffer, 4), &buffer));
+
+        // If the wrapper goes away, rows are wrapped on the foreground.
+        drop(wrapper);
+        buffer.edit(0..0, pasted_text.as_str());
+        map.sync(&buffer);
+        assert_eq!(render(&map, &buffer), render(&wrapped(&mut buffer, 4), &buffer));
+    }
+
+    #[test]
+    fn test_folds() {
+        let mut buffer = Buffer::new(0);
+        buffer.set_group_interval(Duration::from_millis(0));
+        buffer.edit(0..0, "fn a() {\n    b();\n    c();\n}\nfn d() {\n    e();\n}");
+        let mut map = DisplayMap::new(&mut buffer);
+        map.fold(Point::new(0, 8)..Point::new(2, 8), &buffer);
+        map.fold(Point::new(4, 8)..Point::new(5, 8), &buffer);
+        map.sync(&buffer);
+        assert_eq!(render(&map, &buffer), vec!["fn a() {⋯", "}", "fn d() {⋯", "}"]);
+        assert_eq!(map.max_row(), 3);
+        assert_eq!(map.folded_rows(0), None);
+        assert_eq!(map.folded_rows(2), Some(1..3));
+        assert_eq!(
+            map.to_display_point(Point::new(2, 3), &buffer),
+            DisplayPoint::new(0, 8)
+        );
+        assert_eq!(
+            map.to_display_point(Point::new(4, 1), &buffer),
+            DisplayPoint::new(2, 1)
+        );
+        assert_eq!(
+            map.to_buffer_point(DisplayPoint::new(1, 0), &buffer),
+            Point::new(3, 0)
+        );
+        assert_eq!(
+            map.to_buffer_point(DisplayPoint::new(10, 0), &buffer),
+            Point::new(6, 1)
+        );
+
+        // Folds are wrapped along with the rest of the rows and survive edits inside them.
+        map.set_wrap_width(Some(4));
+        buffer.edit(17..17, "\n    x();");
+        map.sync(&buffer);
+        assert_eq!(
+            render(&map, &buffer),
+            vec!["fn ", "a() ", "{⋯", "}", "fn ", "d() ", "{⋯", "}"]
+        );
+        assert_eq!(map.folded_rows(1), Some(1..4));
+
+        // Folds are dropped once their rows are joined.
+        buffer.edit(8..35, "");
+        map.sync(&buffer);
+        assert_eq!(buffer.to_string(), "fn a() {\n}\nfn d() {\n    e();\n}");
+        assert_eq!(
+            render(&map, &buffer),
+            vec!["fn ", "a() ", "{", "}", "fn ", "d() ", "{⋯", "}"]
+        );
+
+        map.unfold(2..3, &buffer);
+        map.sync(&buffer);
+        assert_eq!(render(&map, &buffer), render(&wrapped(&mut buffer, 4), &buffer));
+        map.set_wrap_width(None);
+        map.sync(&buffer);
+        assert_eq!(map.max_row(), 4);
+    }
+
+    fn wrap(line: &str, wrap_width: u32) -> Vec<String> {
+        let line = line.encode_utf16().collect::<Vec<_>>();
+        let mut segments = Vec::new();
+        let mut start = 0;
+        for wrap in wrap_line(&line, wrap_width) {
+            segments.push(String::from_utf16_lossy(&line[start..wrap as usize]));
+            start = wrap as usize;
+        }
+        segments.push(String::from_utf16_lossy(&line[start..]));
+        segments
+    }
+
+    fn wrapped(buffer: &mut Buffer, wrap_width: u32) -> DisplayMap {
+        let mut map = DisplayMap::new(buffer);
+        map.set_wrap_width(Some(wrap_width));
+        map.sync(buffer);
+        map
+    }
+
+    fn render(map: &DisplayMap, buffer: &Buffer) -> Vec<String> {
+        let lines = buffer
+            .to_string()
+            .split('\n')
+            .map(|line| line.encode_utf16().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        map.segments(0..map.max_row() + 1)
+            .into_iter()
+            .map(|segment| {
+                let line = &lines[segment.buffer_row as usize];
+                let end = segment
+                    .end_column
+                    .map_or(line.len(), |end_column| end_column as usize);
+                let mut line = String::from_utf16_lossy(&line[segment.start_column as usize..end]);
+                if segment.folded {
+                    line.push_str("⋯");
+                }
+                line
+            })
+            .collect()
+    }
+}
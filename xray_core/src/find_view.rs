@@ -0,0 +1,210 @@
+use buffer_view::BufferView;
+use futures::{Async, Poll, Stream};
+use notify_cell::{NotifyCell, NotifyCellObserver};
+use project::TextSearchOptions;
+use serde_json;
+use window::{View, WeakViewHandle, Window};
+
+pub trait FindViewDelegate {
+    fn did_close(&mut self);
+}
+
+pub struct FindView<T: FindViewDelegate> {
+    delegate: WeakViewHandle<T>,
+    buffer_view: WeakViewHandle<BufferView>,
+    query: String,
+    replacement: String,
+    regex: bool,
+    case_sensitive: bool,
+    match_count: usize,
+    match_counts: Option<NotifyCellObserver<usize>>,
+    error: Option<String>,
+    updates: NotifyCell<()>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum FindViewAction {
+    UpdateQuery { query: String },
+    UpdateReplacement { replacement: String },
+    UpdateRegex { regex: bool },
+    UpdateCaseSensitive { case_sensitive: bool },
+    SelectNext,
+    SelectPrevious,
+    Replace,
+    ReplaceAll,
+    Close,
+}
+
+impl<T: FindViewDelegate> View for FindView<T> {
+    fn component_name(&self) -> &'static str {
+        "FindView"
+    }
+
+    fn render(&self) -> serde_json::Value {
+        json!({
+            "query": self.query.as_str(),
+            "replacement": self.replacement.as_str(),
+            "regex": self.regex,
+            "case_sensitive": self.case_sensitive,
+            "match_count": self.match_count,
+            "error": self.error,
+        })
+    }
+
+    fn dispatch_action(&mut self, action: serde_json::Value, _: &mut Window) {
+        match serde_json::from_value(action) {
+            Ok(FindViewAction::UpdateQuery { query }) => self.update_query(query),
+            Ok(FindViewAction::UpdateReplacement { replacement }) => {
+                self.update_replacement(replacement)
+            }
+            Ok(FindViewAction::UpdateRegex { regex }) => self.update_regex(regex),
+            Ok(FindViewAction::UpdateCaseSensitive { case_sensitive }) => {
+                self.update_case_sensitive(case_sensitive)
+            }
+            Ok(FindViewAction::SelectNext) => self.select_next(),
+            Ok(FindViewAction::SelectPrevious) => self.select_previous(),
+            Ok(FindViewAction::Replace) => self.replace(),
+            Ok(FindViewAction::ReplaceAll) => self.replace_all(),
+            Ok(FindViewAction::Close) => self.close(),
+            _ => eprintln!("Unrecognized action"),
+        }
+    }
+}
+
+impl<T: FindViewDelegate> Stream for FindView<T> {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        // The match count changes whenever the buffer is edited, not just when searching.
+        let mut match_count_changed = false;
+        if let Some(ref mut match_counts) = self.match_counts {
+            while let Async::Ready(Some(match_count)) = match_counts.poll()? {
+                match_count_changed |= self.match_count != match_count;
+                self.match_count = match_count;
+            }
+        }
+
+        match self.updates.poll()? {
+            Async::NotReady if match_count_changed => Ok(Async::Ready(Some(()))),
+            poll => Ok(poll),
+        }
+    }
+}
+
+impl<T: FindViewDelegate> FindView<T> {
+    pub fn new(buffer_view: WeakViewHandle<BufferView>, delegate: WeakViewHandle<T>) -> Self {
+        let match_counts = buffer_view.map(|buffer_view| buffer_view.search_match_counts());
+        Self {
+            delegate,
+            buffer_view,
+            query: String::new(),
+            replacement: String::new(),
+            regex: false,
+            case_sensitive: false,
+            match_count: 0,
+            match_counts,
+            error: None,
+            updates: NotifyCell::new(()),
+        }
+    }
+
+    fn update_query(&mut self, query: String) {
+        if self.query != query {
+            self.query = query;
+            self.search();
+            self.select_next();
+        }
+    }
+
+    fn update_replacement(&mut self, replacement: String) {
+        if self.replacement != replacement {
+            self.replacement = replacement;
+            self.updates.set(());
+        }
+    }
+
+    fn update_regex(&mut self, regex: bool) {
+        if self.regex != regex {
+            self.regex = regex;
+            self.search();
+        }
+    }
+
+    fn update_case_sensitive(&mut self, case_sensitive: bool) {
+        if self.case_sensitive != case_sensitive {
+            self.case_sensitive = case_sensitive;
+            self.search();
+        }
+    }
+
+    fn select_next(&mut self) {
+        self.buffer_view
+            .map(|buffer_view| buffer_view.select_next_search_match());
+    }
+
+    fn select_previous(&mut self) {
+        self.buffer_view
+            .map(|buffer_view| buffer_view.select_previous_search_match());
+    }
+
+    fn replace(&mut self) {
+        let replacement = self.replacement.as_str();
+        let match_count = self.buffer_view.map(|buffer_view| {
+            buffer_view.replace_search_match(replacement);
+            buffer_view.search_match_count()
+        });
+        self.set_match_count(match_count.unwrap_or(0));
+    }
+
+    fn replace_all(&mut self) {
+        let replacement = self.replacement.as_str();
+        let match_count = self.buffer_view.map(|buffer_view| {
+            buffer_view.replace_all_search_matches(replacement);
+            buffer_view.search_match_count()
+        });
+        self.set_match_count(match_count.unwrap_or(0));
+    }
+
+    fn close(&mut self) {
+        self.delegate.map(|delegate| delegate.did_close());
+    }
+
+    fn search(&mut self) {
+        let query = self.query.as_str();
+        let options = TextSearchOptions {
+            regex: self.regex,
+            case_sensitive: self.case_sensitive,
+            ..TextSearchOptions::default()
+        };
+        let result = self.buffer_view
+            .map(|buffer_view| buffer_view.search(query, options))
+            .unwrap_or(Ok(0));
+        match result {
+            Ok(match_count) => {
+                self.match_count = match_count;
+                self.error = None;
+            }
+            Err(error) => {
+                self.match_count = 0;
+                self.error = Some(error);
+            }
+        }
+        self.updates.set(());
+    }
+
+    fn set_match_count(&mut self, match_count: usize) {
+        if self.match_count != match_count {
+            self.match_count = match_count;
+            self.updates.set(());
+        }
+    }
+}
+
+impl<T: FindViewDelegate> Drop for FindView<T> {
+    fn drop(&mut self) {
+        self.buffer_view
+            .map(|buffer_view| buffer_view.clear_search());
+    }
+}
@@ -7,6 +7,8 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 #[cfg(test)]
 use serde_json;
 use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::io;
 use std::iter::Iterator;
 use std::rc::Rc;
@@ -24,6 +26,9 @@ pub trait Tree {
 pub trait LocalTree: Tree {
     fn path(&self) -> &cross_platform::Path;
     fn populated(&self) -> Box<Future<Item = (), Error = ()>>;
+    /// Yields whenever the contents of the file at the given path change on disk.
+    fn file_updates(&self, relative_path: &cross_platform::Path)
+        -> Box<Stream<Item = (), Error = ()>>;
     fn as_tree(&self) -> &Tree;
 }
 
@@ -35,6 +40,7 @@ pub trait FileProvider {
 pub trait File {
     fn id(&self) -> FileId;
     fn read(&self) -> Box<Future<Item = String, Error = io::Error>>;
+    fn write(&self, text: Vec<u16>) -> Box<Future<Item = (), Error = io::Error>>;
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -69,6 +75,28 @@ pub struct FileInner {
 pub struct TreeService {
     tree: Rc<LocalTree>,
     populated: Option<Box<Future<Item = (), Error = ()>>>,
+    updates: Box<Stream<Item = (), Error = ()>>,
+    snapshot: DirSnapshot,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum TreeOperation {
+    Insert {
+        parent_path: Vec<cross_platform::PathComponent>,
+        entry: Entry,
+    },
+    Remove {
+        parent_path: Vec<cross_platform::PathComponent>,
+        name: cross_platform::PathComponent,
+    },
+}
+
+// The children of each directory as of the last update we sent to the client. Because children
+// are copied on write, a directory whose children are no longer pointer-equal to its snapshot has
+// changed since then.
+struct DirSnapshot {
+    children: Arc<Vec<Entry>>,
+    child_dirs: HashMap<EntryId, DirSnapshot>,
 }
 
 pub struct RemoteTree(Rc<RefCell<RemoteTreeState>>);
@@ -177,6 +205,32 @@ impl Entry {
             &Entry::File(_) => Err(()),
         }
     }
+
+    pub fn child(&self, name: &cross_platform::PathComponent) -> Option<Entry> {
+        match self {
+            &Entry::Dir(ref inner) => {
+                let children = inner.children.read();
+                children
+                    .binary_search_by(|child| child.name().cmp(name))
+                    .ok()
+                    .map(|index| children[index].clone())
+            }
+            &Entry::File(_) => None,
+        }
+    }
+
+    pub fn remove(&self, name: &cross_platform::PathComponent) -> Result<Entry, ()> {
+        match self {
+            &Entry::Dir(ref inner) => {
+                let mut children = inner.children.write();
+                let index = children
+                    .binary_search_by(|child| child.name().cmp(name))
+                    .map_err(|_| ())?;
+                Ok(Arc::make_mut(&mut children).remove(index))
+            }
+            &Entry::File(_) => Err(()),
+        }
+    }
 }
 
 fn serialize_dir<S: Serializer>(dir: &Arc<DirInner>, serializer: S) -> Result<S::Ok, S::Error> {
@@ -221,35 +275,141 @@ fn deserialize_dir_children<'de, D: Deserializer<'de>>(
 impl TreeService {
     pub fn new(tree: Rc<LocalTree>) -> Self {
         let populated = Some(tree.populated());
-        Self { tree, populated }
+        let updates = tree.updates();
+        let snapshot = DirSnapshot::new(&tree.root());
+        Self {
+            tree,
+            populated,
+            updates,
+            snapshot,
+        }
+    }
+
+    fn poll_populated(&mut self) -> bool {
+        if let Some(populated) = self.populated.as_mut().map(|p| p.poll().unwrap()) {
+            if let Async::Ready(_) = populated {
+                self.populated.take();
+            } else {
+                return false;
+            }
+        }
+        true
     }
 }
 
 impl server::Service for TreeService {
     type State = Entry;
-    type Update = Entry;
+    type Update = Vec<TreeOperation>;
     type Request = ();
     type Response = ();
 
-    fn init(&mut self, connection: &server::Connection) -> Self::State {
-        if let Async::Ready(Some(tree)) = self.poll_update(connection) {
-            tree
+    fn init(&mut self, _: &server::Connection) -> Self::State {
+        let root = self.tree.root();
+        if self.poll_populated() {
+            // Take the snapshot before serializing the root. Any entries that change in between
+            // will be sent again in the next update, which the client applies idempotently.
+            self.snapshot = DirSnapshot::new(&root);
+            root
         } else {
-            let root = self.tree.root();
-            Entry::dir(root.name().to_owned(), root.is_symlink(), root.is_ignored())
+            let empty_root =
+                Entry::dir(root.name().to_owned(), root.is_symlink(), root.is_ignored());
+            self.snapshot = DirSnapshot::new(&empty_root);
+            empty_root
         }
     }
 
     fn poll_update(&mut self, _: &server::Connection) -> Async<Option<Self::Update>> {
-        if let Some(populated) = self.populated.as_mut().map(|p| p.poll().unwrap()) {
-            if let Async::Ready(_) = populated {
-                self.populated.take();
-                Async::Ready(Some(self.tree.root().clone()))
-            } else {
-                Async::NotReady
+        if !self.poll_populated() {
+            return Async::NotReady;
+        }
+
+        loop {
+            match self.updates.poll() {
+                Ok(Async::Ready(Some(()))) => {}
+                Ok(Async::Ready(None)) | Err(_) => return Async::Ready(None),
+                Ok(Async::NotReady) => break,
             }
-        } else {
+        }
+
+        let mut operations = Vec::new();
+        self.snapshot.update(&self.tree.root(), &mut Vec::new(), &mut operations);
+        if operations.is_empty() {
             Async::NotReady
+        } else {
+            Async::Ready(Some(operations))
+        }
+    }
+}
+
+impl DirSnapshot {
+    fn new(dir: &Entry) -> Self {
+        let children = dir.children().unwrap();
+        let child_dirs = children
+            .iter()
+            .filter(|child| child.is_dir())
+            .map(|child| (child.id(), DirSnapshot::new(child)))
+            .collect();
+        DirSnapshot {
+            children,
+            child_dirs,
+        }
+    }
+
+    fn update(
+        &mut self,
+        dir: &Entry,
+        path: &mut Vec<cross_platform::PathComponent>,
+        operations: &mut Vec<TreeOperation>,
+    ) {
+        let children = dir.children().unwrap();
+        if !Arc::ptr_eq(&children, &self.children) {
+            let mut old_children = self.children.iter().peekable();
+            let mut new_children = children.iter().peekable();
+            loop {
+                let ordering = match (old_children.peek(), new_children.peek()) {
+                    (Some(old_child), Some(new_child)) => {
+                        if old_child.id() == new_child.id() {
+                            old_children.next();
+                            new_children.next();
+                            continue;
+                        }
+                        old_child.name().cmp(new_child.name())
+                    }
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => break,
+                };
+
+                // When an entry has been replaced by another with the same name, we remove the
+                // old entry and insert the new one.
+                if ordering != Ordering::Greater {
+                    let old_child = old_children.next().unwrap();
+                    self.child_dirs.remove(&old_child.id());
+                    operations.push(TreeOperation::Remove {
+                        parent_path: path.clone(),
+                        name: old_child.name().clone(),
+                    });
+                }
+                if ordering != Ordering::Less {
+                    let new_child = new_children.next().unwrap();
+                    if new_child.is_dir() {
+                        self.child_dirs.insert(new_child.id(), DirSnapshot::new(new_child));
+                    }
+                    operations.push(TreeOperation::Insert {
+                        parent_path: path.clone(),
+                        entry: new_child.clone(),
+                    });
+                }
+            }
+            self.children = children.clone();
+        }
+
+        for child in children.iter() {
+            if let Some(child_snapshot) = self.child_dirs.get_mut(&child.id()) {
+                path.push(child.name().clone());
+                child_snapshot.update(child, path, operations);
+                path.pop();
+            }
         }
     }
 }
@@ -265,9 +425,11 @@ impl RemoteTree {
 
         let state_clone = state.clone();
         foreground
-            .execute(Box::new(updates.for_each(move |root| {
-                let mut state = state_clone.borrow_mut();
-                state.root = root;
+            .execute(Box::new(updates.for_each(move |operations| {
+                let state = state_clone.borrow();
+                for operation in operations {
+                    state.apply_operation(operation);
+                }
                 state.updates.set(());
                 Ok(())
             })))
@@ -277,6 +439,38 @@ impl RemoteTree {
     }
 }
 
+impl RemoteTreeState {
+    fn apply_operation(&self, operation: TreeOperation) {
+        match operation {
+            TreeOperation::Insert { parent_path, entry } => {
+                if let Some(parent) = self.dir_for_path(&parent_path) {
+                    // We may already have this entry if it changed while our initial state was
+                    // being serialized, in which case the update replaces it.
+                    let _ = parent.remove(entry.name());
+                    parent.insert(entry).unwrap();
+                }
+            }
+            TreeOperation::Remove { parent_path, name } => {
+                if let Some(parent) = self.dir_for_path(&parent_path) {
+                    let _ = parent.remove(&name);
+                }
+            }
+        }
+    }
+
+    fn dir_for_path(&self, path: &[cross_platform::PathComponent]) -> Option<Entry> {
+        let mut dir = self.root.clone();
+        for name in path {
+            dir = dir.child(name)?;
+        }
+        if dir.is_dir() {
+            Some(dir)
+        } else {
+            None
+        }
+    }
+}
+
 impl Tree for RemoteTree {
     fn root(&self) -> Entry {
         self.0.borrow().root.clone()
@@ -324,6 +518,27 @@ pub(crate) mod tests {
         assert_eq!(root.child_names(), vec!["a", "b", "c"]);
     }
 
+    #[test]
+    fn test_remove() {
+        let root = Entry::dir(PathComponent::from("root"), false, false);
+        root.insert(Entry::file(PathComponent::from("a"), false, false))
+            .unwrap();
+        root.insert(Entry::dir(PathComponent::from("b"), false, false))
+            .unwrap();
+        root.insert(Entry::file(PathComponent::from("c"), false, false))
+            .unwrap();
+
+        let removed = root.remove(&PathComponent::from("b")).unwrap();
+        assert!(removed.is_dir());
+        assert_eq!(root.remove(&PathComponent::from("b")), Err(()));
+        assert_eq!(root.child(&PathComponent::from("b")), None);
+        assert_eq!(
+            root.child(&PathComponent::from("c")).map(|child| child.is_dir()),
+            Some(false)
+        );
+        assert_eq!(root.child_names(), vec!["a", "c"]);
+    }
+
     #[test]
     fn test_serialize_deserialize() {
         let root = Entry::from_json(
@@ -375,12 +590,37 @@ pub(crate) mod tests {
         local_tree.populated.set(true);
         remote_tree_updates.wait_next(&mut reactor);
         assert_eq!(remote_tree.root(), local_tree.root());
+
+        let child_1 = local_tree.root().child(&PathComponent::from("child-1")).unwrap();
+        child_1
+            .insert(Entry::file(PathComponent::from("subchild-2"), false, false))
+            .unwrap();
+        local_tree.root().remove(&PathComponent::from("child-2")).unwrap();
+        local_tree
+            .root()
+            .insert(Entry::from_json("child-3", &json!({"subchild-3": null})))
+            .unwrap();
+        local_tree.updates.set(());
+        remote_tree_updates.wait_next(&mut reactor);
+        assert_eq!(remote_tree.root(), local_tree.root());
+
+        // Replacing an entry with another of the same name removes the old one on the remote.
+        local_tree.root().remove(&PathComponent::from("child-1")).unwrap();
+        local_tree
+            .root()
+            .insert(Entry::file(PathComponent::from("child-1"), false, false))
+            .unwrap();
+        local_tree.updates.set(());
+        remote_tree_updates.wait_next(&mut reactor);
+        assert_eq!(remote_tree.root(), local_tree.root());
     }
 
     pub struct TestTree {
         path: cross_platform::Path,
         root: Entry,
         pub populated: NotifyCell<bool>,
+        pub updates: NotifyCell<()>,
+        pub file_updates: Rc<RefCell<HashMap<PathBuf, NotifyCell<()>>>>,
     }
 
     pub struct TestFileProvider(Rc<RefCell<TestFileProviderState>>);
@@ -406,6 +646,8 @@ pub(crate) mod tests {
                 path: cross_platform::Path::from(path.into()),
                 root,
                 populated: NotifyCell::new(false),
+                updates: NotifyCell::new(()),
+                file_updates: Rc::new(RefCell::new(HashMap::new())),
             }
         }
 
@@ -422,7 +664,7 @@ pub(crate) mod tests {
         }
 
         fn updates(&self) -> Box<Stream<Item = (), Error = ()>> {
-            unimplemented!()
+            Box::new(self.updates.observe())
         }
     }
 
@@ -445,6 +687,19 @@ pub(crate) mod tests {
             }
         }
 
+        fn file_updates(
+            &self,
+            relative_path: &cross_platform::Path,
+        ) -> Box<Stream<Item = (), Error = ()>> {
+            Box::new(
+                self.file_updates
+                    .borrow_mut()
+                    .entry(relative_path.to_path_buf())
+                    .or_insert_with(|| NotifyCell::new(()))
+                    .observe(),
+            )
+        }
+
         fn as_tree(&self) -> &Tree {
             self
         }
@@ -498,6 +753,11 @@ pub(crate) mod tests {
         pub fn write_sync<S: Into<String>>(&self, path: cross_platform::Path, content: S) {
             let mut state = self.0.borrow_mut();
 
+            if let Some(file) = state.files.get(&path.to_path_buf()) {
+                file.0.borrow_mut().content = content.into();
+                return;
+            }
+
             let file_id = state.next_file_id;
             state.next_file_id += 1;
 
@@ -509,6 +769,13 @@ pub(crate) mod tests {
                 }))),
             );
         }
+
+        pub fn read_sync(&self, path: cross_platform::Path) -> String {
+            let state = self.0.borrow();
+            let file = state.files.get(&path.to_path_buf()).unwrap();
+            let content = file.0.borrow().content.clone();
+            content
+        }
     }
 
     impl FileProvider for TestFileProvider {
@@ -542,6 +809,14 @@ pub(crate) mod tests {
                 future::ok(file.content.clone())
             }))
         }
+
+        fn write(&self, text: Vec<u16>) -> Box<Future<Item = (), Error = io::Error>> {
+            let file = self.0.clone();
+            Box::new(NextTick::new().then(move |_| {
+                file.borrow_mut().content = String::from_utf16_lossy(&text);
+                future::ok(())
+            }))
+        }
     }
 
     impl NextTick {
@@ -7,6 +7,7 @@ extern crate bytes;
 extern crate lazy_static;
 extern crate futures;
 extern crate parking_lot;
+extern crate regex;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
@@ -30,18 +31,25 @@ pub mod buffer_view;
 pub mod cross_platform;
 pub mod fs;
 pub mod notify_cell;
+pub mod project;
 pub mod rpc;
+pub mod syntax;
 pub mod window;
 pub mod workspace;
 
+mod clipboard;
+mod diff;
+mod display_map;
 mod discussion;
 mod file_finder;
+mod find_view;
 mod fuzzy;
 mod movement;
 mod never;
-mod project;
+mod presence;
 #[cfg(test)]
 mod stream_ext;
+mod time;
 mod tree;
 
 pub use app::{App, WindowId};
@@ -1,48 +1,288 @@
-use std::cmp;
 use buffer::{Buffer, Point};
+use display_map::DisplayMap;
+use std::cmp;
+use std::ops::Range;
+
+const BRACKETS: &[(u8, u8)] = &[(b'(', b')'), (b'[', b']'), (b'{', b'}')];
+const MAX_BRACKET_SCAN_ROWS: u32 = 1000;
 
-pub fn left(buffer: &Buffer, mut point: Point) -> Point {
+pub fn left(buffer: &Buffer, display_map: &DisplayMap, mut point: Point) -> Point {
     if point.column > 0 {
         point.column -= 1;
     } else if point.row > 0 {
         point.row -= 1;
+        if let Some(folded_rows) = display_map.folded_rows(point.row) {
+            point.row = folded_rows.start - 1;
+        }
         point.column = buffer.len_for_row(point.row).unwrap();
     }
     point
 }
 
-pub fn right(buffer: &Buffer, mut point: Point) -> Point {
+pub fn right(buffer: &Buffer, display_map: &DisplayMap, mut point: Point) -> Point {
     let max_column = buffer.len_for_row(point.row).unwrap();
     if point.column < max_column {
         point.column += 1;
-    } else if point.row < buffer.max_point().row {
-        point.row += 1;
-        point.column = 0;
+    } else {
+        let mut next_row = point.row + 1;
+        if let Some(folded_rows) = display_map.folded_rows(next_row) {
+            next_row = folded_rows.end;
+        }
+        if next_row <= buffer.max_point().row {
+            point = Point::new(next_row, 0);
+        }
     }
     point
 }
 
-pub fn up(buffer: &Buffer, mut point: Point, goal_column: Option<u32>) -> (Point, Option<u32>) {
-    let goal_column = goal_column.or(Some(point.column));
-    if point.row > 0 {
-        point.row -= 1;
-        point.column = cmp::min(goal_column.unwrap(), buffer.len_for_row(point.row).unwrap());
+pub fn up(
+    buffer: &Buffer,
+    display_map: &DisplayMap,
+    point: Point,
+    goal_column: Option<u32>,
+    row_count: u32,
+) -> (Point, Option<u32>) {
+    let mut display_point = display_map.to_display_point(point, buffer);
+    let goal_column = goal_column.or(Some(display_point.column));
+    if display_point.row > 0 {
+        display_point.row = display_point.row.saturating_sub(row_count);
+        display_point.column = goal_column.unwrap();
+        (display_map.to_buffer_point(display_point, buffer), goal_column)
+    } else {
+        (Point::new(0, 0), goal_column)
+    }
+}
+
+pub fn down(
+    buffer: &Buffer,
+    display_map: &DisplayMap,
+    point: Point,
+    goal_column: Option<u32>,
+    row_count: u32,
+) -> (Point, Option<u32>) {
+    let mut display_point = display_map.to_display_point(point, buffer);
+    let goal_column = goal_column.or(Some(display_point.column));
+    let max_row = display_map.max_row();
+    if display_point.row < max_row {
+        display_point.row = cmp::min(display_point.row.saturating_add(row_count), max_row);
+        display_point.column = goal_column.unwrap();
+        (display_map.to_buffer_point(display_point, buffer), goal_column)
     } else {
-        point = Point::new(0, 0);
+        display_point.column = u32::max_value();
+        (display_map.to_buffer_point(display_point, buffer), goal_column)
+    }
+}
+
+pub fn previous_word_start(buffer: &Buffer, point: Point) -> Point {
+    let mut row = point.row;
+    let mut line = row_chars(buffer, row);
+    let mut column = point.column as usize;
+    loop {
+        while column > 0 && char_kind(line[column - 1]) == CharKind::Whitespace {
+            column -= 1;
+        }
+        if column > 0 || row == 0 {
+            break;
+        }
+        row -= 1;
+        line = row_chars(buffer, row);
+        column = line.len();
     }
 
-    (point, goal_column)
+    if column > 0 {
+        let kind = char_kind(line[column - 1]);
+        while column > 0 && char_kind(line[column - 1]) == kind {
+            column -= 1;
+        }
+    }
+    Point::new(row, column as u32)
+}
+
+pub fn next_word_end(buffer: &Buffer, point: Point) -> Point {
+    let max_row = buffer.max_point().row;
+    let mut row = point.row;
+    let mut line = row_chars(buffer, row);
+    let mut column = point.column as usize;
+    loop {
+        while column < line.len() && char_kind(line[column]) == CharKind::Whitespace {
+            column += 1;
+        }
+        if column < line.len() || row == max_row {
+            break;
+        }
+        row += 1;
+        line = row_chars(buffer, row);
+        column = 0;
+    }
+
+    if column < line.len() {
+        let kind = char_kind(line[column]);
+        while column < line.len() && char_kind(line[column]) == kind {
+            column += 1;
+        }
+    }
+    Point::new(row, column as u32)
 }
 
-pub fn down(buffer: &Buffer, mut point: Point, goal_column: Option<u32>) -> (Point, Option<u32>) {
-    let goal_column = goal_column.or(Some(point.column));
-    let max_point = buffer.max_point();
-    if point.row < max_point.row {
-        point.row += 1;
-        point.column = cmp::min(goal_column.unwrap(), buffer.len_for_row(point.row).unwrap())
+/// Moves to the first non-whitespace character of the row, or to the start of the row if the
+/// point is already there.
+pub fn beginning_of_line(buffer: &Buffer, point: Point) -> Point {
+    let indentation = row_chars(buffer, point.row)
+        .iter()
+        .take_while(|c| char_kind(**c) == CharKind::Whitespace)
+        .count() as u32;
+    if point.column == indentation {
+        Point::new(point.row, 0)
     } else {
-        point = max_point;
+        Point::new(point.row, indentation)
+    }
+}
+
+pub fn end_of_line(buffer: &Buffer, point: Point) -> Point {
+    Point::new(point.row, buffer.len_for_row(point.row).unwrap())
+}
+
+/// Returns the range of the word containing or adjacent to the point. The range is empty if the
+/// point isn't touching a word.
+pub fn word_range(buffer: &Buffer, point: Point) -> Range<Point> {
+    let line = row_chars(buffer, point.row);
+    let mut start = point.column as usize;
+    let mut end = start;
+    while start > 0 && char_kind(line[start - 1]) == CharKind::Word {
+        start -= 1;
+    }
+    while end < line.len() && char_kind(line[end]) == CharKind::Word {
+        end += 1;
     }
+    Point::new(point.row, start as u32)..Point::new(point.row, end as u32)
+}
+
+/// Finds the bracket adjacent to the point and the bracket that matches it, returning both of
+/// their positions. A closing bracket before the point takes precedence over an opening bracket
+/// after it, so that jumping between the two brackets of a pair is reversible.
+pub fn matching_bracket(buffer: &Buffer, point: Point) -> Option<(Point, Point)> {
+    let newline = u16::from(b'\n');
+    let previous = if point.column > 0 {
+        buffer.backward_iter_starting_at_point(point).next()
+    } else {
+        None
+    };
+    let next = buffer
+        .iter_starting_at_point(point)
+        .next()
+        .and_then(|c| if c == newline { None } else { Some(c) });
+    let before = Point::new(point.row, point.column.saturating_sub(1));
+
+    let candidates = [
+        (previous, before, false),
+        (next, point, true),
+        (next, point, false),
+        (previous, before, true),
+    ];
+    for &(c, position, opening) in &candidates {
+        if let Some(c) = c {
+            for &(open, close) in BRACKETS {
+                let matching = if opening && c == u16::from(open) {
+                    scan_forward(buffer, position, open, close)
+                } else if !opening && c == u16::from(close) {
+                    scan_backward(buffer, position, open, close)
+                } else {
+                    None
+                };
+                if let Some(matching) = matching {
+                    return Some((position, matching));
+                }
+            }
+        }
+    }
+    None
+}
+
+pub fn top() -> Point {
+    Point::new(0, 0)
+}
+
+pub fn bottom(buffer: &Buffer) -> Point {
+    buffer.max_point()
+}
+
+// Returns the position of the closing bracket that matches the opening bracket at the given
+// position, skipping over nested pairs.
+fn scan_forward(buffer: &Buffer, position: Point, open: u8, close: u8) -> Option<Point> {
+    let max_row = cmp::min(buffer.max_point().row, position.row + MAX_BRACKET_SCAN_ROWS);
+    let mut depth = 0;
+    let mut row = position.row;
+    let mut column = position.column + 1;
+    for c in buffer.iter_starting_at_point(Point::new(row, column)) {
+        if c == u16::from(b'\n') {
+            row += 1;
+            column = 0;
+            if row > max_row {
+                break;
+            }
+            continue;
+        }
+
+        if c == u16::from(open) {
+            depth += 1;
+        } else if c == u16::from(close) {
+            if depth == 0 {
+                return Some(Point::new(row, column));
+            }
+            depth -= 1;
+        }
+        column += 1;
+    }
+    None
+}
+
+// Returns the position of the opening bracket that matches the closing bracket at the given
+// position, skipping over nested pairs.
+fn scan_backward(buffer: &Buffer, position: Point, open: u8, close: u8) -> Option<Point> {
+    let min_row = position.row.saturating_sub(MAX_BRACKET_SCAN_ROWS);
+    let mut depth = 0;
+    let mut row = position.row;
+    let mut chars = buffer.backward_iter_starting_at_point(position);
+    while let Some(c) = chars.next() {
+        if c == u16::from(b'\n') {
+            if row == min_row {
+                break;
+            }
+            row -= 1;
+        } else if c == u16::from(close) {
+            depth += 1;
+        } else if c == u16::from(open) {
+            if depth == 0 {
+                // The column is the number of characters preceding the bracket on its row.
+                let column = chars.take_while(|c| *c != u16::from(b'\n')).count();
+                return Some(Point::new(row, column as u32));
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum CharKind {
+    Whitespace,
+    Word,
+    Punctuation,
+}
+
+fn char_kind(c: u16) -> CharKind {
+    if c == u16::from(b' ') || c == u16::from(b'\t') {
+        CharKind::Whitespace
+    } else if c == u16::from(b'_') || c > 127 || (c as u8 as char).is_ascii_alphanumeric() {
+        CharKind::Word
+    } else {
+        CharKind::Punctuation
+    }
+}
 
-    (point, goal_column)
+fn row_chars(buffer: &Buffer, row: u32) -> Vec<u16> {
+    buffer
+        .iter_starting_at_row(row)
+        .take_while(|c| *c != u16::from(b'\n'))
+        .collect()
 }
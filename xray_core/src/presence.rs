@@ -0,0 +1,231 @@
+use buffer::BufferId;
+use notify_cell::{NotifyCell, NotifyCellObserver};
+use std::collections::HashMap;
+use UserId;
+
+const USER_COLORS: &[Color] = &[
+    Color {
+        r: 31,
+        g: 150,
+        b: 255,
+    },
+    Color {
+        r: 64,
+        g: 181,
+        b: 87,
+    },
+    Color {
+        r: 206,
+        g: 157,
+        b: 59,
+    },
+    Color {
+        r: 216,
+        g: 49,
+        b: 176,
+    },
+    Color {
+        r: 235,
+        g: 221,
+        b: 91,
+    },
+];
+
+/// Tracks the users connected to a workspace. The host's presence is the source of truth, and
+/// guests keep a copy of it that is replaced whenever somebody joins or leaves.
+pub struct Presence {
+    users: Vec<UserProfile>,
+    viewports: HashMap<UserId, Viewport>,
+    updates: NotifyCell<()>,
+    viewport_updates: NotifyCell<()>,
+}
+
+/// How a user is identified to the other participants in a workspace.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct UserProfile {
+    pub user_id: UserId,
+    pub name: String,
+    pub color: Color,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// The buffer a user is looking at and how far they have scrolled it, which is what somebody
+/// following them needs to see the same thing.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Viewport {
+    pub buffer_id: BufferId,
+    pub scroll_top: f64,
+}
+
+impl Presence {
+    pub fn new() -> Self {
+        Self {
+            users: Vec::new(),
+            viewports: HashMap::new(),
+            updates: NotifyCell::new(()),
+            viewport_updates: NotifyCell::new(()),
+        }
+    }
+
+    pub fn users(&self) -> &[UserProfile] {
+        &self.users
+    }
+
+    pub fn user(&self, user_id: UserId) -> Option<&UserProfile> {
+        self.users
+            .binary_search_by_key(&user_id, |user| user.user_id)
+            .ok()
+            .map(|index| &self.users[index])
+    }
+
+    pub fn join(&mut self, profile: UserProfile) {
+        match self.users
+            .binary_search_by_key(&profile.user_id, |user| user.user_id)
+        {
+            Ok(index) => self.users[index] = profile,
+            Err(index) => self.users.insert(index, profile),
+        }
+        self.updates.set(());
+    }
+
+    pub fn leave(&mut self, user_id: UserId) {
+        if let Ok(index) = self.users
+            .binary_search_by_key(&user_id, |user| user.user_id)
+        {
+            self.users.remove(index);
+            self.updates.set(());
+        }
+        if self.viewports.remove(&user_id).is_some() {
+            self.viewport_updates.set(());
+        }
+    }
+
+    pub fn set_user_name(&mut self, user_id: UserId, name: String) {
+        if let Ok(index) = self.users
+            .binary_search_by_key(&user_id, |user| user.user_id)
+        {
+            if self.users[index].name != name {
+                self.users[index].name = name;
+                self.updates.set(());
+            }
+        }
+    }
+
+    pub fn set_users(&mut self, mut users: Vec<UserProfile>) {
+        users.sort_by_key(|user| user.user_id);
+        self.users = users;
+        self.updates.set(());
+    }
+
+    pub fn updates(&self) -> NotifyCellObserver<()> {
+        self.updates.observe()
+    }
+
+    pub fn viewport(&self, user_id: UserId) -> Option<&Viewport> {
+        self.viewports.get(&user_id)
+    }
+
+    pub fn viewports(&self) -> &HashMap<UserId, Viewport> {
+        &self.viewports
+    }
+
+    // Observers are only notified when the viewport actually changes, so that views following
+    // one another don't keep waking each other up.
+    pub fn set_viewport(&mut self, user_id: UserId, viewport: Viewport) {
+        if self.viewports.get(&user_id) != Some(&viewport) {
+            self.viewports.insert(user_id, viewport);
+            self.viewport_updates.set(());
+        }
+    }
+
+    pub fn set_viewports(&mut self, viewports: HashMap<UserId, Viewport>) {
+        if self.viewports != viewports {
+            self.viewports = viewports;
+            self.viewport_updates.set(());
+        }
+    }
+
+    pub fn viewport_updates(&self) -> NotifyCellObserver<()> {
+        self.viewport_updates.observe()
+    }
+}
+
+impl UserProfile {
+    /// Creates a profile with a placeholder name, which users can replace once they have joined,
+    /// and a color that distinguishes the user from those who joined just before or after them.
+    pub fn new(user_id: UserId) -> Self {
+        let name = if user_id == 0 {
+            String::from("Host")
+        } else {
+            format!("Guest {}", user_id)
+        };
+        Self {
+            user_id,
+            name,
+            color: USER_COLORS[user_id % USER_COLORS.len()],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_and_leave() {
+        let mut presence = Presence::new();
+        presence.join(UserProfile::new(2));
+        presence.join(UserProfile::new(0));
+        presence.join(UserProfile::new(1));
+        assert_eq!(user_names(&presence), vec!["Host", "Guest 1", "Guest 2"]);
+        assert_eq!(presence.user(1).unwrap().color, USER_COLORS[1]);
+        assert_eq!(UserProfile::new(5).color, USER_COLORS[0]);
+
+        presence.leave(1);
+        presence.leave(3);
+        assert_eq!(user_names(&presence), vec!["Host", "Guest 2"]);
+        assert_eq!(presence.user(1), None);
+
+        presence.set_user_name(2, String::from("Nathan"));
+        presence.set_user_name(1, String::from("Antonio"));
+        assert_eq!(user_names(&presence), vec!["Host", "Nathan"]);
+    }
+
+    #[test]
+    fn test_viewports() {
+        let mut presence = Presence::new();
+        presence.join(UserProfile::new(0));
+        presence.join(UserProfile::new(1));
+        presence.set_viewport(0, viewport(3, 0.0));
+        presence.set_viewport(1, viewport(5, 40.0));
+        presence.set_viewport(1, viewport(5, 80.0));
+        assert_eq!(presence.viewport(0), Some(&viewport(3, 0.0)));
+        assert_eq!(presence.viewport(1), Some(&viewport(5, 80.0)));
+
+        // Viewports are forgotten when their users leave
+        presence.leave(1);
+        assert_eq!(presence.viewport(1), None);
+        assert_eq!(presence.viewports().len(), 1);
+    }
+
+    fn viewport(buffer_id: BufferId, scroll_top: f64) -> Viewport {
+        Viewport {
+            buffer_id,
+            scroll_top,
+        }
+    }
+
+    fn user_names(presence: &Presence) -> Vec<&str> {
+        presence
+            .users()
+            .iter()
+            .map(|user| user.name.as_str())
+            .collect()
+    }
+}
@@ -1,16 +1,18 @@
-use buffer::{self, Buffer, BufferId};
+use buffer::{self, Buffer, BufferId, Point};
 use cross_platform;
 use fs;
-use futures::{future, Async, Future, Poll};
+use futures::{future, Async, Future, Poll, Stream};
 use fuzzy;
 use never::Never;
 use notify_cell::{NotifyCell, NotifyCellObserver, WeakNotifyCell};
+use regex::{self, Regex, RegexBuilder};
 use rpc;
-use std::cell::{Cell, RefCell};
+use std::cell::{Cell, Ref, RefCell};
 use std::cmp;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
 use std::error::Error;
 use std::io;
+use std::ops::Range;
 use std::rc::Rc;
 use std::sync::Arc;
 use ForegroundExecutor;
@@ -18,6 +20,8 @@ use IntoShared;
 
 pub type TreeId = usize;
 
+const MAX_CONCURRENT_TEXT_LOADS: usize = 16;
+
 pub trait Project {
     fn open_path(
         &self,
@@ -28,21 +32,37 @@ pub trait Project {
         &self,
         buffer_id: BufferId,
     ) -> Box<Future<Item = Rc<RefCell<Buffer>>, Error = OpenError>>;
+    fn save_buffer(&self, buffer_id: BufferId) -> Box<Future<Item = (), Error = SaveError>>;
     fn search_paths(
         &self,
         needle: &str,
         max_results: usize,
         include_ignored: bool,
     ) -> (PathSearch, NotifyCellObserver<PathSearchStatus>);
+    fn search_text(
+        &self,
+        query: &str,
+        options: TextSearchOptions,
+    ) -> (TextSearch, NotifyCellObserver<TextSearchStatus>);
 }
 
 pub struct LocalProject {
+    foreground: ForegroundExecutor,
     file_provider: Rc<fs::FileProvider>,
     next_tree_id: TreeId,
     next_buffer_id: Rc<Cell<BufferId>>,
     trees: HashMap<TreeId, Rc<fs::LocalTree>>,
     buffers: Rc<RefCell<HashMap<BufferId, Rc<RefCell<Buffer>>>>>,
     buffers_by_file: Rc<RefCell<HashMap<fs::FileId, Rc<RefCell<Buffer>>>>>,
+    files: Rc<RefCell<HashMap<BufferId, BufferFile>>>,
+}
+
+struct BufferFile {
+    file: Box<fs::File>,
+    saved_text: Vec<u16>,
+    // The texts being written by saves that haven't completed yet. Reading one of them back means
+    // the file changed because of our own save rather than somebody else's edit.
+    pending_saves: Vec<Vec<u16>>,
 }
 
 pub struct RemoteProject {
@@ -70,11 +90,20 @@ pub enum RpcRequest {
     OpenBuffer {
         buffer_id: BufferId,
     },
+    SaveBuffer {
+        buffer_id: BufferId,
+    },
+    SearchText {
+        query: String,
+        options: TextSearchOptions,
+    },
 }
 
 #[derive(Deserialize, Serialize)]
 pub enum RpcResponse {
     OpenedBuffer(Result<rpc::ServiceId, OpenError>),
+    SavedBuffer(Result<(), SaveError>),
+    SearchedText(rpc::ServiceId),
 }
 
 pub struct PathSearch {
@@ -114,26 +143,120 @@ enum MatchMarker {
     IsMatch,
 }
 
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct TextSearchOptions {
+    pub regex: bool,
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub include_ignored: bool,
+}
+
+pub type TextSearch = Box<Future<Item = (), Error = ()>>;
+
+struct LocalTextSearch {
+    tree_ids: Vec<TreeId>,
+    roots: Vec<fs::Entry>,
+    tree_paths: HashMap<TreeId, cross_platform::Path>,
+    file_provider: Rc<fs::FileProvider>,
+    buffers_by_file: Rc<RefCell<HashMap<fs::FileId, Rc<RefCell<Buffer>>>>>,
+    regex: Option<Regex>,
+    include_ignored: bool,
+    paths: Option<VecDeque<TextSearchPath>>,
+    loads: VecDeque<TextSearchLoad>,
+    results: TextSearchResults,
+    updates: WeakNotifyCell<TextSearchStatus>,
+}
+
+struct RemoteTextSearch {
+    response: Box<Future<Item = rpc::client::Service<TextSearchService>, Error = ()>>,
+    service: Option<rpc::client::Service<TextSearchService>>,
+    service_updates: Option<Box<Stream<Item = TextSearchUpdate, Error = ()>>>,
+    results: TextSearchResults,
+    updates: WeakNotifyCell<TextSearchStatus>,
+}
+
+pub struct TextSearchService {
+    search: Option<TextSearch>,
+    status: NotifyCellObserver<TextSearchStatus>,
+    sent_results: usize,
+    finished: bool,
+}
+
+#[derive(Deserialize, Serialize)]
+pub enum TextSearchUpdate {
+    Results(Vec<TextSearchResult>),
+    Finished,
+    InvalidQuery(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextSearchStatus {
+    Pending,
+    Searching(TextSearchResults),
+    Ready(TextSearchResults),
+    InvalidQuery(String),
+}
+
+// The search appends to its results as it goes and every status update shares them, so reporting
+// progress doesn't copy everything found so far.
+#[derive(Clone, Debug, Default)]
+pub struct TextSearchResults(Rc<RefCell<Vec<TextSearchResult>>>);
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct TextSearchResult {
+    pub tree_id: TreeId,
+    pub relative_path: cross_platform::Path,
+    pub display_path: String,
+    pub ranges: Vec<Range<Point>>,
+}
+
+struct TextSearchPath {
+    tree_id: TreeId,
+    relative_path: cross_platform::Path,
+    display_path: String,
+}
+
+struct TextSearchLoad {
+    path: TextSearchPath,
+    text: Box<Future<Item = Vec<u16>, Error = ()>>,
+    result: Option<Result<Vec<u16>, ()>>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum OpenError {
     BufferNotFound,
     TreeNotFound,
     IoError(String),
     RpcError(rpc::Error),
+    UnexpectedResponse,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum SaveError {
+    BufferNotFound,
+    IoError(String),
+    RpcError(rpc::Error),
+    UnexpectedResponse,
 }
 
 impl LocalProject {
-    pub fn new<T>(file_provider: Rc<fs::FileProvider>, trees: Vec<T>) -> Self
+    pub fn new<T>(
+        foreground: ForegroundExecutor,
+        file_provider: Rc<fs::FileProvider>,
+        trees: Vec<T>,
+    ) -> Self
     where
         T: 'static + fs::LocalTree,
     {
         let mut project = LocalProject {
+            foreground,
             file_provider,
             next_tree_id: 0,
             next_buffer_id: Rc::new(Cell::new(0)),
             trees: HashMap::new(),
             buffers: Rc::new(RefCell::new(HashMap::new())),
             buffers_by_file: Rc::new(RefCell::new(HashMap::new())),
+            files: Rc::new(RefCell::new(HashMap::new())),
         };
         for tree in trees {
             project.add_tree(tree);
@@ -158,6 +281,69 @@ impl LocalProject {
             absolute_path
         })
     }
+
+    /// Reads the file backing the given buffer and merges any changes made to it on disk since
+    /// it was last loaded or saved into the buffer. This happens automatically whenever the file
+    /// changes on disk.
+    pub fn reload_buffer(&self, buffer_id: BufferId) -> Box<Future<Item = (), Error = OpenError>> {
+        Self::reload(&self.buffers, &self.files, buffer_id)
+    }
+
+    fn reload(
+        buffers: &Rc<RefCell<HashMap<BufferId, Rc<RefCell<Buffer>>>>>,
+        files: &Rc<RefCell<HashMap<BufferId, BufferFile>>>,
+        buffer_id: BufferId,
+    ) -> Box<Future<Item = (), Error = OpenError>> {
+        let buffer = buffers.borrow().get(&buffer_id).cloned();
+        let files_ref = files.borrow();
+        if let (Some(buffer), Some(buffer_file)) = (buffer, files_ref.get(&buffer_id)) {
+            let files = files.clone();
+            Box::new(
+                buffer_file
+                    .file
+                    .read()
+                    .map(move |content| {
+                        let new_text = content.encode_utf16().collect::<Vec<_>>();
+                        if let Some(buffer_file) = files.borrow_mut().get_mut(&buffer_id) {
+                            // The buffer may have been edited since a pending save started, so
+                            // merging what it wrote could apply its changes a second time or
+                            // flag a conflict. The save updates the saved text instead.
+                            if !buffer_file.pending_saves.contains(&new_text) {
+                                buffer
+                                    .borrow_mut()
+                                    .reload(&buffer_file.saved_text, &new_text);
+                                buffer_file.saved_text = new_text;
+                            }
+                        }
+                    })
+                    .map_err(|error| error.into()),
+            )
+        } else {
+            Box::new(future::err(OpenError::BufferNotFound))
+        }
+    }
+
+    fn watch_file(
+        foreground: &ForegroundExecutor,
+        tree: &fs::LocalTree,
+        relative_path: &cross_platform::Path,
+        buffer_id: BufferId,
+        buffers: &Rc<RefCell<HashMap<BufferId, Rc<RefCell<Buffer>>>>>,
+        files: &Rc<RefCell<HashMap<BufferId, BufferFile>>>,
+    ) {
+        let buffers = Rc::downgrade(buffers);
+        let files = Rc::downgrade(files);
+        let reloads = tree.file_updates(relative_path).for_each(move |_| {
+            if let (Some(buffers), Some(files)) = (buffers.upgrade(), files.upgrade()) {
+                // A failed reload leaves the buffer as it was until the file changes again.
+                Box::new(Self::reload(&buffers, &files, buffer_id).then(|_| Ok(())))
+                    as Box<Future<Item = (), Error = ()>>
+            } else {
+                Box::new(future::err(()))
+            }
+        });
+        foreground.execute(Box::new(reloads)).unwrap();
+    }
 }
 
 impl Project for LocalProject {
@@ -167,9 +353,13 @@ impl Project for LocalProject {
         relative_path: &cross_platform::Path,
     ) -> Box<Future<Item = Rc<RefCell<Buffer>>, Error = OpenError>> {
         if let Some(absolute_path) = self.resolve_path(tree_id, relative_path) {
+            let foreground = self.foreground.clone();
+            let tree = self.trees[&tree_id].clone();
+            let relative_path = relative_path.clone();
             let next_buffer_id_cell = self.next_buffer_id.clone();
             let buffers_by_file = self.buffers_by_file.clone();
             let buffers = self.buffers.clone();
+            let files = self.files.clone();
             Box::new(
                 self.file_provider
                     .open(&absolute_path)
@@ -188,8 +378,27 @@ impl Project for LocalProject {
                                         next_buffer_id_cell.set(next_buffer_id_cell.get() + 1);
                                         let mut buffer = Buffer::new(buffer_id);
                                         buffer.edit(0..0, content.as_str());
+                                        let version = buffer.version.clone();
+                                        buffer.did_save(version);
+                                        let saved_text = buffer.to_u16_chars();
                                         let buffer = buffer.into_shared();
                                         buffers.borrow_mut().insert(buffer_id, buffer.clone());
+                                        files.borrow_mut().insert(
+                                            buffer_id,
+                                            BufferFile {
+                                                file,
+                                                saved_text,
+                                                pending_saves: Vec::new(),
+                                            },
+                                        );
+                                        Self::watch_file(
+                                            &foreground,
+                                            tree.as_ref(),
+                                            &relative_path,
+                                            buffer_id,
+                                            &buffers,
+                                            &files,
+                                        );
                                         buffer
                                     })
                                     .clone())
@@ -218,6 +427,47 @@ impl Project for LocalProject {
         )
     }
 
+    fn save_buffer(&self, buffer_id: BufferId) -> Box<Future<Item = (), Error = SaveError>> {
+        let buffer = self.buffers.borrow().get(&buffer_id).cloned();
+        let buffers_by_file = self.buffers_by_file.clone();
+        let files = self.files.clone();
+        let mut files_ref = self.files.borrow_mut();
+        if let (Some(buffer), Some(buffer_file)) = (buffer, files_ref.get_mut(&buffer_id)) {
+            let (text, version) = {
+                let buffer = buffer.borrow();
+                (buffer.to_u16_chars(), buffer.version.clone())
+            };
+            let old_file_id = buffer_file.file.id();
+            buffer_file.pending_saves.push(text.clone());
+            Box::new(buffer_file.file.write(text.clone()).then(move |result| {
+                if let Some(buffer_file) = files.borrow_mut().get_mut(&buffer_id) {
+                    let index = buffer_file
+                        .pending_saves
+                        .iter()
+                        .position(|pending_text| *pending_text == text);
+                    buffer_file.pending_saves.remove(index.unwrap());
+
+                    if result.is_ok() {
+                        buffer_file.saved_text = text;
+
+                        // Writing may replace the file on disk, which gives it a new id.
+                        let file_id = buffer_file.file.id();
+                        if file_id != old_file_id {
+                            let mut buffers_by_file = buffers_by_file.borrow_mut();
+                            buffers_by_file.remove(&old_file_id);
+                            buffers_by_file.insert(file_id, buffer.clone());
+                        }
+                    }
+                }
+                result
+                    .map(|_| buffer.borrow_mut().did_save(version))
+                    .map_err(|error| error.into())
+            }))
+        } else {
+            Box::new(future::err(SaveError::BufferNotFound))
+        }
+    }
+
     fn search_paths(
         &self,
         needle: &str,
@@ -245,6 +495,36 @@ impl Project for LocalProject {
 
         (search, updates_observer)
     }
+
+    fn search_text(
+        &self,
+        query: &str,
+        options: TextSearchOptions,
+    ) -> (TextSearch, NotifyCellObserver<TextSearchStatus>) {
+        let mut trees = self.trees.iter().collect::<Vec<_>>();
+        trees.sort_by_key(|&(id, _)| *id);
+        let (regex, status) = LocalTextSearch::compile_query(query, options);
+        let (updates, updates_observer) = NotifyCell::weak(status);
+
+        let search = LocalTextSearch {
+            tree_ids: trees.iter().map(|&(id, _)| *id).collect(),
+            roots: trees.iter().map(|&(_, tree)| tree.root()).collect(),
+            tree_paths: trees
+                .iter()
+                .map(|&(id, tree)| (*id, tree.path().clone()))
+                .collect(),
+            file_provider: self.file_provider.clone(),
+            buffers_by_file: self.buffers_by_file.clone(),
+            regex,
+            include_ignored: options.include_ignored,
+            paths: None,
+            loads: VecDeque::new(),
+            results: TextSearchResults::default(),
+            updates,
+        };
+
+        (Box::new(search), updates_observer)
+    }
 }
 
 impl RemoteProject {
@@ -267,6 +547,31 @@ impl RemoteProject {
             trees,
         })
     }
+
+    fn request_buffer(
+        foreground: ForegroundExecutor,
+        service: Rc<RefCell<rpc::client::Service<ProjectService>>>,
+        request: RpcRequest,
+    ) -> Box<Future<Item = Rc<RefCell<Buffer>>, Error = OpenError>> {
+        let response = service.borrow().request(request);
+        Box::new(response.then(move |response| {
+            response
+                .map_err(|error| error.into())
+                .and_then(|response| match response {
+                    RpcResponse::OpenedBuffer(result) => result.and_then(|service_id| {
+                        service
+                            .borrow()
+                            .take_service(service_id)
+                            .map_err(|error| error.into())
+                            .and_then(|buffer_service| {
+                                Buffer::remote(foreground, buffer_service)
+                                    .map_err(|error| error.into())
+                            })
+                    }),
+                    _ => Err(OpenError::UnexpectedResponse),
+                })
+        }))
+    }
 }
 
 impl Project for RemoteProject {
@@ -275,32 +580,13 @@ impl Project for RemoteProject {
         tree_id: TreeId,
         relative_path: &cross_platform::Path,
     ) -> Box<Future<Item = Rc<RefCell<Buffer>>, Error = OpenError>> {
-        let foreground = self.foreground.clone();
-        let service = self.service.clone();
-
-        Box::new(
-            self.service
-                .borrow()
-                .request(RpcRequest::OpenPath {
-                    tree_id,
-                    relative_path: relative_path.clone(),
-                })
-                .then(move |response| {
-                    response
-                        .map_err(|error| error.into())
-                        .and_then(|response| match response {
-                            RpcResponse::OpenedBuffer(result) => result.and_then(|service_id| {
-                                service
-                                    .borrow()
-                                    .take_service(service_id)
-                                    .map_err(|error| error.into())
-                                    .and_then(|buffer_service| {
-                                        Buffer::remote(foreground, buffer_service)
-                                            .map_err(|error| error.into())
-                                    })
-                            }),
-                        })
-                }),
+        Self::request_buffer(
+            self.foreground.clone(),
+            self.service.clone(),
+            RpcRequest::OpenPath {
+                tree_id,
+                relative_path: relative_path.clone(),
+            },
         )
     }
 
@@ -308,26 +594,24 @@ impl Project for RemoteProject {
         &self,
         buffer_id: BufferId,
     ) -> Box<Future<Item = Rc<RefCell<Buffer>>, Error = OpenError>> {
-        let foreground = self.foreground.clone();
-        let service = self.service.clone();
+        Self::request_buffer(
+            self.foreground.clone(),
+            self.service.clone(),
+            RpcRequest::OpenBuffer { buffer_id },
+        )
+    }
+
+    fn save_buffer(&self, buffer_id: BufferId) -> Box<Future<Item = (), Error = SaveError>> {
         Box::new(
             self.service
                 .borrow()
-                .request(RpcRequest::OpenBuffer { buffer_id })
-                .then(move |response| {
+                .request(RpcRequest::SaveBuffer { buffer_id })
+                .then(|response| {
                     response
                         .map_err(|error| error.into())
                         .and_then(|response| match response {
-                            RpcResponse::OpenedBuffer(result) => result.and_then(|service_id| {
-                                service
-                                    .borrow()
-                                    .take_service(service_id)
-                                    .map_err(|error| error.into())
-                                    .and_then(|buffer_service| {
-                                        Buffer::remote(foreground, buffer_service)
-                                            .map_err(|error| error.into())
-                                    })
-                            }),
+                            RpcResponse::SavedBuffer(result) => result,
+                            _ => Err(SaveError::UnexpectedResponse),
                         })
                 }),
         )
@@ -360,6 +644,38 @@ impl Project for RemoteProject {
 
         (search, updates_observer)
     }
+
+    fn search_text(
+        &self,
+        query: &str,
+        options: TextSearchOptions,
+    ) -> (TextSearch, NotifyCellObserver<TextSearchStatus>) {
+        let (updates, updates_observer) = NotifyCell::weak(TextSearchStatus::Pending);
+        let service = self.service.clone();
+        let response = self.service
+            .borrow()
+            .request(RpcRequest::SearchText {
+                query: query.to_string(),
+                options,
+            })
+            .map_err(|_| ())
+            .and_then(move |response| match response {
+                RpcResponse::SearchedText(service_id) => {
+                    service.borrow().take_service(service_id).map_err(|_| ())
+                }
+                _ => Err(()),
+            });
+
+        let search = RemoteTextSearch {
+            response: Box::new(response),
+            service: None,
+            service_updates: None,
+            results: TextSearchResults::default(),
+            updates,
+        };
+
+        (Box::new(search), updates_observer)
+    }
 }
 
 impl ProjectService {
@@ -433,6 +749,19 @@ impl rpc::server::Service for ProjectService {
                     },
                 )))
             }
+            RpcRequest::SaveBuffer { buffer_id } => Some(Box::new(
+                self.project
+                    .borrow()
+                    .save_buffer(buffer_id)
+                    .then(|result| Ok(RpcResponse::SavedBuffer(result))),
+            )),
+            RpcRequest::SearchText { query, options } => {
+                let (search, status) = self.project.borrow().search_text(&query, options);
+                let handle = connection.add_service(TextSearchService::new(search, status));
+                Some(Box::new(future::ok(RpcResponse::SearchedText(
+                    handle.service_id(),
+                ))))
+            }
         }
     }
 }
@@ -651,6 +980,363 @@ impl Future for PathSearch {
     }
 }
 
+impl TextSearchOptions {
+    pub(crate) fn build_regex(&self, query: &str) -> Result<Regex, regex::Error> {
+        let mut pattern = if self.regex {
+            query.to_string()
+        } else {
+            regex::escape(query)
+        };
+        if self.whole_word {
+            pattern = format!(r"\b(?:{})\b", pattern);
+        }
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .multi_line(true)
+            .build()
+    }
+}
+
+/// Returns the ranges of all non-empty matches in the given text, with columns measured in
+/// UTF-16 code units to agree with buffer points.
+pub(crate) fn find_ranges(regex: &Regex, text: &[u16]) -> Vec<Range<Point>> {
+    let text = String::from_utf16_lossy(text);
+    let mut ranges = Vec::new();
+    let mut offset = 0;
+    let mut position = Point::new(0, 0);
+    let mut advance = |offset: &mut usize, new_offset: usize| {
+        for c in text[*offset..new_offset].chars() {
+            if c == '\n' {
+                position.row += 1;
+                position.column = 0;
+            } else {
+                position.column += c.len_utf16() as u32;
+            }
+        }
+        *offset = new_offset;
+        position
+    };
+
+    for match_ in regex.find_iter(&text) {
+        if match_.start() < match_.end() {
+            let start = advance(&mut offset, match_.start());
+            let end = advance(&mut offset, match_.end());
+            ranges.push(start..end);
+        }
+    }
+    ranges
+}
+
+impl LocalTextSearch {
+    fn compile_query(query: &str, options: TextSearchOptions) -> (Option<Regex>, TextSearchStatus) {
+        if query.is_empty() {
+            (None, TextSearchStatus::Ready(TextSearchResults::default()))
+        } else {
+            match options.build_regex(query) {
+                Ok(regex) => (Some(regex), TextSearchStatus::Pending),
+                Err(error) => (None, TextSearchStatus::InvalidQuery(error.to_string())),
+            }
+        }
+    }
+
+    fn collect_paths(&self) -> Result<VecDeque<TextSearchPath>, ()> {
+        let mut paths = VecDeque::new();
+        let mut steps_since_last_check = 0;
+        for (tree_id, root) in self.tree_ids.iter().zip(self.roots.iter()) {
+            let display_path = if self.roots.len() == 1 {
+                String::new()
+            } else {
+                root.name_chars().iter().collect()
+            };
+            self.collect_dir_paths(
+                *tree_id,
+                root,
+                &cross_platform::Path::new(),
+                &display_path,
+                &mut paths,
+                &mut steps_since_last_check,
+            )?;
+        }
+        Ok(paths)
+    }
+
+    fn collect_dir_paths(
+        &self,
+        tree_id: TreeId,
+        dir: &fs::Entry,
+        dir_path: &cross_platform::Path,
+        dir_display_path: &str,
+        paths: &mut VecDeque<TextSearchPath>,
+        steps_since_last_check: &mut usize,
+    ) -> Result<(), ()> {
+        for child in dir.children().unwrap().iter() {
+            self.check_cancellation(steps_since_last_check, 10000)?;
+            if child.is_ignored() && !self.include_ignored {
+                continue;
+            }
+
+            let mut relative_path = dir_path.clone();
+            relative_path.push(child.name());
+            let mut display_path = dir_display_path.to_string();
+            display_path.extend(child.name_chars());
+            if child.is_dir() {
+                self.collect_dir_paths(
+                    tree_id,
+                    child,
+                    &relative_path,
+                    &display_path,
+                    paths,
+                    steps_since_last_check,
+                )?;
+            } else {
+                paths.push_back(TextSearchPath {
+                    tree_id,
+                    relative_path,
+                    display_path,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn check_cancellation(
+        &self,
+        steps_since_last_check: &mut usize,
+        steps_between_checks: usize,
+    ) -> Result<(), ()> {
+        *steps_since_last_check += 1;
+        if *steps_since_last_check == steps_between_checks {
+            if self.updates.has_observers() {
+                *steps_since_last_check = 0;
+            } else {
+                return Err(());
+            }
+        }
+        Ok(())
+    }
+
+    fn load_text(&self, path: &TextSearchPath) -> Box<Future<Item = Vec<u16>, Error = ()>> {
+        let mut absolute_path = self.tree_paths[&path.tree_id].clone();
+        absolute_path.push_path(&path.relative_path);
+        let buffers_by_file = self.buffers_by_file.clone();
+        Box::new(
+            self.file_provider
+                .open(&absolute_path)
+                .and_then(move |file| {
+                    // Search the contents of open buffers rather than what was last saved.
+                    let buffer = buffers_by_file.borrow().get(&file.id()).cloned();
+                    if let Some(buffer) = buffer {
+                        Box::new(future::ok(buffer.borrow().to_u16_chars()))
+                            as Box<Future<Item = Vec<u16>, Error = io::Error>>
+                    } else {
+                        Box::new(file.read().map(|content| content.encode_utf16().collect()))
+                    }
+                })
+                .map_err(|_| ()),
+        )
+    }
+}
+
+impl Future for LocalTextSearch {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let regex = match self.regex {
+            Some(ref regex) => regex.clone(),
+            None => return Ok(Async::Ready(())),
+        };
+        if self.paths.is_none() {
+            self.paths = Some(self.collect_paths()?);
+        }
+
+        loop {
+            if !self.updates.has_observers() {
+                return Err(());
+            }
+
+            while self.loads.len() < MAX_CONCURRENT_TEXT_LOADS {
+                if let Some(path) = self.paths.as_mut().unwrap().pop_front() {
+                    let text = self.load_text(&path);
+                    self.loads.push_back(TextSearchLoad {
+                        path,
+                        text,
+                        result: None,
+                    });
+                } else {
+                    break;
+                }
+            }
+
+            for load in self.loads.iter_mut().filter(|load| load.result.is_none()) {
+                match load.text.poll() {
+                    Ok(Async::Ready(text)) => load.result = Some(Ok(text)),
+                    Ok(Async::NotReady) => {}
+                    Err(_) => load.result = Some(Err(())),
+                }
+            }
+
+            // Search files in the order we found them so that results are reported in a
+            // consistent order, even though their contents may load in any order.
+            let mut searched_file = false;
+            let mut found_match = false;
+            while self.loads.front().map_or(false, |load| load.result.is_some()) {
+                let load = self.loads.pop_front().unwrap();
+                searched_file = true;
+                if let Some(Ok(text)) = load.result {
+                    let ranges = find_ranges(&regex, &text);
+                    if !ranges.is_empty() {
+                        found_match = true;
+                        self.results.0.borrow_mut().push(TextSearchResult {
+                            tree_id: load.path.tree_id,
+                            relative_path: load.path.relative_path,
+                            display_path: load.path.display_path,
+                            ranges,
+                        });
+                    }
+                }
+            }
+
+            if self.loads.is_empty() {
+                let results = self.results.clone();
+                self.updates
+                    .try_set(TextSearchStatus::Ready(results))
+                    .map_err(|_| ())?;
+                return Ok(Async::Ready(()));
+            } else if found_match {
+                let results = self.results.clone();
+                self.updates
+                    .try_set(TextSearchStatus::Searching(results))
+                    .map_err(|_| ())?;
+            }
+
+            if !searched_file {
+                return Ok(Async::NotReady);
+            }
+        }
+    }
+}
+
+impl Future for RemoteTextSearch {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.service.is_none() {
+            let service = match self.response.poll()? {
+                Async::Ready(service) => service,
+                Async::NotReady => return Ok(Async::NotReady),
+            };
+            self.service_updates = Some(service.updates().map_err(|_| ())?);
+            self.service = Some(service);
+        }
+
+        loop {
+            if !self.updates.has_observers() {
+                return Err(());
+            }
+
+            let update = match self.service_updates.as_mut().unwrap().poll()? {
+                Async::Ready(update) => update,
+                Async::NotReady => return Ok(Async::NotReady),
+            };
+            match update {
+                Some(TextSearchUpdate::Results(results)) => {
+                    self.results.0.borrow_mut().extend(results);
+                    let results = self.results.clone();
+                    self.updates
+                        .try_set(TextSearchStatus::Searching(results))
+                        .map_err(|_| ())?;
+                }
+                Some(TextSearchUpdate::Finished) => {
+                    let results = self.results.clone();
+                    self.updates
+                        .try_set(TextSearchStatus::Ready(results))
+                        .map_err(|_| ())?;
+                    return Ok(Async::Ready(()));
+                }
+                Some(TextSearchUpdate::InvalidQuery(error)) => {
+                    self.updates
+                        .try_set(TextSearchStatus::InvalidQuery(error))
+                        .map_err(|_| ())?;
+                    return Ok(Async::Ready(()));
+                }
+                None => return Err(()),
+            }
+        }
+    }
+}
+
+impl TextSearchService {
+    fn new(search: TextSearch, status: NotifyCellObserver<TextSearchStatus>) -> Self {
+        Self {
+            search: Some(search),
+            status,
+            sent_results: 0,
+            finished: false,
+        }
+    }
+}
+
+impl rpc::server::Service for TextSearchService {
+    type State = ();
+    type Update = TextSearchUpdate;
+    type Request = ();
+    type Response = ();
+
+    fn init(&mut self, _: &rpc::server::Connection) -> Self::State {}
+
+    fn poll_update(&mut self, _: &rpc::server::Connection) -> Async<Option<Self::Update>> {
+        if self.finished {
+            return Async::NotReady;
+        }
+
+        // The search runs for as long as this service exists. Once the client drops its handle,
+        // the service is dropped along with the search.
+        let search_done = self.search
+            .as_mut()
+            .map_or(false, |search| search.poll() != Ok(Async::NotReady));
+        if search_done {
+            self.search.take();
+        }
+
+        // Send all results found since the last update together, rather than one at a time.
+        match self.status.get() {
+            TextSearchStatus::Pending => Async::NotReady,
+            TextSearchStatus::Searching(ref results) | TextSearchStatus::Ready(ref results)
+                if results.get().len() > self.sent_results =>
+            {
+                let results = results.get();
+                let new_results = results[self.sent_results..].to_vec();
+                self.sent_results = results.len();
+                Async::Ready(Some(TextSearchUpdate::Results(new_results)))
+            }
+            TextSearchStatus::Searching(_) => Async::NotReady,
+            TextSearchStatus::Ready(_) => {
+                self.finished = true;
+                Async::Ready(Some(TextSearchUpdate::Finished))
+            }
+            TextSearchStatus::InvalidQuery(error) => {
+                self.finished = true;
+                Async::Ready(Some(TextSearchUpdate::InvalidQuery(error)))
+            }
+        }
+    }
+}
+
+impl TextSearchResults {
+    pub fn get(&self) -> Ref<Vec<TextSearchResult>> {
+        self.0.borrow()
+    }
+}
+
+impl PartialEq for TextSearchResults {
+    fn eq(&self, other: &Self) -> bool {
+        *self.get() == *other.get()
+    }
+}
+
 impl Ord for PathSearchResult {
     fn cmp(&self, other: &Self) -> cmp::Ordering {
         self.partial_cmp(other).unwrap_or(cmp::Ordering::Equal)
@@ -679,6 +1365,18 @@ impl From<rpc::Error> for OpenError {
     }
 }
 
+impl From<io::Error> for SaveError {
+    fn from(error: io::Error) -> Self {
+        SaveError::IoError(error.description().to_owned())
+    }
+}
+
+impl From<rpc::Error> for SaveError {
+    fn from(error: rpc::Error) -> Self {
+        SaveError::RpcError(error)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -688,8 +1386,9 @@ mod tests {
 
     #[test]
     fn test_open_same_path_concurrently() {
+        let reactor = reactor::Core::new().unwrap();
         let file_provider = Rc::new(TestFileProvider::new());
-        let project = build_project(file_provider.clone());
+        let project = build_project(Rc::new(reactor.handle()), file_provider.clone());
 
         let tree_id = 0;
         let relative_path = cross_platform::Path::from("subdir-a/subdir-1/bar");
@@ -724,7 +1423,12 @@ mod tests {
                 }
             }),
         );
-        let project = LocalProject::new(Rc::new(TestFileProvider::new()), vec![tree]);
+        let reactor = reactor::Core::new().unwrap();
+        let project = LocalProject::new(
+            Rc::new(reactor.handle()),
+            Rc::new(TestFileProvider::new()),
+            vec![tree],
+        );
         let (mut search, observer) = project.search_paths("sub2", 10, true);
 
         assert_eq!(search.poll(), Ok(Async::Ready(())));
@@ -755,7 +1459,8 @@ mod tests {
 
     #[test]
     fn test_search_many_trees() {
-        let project = build_project(Rc::new(TestFileProvider::new()));
+        let reactor = reactor::Core::new().unwrap();
+        let project = build_project(Rc::new(reactor.handle()), Rc::new(TestFileProvider::new()));
 
         let (mut search, observer) = project.search_paths("bar", 10, true);
         assert_eq!(search.poll(), Ok(Async::Ready(())));
@@ -796,7 +1501,8 @@ mod tests {
         let handle = Rc::new(reactor.handle());
         let file_provider = Rc::new(TestFileProvider::new());
 
-        let local_project = build_project(file_provider.clone()).into_shared();
+        let local_project =
+            build_project(Rc::new(reactor.handle()), file_provider.clone()).into_shared();
         let remote_project = RemoteProject::new(
             handle,
             rpc::tests::connect(&mut reactor, ProjectService::new(local_project.clone())),
@@ -841,7 +1547,257 @@ mod tests {
         );
     }
 
-    fn build_project(file_provider: Rc<TestFileProvider>) -> LocalProject {
+    #[test]
+    fn test_save_buffer() {
+        let mut reactor = reactor::Core::new().unwrap();
+        let handle = Rc::new(reactor.handle());
+        let file_provider = Rc::new(TestFileProvider::new());
+
+        let local_project =
+            build_project(Rc::new(reactor.handle()), file_provider.clone()).into_shared();
+        let remote_project = RemoteProject::new(
+            handle,
+            rpc::tests::connect(&mut reactor, ProjectService::new(local_project.clone())),
+        ).unwrap();
+
+        let tree_id = 0;
+        let relative_path = cross_platform::Path::from("subdir-a/subdir-1/bar");
+        let absolute_path = local_project
+            .borrow()
+            .resolve_path(tree_id, &relative_path)
+            .unwrap();
+        file_provider.write_sync(absolute_path.clone(), "abc");
+
+        let local_buffer = reactor
+            .run(local_project.borrow().open_path(tree_id, &relative_path))
+            .unwrap();
+        let buffer_id = local_buffer.borrow().id();
+        assert!(!local_buffer.borrow().is_modified());
+        local_buffer.borrow_mut().edit(3..3, "def");
+        assert!(local_buffer.borrow().is_modified());
+        reactor
+            .run(local_project.borrow().save_buffer(buffer_id))
+            .unwrap();
+        assert_eq!(file_provider.read_sync(absolute_path.clone()), "abcdef");
+        assert!(!local_buffer.borrow().is_modified());
+
+        let remote_buffer = reactor
+            .run(remote_project.open_buffer(buffer_id))
+            .unwrap();
+        remote_buffer.borrow_mut().edit(0..0, "123");
+        reactor.turn(None);
+        reactor.run(remote_project.save_buffer(buffer_id)).unwrap();
+        assert_eq!(file_provider.read_sync(absolute_path), "123abcdef");
+        assert!(!local_buffer.borrow().is_modified());
+
+        assert!(
+            reactor
+                .run(remote_project.save_buffer(buffer_id + 1))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_reload_buffer() {
+        let mut reactor = reactor::Core::new().unwrap();
+        let file_provider = Rc::new(TestFileProvider::new());
+        let project = build_project(Rc::new(reactor.handle()), file_provider.clone());
+
+        let tree_id = 0;
+        let relative_path = cross_platform::Path::from("subdir-a/subdir-1/bar");
+        let absolute_path = project.resolve_path(tree_id, &relative_path).unwrap();
+        file_provider.write_sync(absolute_path.clone(), "a\nb\nc\n");
+
+        let buffer = reactor
+            .run(project.open_path(tree_id, &relative_path))
+            .unwrap();
+        let buffer_id = buffer.borrow().id();
+
+        file_provider.write_sync(absolute_path.clone(), "a\nB\nc\n");
+        reactor.run(project.reload_buffer(buffer_id)).unwrap();
+        assert_eq!(buffer.borrow().to_string(), "a\nB\nc\n");
+        assert!(!buffer.borrow().is_modified());
+
+        buffer.borrow_mut().edit(6..6, "d\n");
+        file_provider.write_sync(absolute_path.clone(), "A\nB\nc\n");
+        reactor.run(project.reload_buffer(buffer_id)).unwrap();
+        assert_eq!(buffer.borrow().to_string(), "A\nB\nc\nd\n");
+        assert!(buffer.borrow().is_modified());
+        assert!(!buffer.borrow().has_conflict());
+
+        reactor.run(project.save_buffer(buffer_id)).unwrap();
+        file_provider.write_sync(absolute_path.clone(), "A\nB\nc\nd\ne\n");
+        reactor.run(project.reload_buffer(buffer_id)).unwrap();
+        assert_eq!(buffer.borrow().to_string(), "A\nB\nc\nd\ne\n");
+        assert!(!buffer.borrow().is_modified());
+
+        // Reading a save back before it completes doesn't conflict with edits made after it.
+        buffer.borrow_mut().edit(10..10, "f\n");
+        let save = project.save_buffer(buffer_id);
+        buffer.borrow_mut().edit(8..10, "");
+        file_provider.write_sync(absolute_path.clone(), "A\nB\nc\nd\ne\nf\n");
+        reactor.run(project.reload_buffer(buffer_id)).unwrap();
+        assert_eq!(buffer.borrow().to_string(), "A\nB\nc\nd\nf\n");
+        assert!(!buffer.borrow().has_conflict());
+        reactor.run(save).unwrap();
+        assert_eq!(file_provider.read_sync(absolute_path.clone()), "A\nB\nc\nd\ne\nf\n");
+        assert!(buffer.borrow().is_modified());
+    }
+
+    #[test]
+    fn test_reload_changed_files() {
+        use stream_ext::StreamExt;
+
+        let mut reactor = reactor::Core::new().unwrap();
+        let file_provider = Rc::new(TestFileProvider::new());
+        let tree = TestTree::from_json("/Users/someone/tree", json!({ "file": null }));
+        let file_updates = tree.file_updates.clone();
+        let project = LocalProject::new(
+            Rc::new(reactor.handle()),
+            file_provider.clone(),
+            vec![tree],
+        );
+
+        let relative_path = cross_platform::Path::from("file");
+        let absolute_path = project.resolve_path(0, &relative_path).unwrap();
+        file_provider.write_sync(absolute_path.clone(), "a\nb\n");
+        let buffer = reactor.run(project.open_path(0, &relative_path)).unwrap();
+        let mut buffer_updates = buffer.borrow().updates();
+
+        file_provider.write_sync(absolute_path.clone(), "a\nB\n");
+        file_updates.borrow()[&relative_path.to_path_buf()].set(());
+        buffer_updates.wait_next(&mut reactor);
+        assert_eq!(buffer.borrow().to_string(), "a\nB\n");
+        assert!(!buffer.borrow().is_modified());
+    }
+
+    #[test]
+    fn test_search_text() {
+        let mut reactor = reactor::Core::new().unwrap();
+        let handle = Rc::new(reactor.handle());
+        let file_provider = Rc::new(TestFileProvider::new());
+        let local_project =
+            build_project(Rc::new(reactor.handle()), file_provider.clone()).into_shared();
+        let remote_project = RemoteProject::new(
+            handle,
+            rpc::tests::connect(&mut reactor, ProjectService::new(local_project.clone())),
+        ).unwrap();
+
+        let write = |tree_id, path, content| {
+            let relative_path = cross_platform::Path::from(path);
+            let absolute_path = local_project
+                .borrow()
+                .resolve_path(tree_id, &relative_path)
+                .unwrap();
+            file_provider.write_sync(absolute_path, content);
+        };
+        write(0, "subdir-a/file-1", "abc\ndef abc\n");
+        write(0, "subdir-a/subdir-1/bar", "xyz");
+        write(1, "subdir-b/subdir-2/foo", "ABC abcd");
+        write(1, "subdir-b/subdir-2/file-3", "🍐 abc");
+
+        let search = |project: &Project, query: &str, options: TextSearchOptions| {
+            let (search, observer) = project.search_text(query, options);
+            reactor::Core::new().unwrap().run(search).unwrap();
+            summarize_text_results(&observer.get())
+        };
+        let options = TextSearchOptions::default();
+        let expected_results = vec![
+            (
+                0,
+                "subdir-a/file-1".to_string(),
+                vec![(0, 0)..(0, 3), (1, 4)..(1, 7)],
+            ),
+            (
+                1,
+                "subdir-b/subdir-2/file-3".to_string(),
+                vec![(0, 3)..(0, 6)],
+            ),
+            (
+                1,
+                "subdir-b/subdir-2/foo".to_string(),
+                vec![(0, 0)..(0, 3), (0, 4)..(0, 7)],
+            ),
+        ];
+        assert_eq!(
+            search(&*local_project.borrow(), "abc", options),
+            Some(expected_results.clone())
+        );
+
+        let (remote_search, remote_observer) = remote_project.search_text("abc", options);
+        reactor.run(remote_search).unwrap();
+        assert_eq!(
+            summarize_text_results(&remote_observer.get()),
+            Some(expected_results)
+        );
+
+        let case_sensitive = TextSearchOptions {
+            case_sensitive: true,
+            whole_word: true,
+            ..options
+        };
+        assert_eq!(
+            search(&*local_project.borrow(), "ABC", case_sensitive),
+            Some(vec![
+                (
+                    1,
+                    "subdir-b/subdir-2/foo".to_string(),
+                    vec![(0, 0)..(0, 3)],
+                ),
+            ])
+        );
+
+        let regex = TextSearchOptions {
+            regex: true,
+            ..options
+        };
+        assert_eq!(
+            search(&*local_project.borrow(), "^d.f|x+", regex),
+            Some(vec![
+                (0, "subdir-a/file-1".to_string(), vec![(1, 0)..(1, 3)]),
+                (0, "subdir-a/subdir-1/bar".to_string(), vec![(0, 0)..(0, 1)]),
+            ])
+        );
+        match local_project.borrow().search_text("(", regex).1.get() {
+            TextSearchStatus::InvalidQuery(_) => {}
+            status => panic!("Unexpected status {:?}", status),
+        }
+        let (remote_search, remote_observer) = remote_project.search_text("(", regex);
+        reactor.run(remote_search).unwrap();
+        match remote_observer.get() {
+            TextSearchStatus::InvalidQuery(_) => {}
+            status => panic!("Unexpected status {:?}", status),
+        }
+
+        // Open buffers are searched instead of the contents of their files.
+        let buffer = reactor
+            .run(
+                local_project
+                    .borrow()
+                    .open_path(0, &cross_platform::Path::from("subdir-a/subdir-1/bar")),
+            )
+            .unwrap();
+        buffer.borrow_mut().edit(0..0, "abc");
+        assert_eq!(
+            search(&*local_project.borrow(), "abcx", options),
+            Some(vec![
+                (0, "subdir-a/subdir-1/bar".to_string(), vec![(0, 0)..(0, 4)]),
+            ])
+        );
+
+        // Dropping the observer cancels the search.
+        let (mut search, observer) = local_project.borrow().search_text("abc", options);
+        drop(observer);
+        assert_eq!(search.poll(), Err(()));
+        let (remote_search, remote_observer) = remote_project.search_text("abc", options);
+        drop(remote_observer);
+        assert_eq!(reactor.run(remote_search), Err(()));
+    }
+
+    fn build_project(
+        foreground: ForegroundExecutor,
+        file_provider: Rc<TestFileProvider>,
+    ) -> LocalProject {
         let tree_1 = TestTree::from_json(
             "/Users/someone/foo",
             json!({
@@ -869,7 +1825,7 @@ mod tests {
         );
         tree_2.populated.set(true);
 
-        LocalProject::new(file_provider, vec![tree_1, tree_2])
+        LocalProject::new(foreground, file_provider, vec![tree_1, tree_2])
     }
 
     fn summarize_results(
@@ -893,6 +1849,36 @@ mod tests {
         }
     }
 
+    fn summarize_text_results(
+        status: &TextSearchStatus,
+    ) -> Option<Vec<(TreeId, String, Vec<Range<(u32, u32)>>)>> {
+        match status {
+            &TextSearchStatus::Ready(ref results) => {
+                let summary = results
+                    .get()
+                    .iter()
+                    .map(|result| {
+                        let ranges = result
+                            .ranges
+                            .iter()
+                            .map(|range| {
+                                (range.start.row, range.start.column)
+                                    ..(range.end.row, range.end.column)
+                            })
+                            .collect();
+                        (
+                            result.tree_id,
+                            result.relative_path.to_string_lossy(),
+                            ranges,
+                        )
+                    })
+                    .collect();
+                Some(summary)
+            }
+            _ => None,
+        }
+    }
+
     impl PathSearchStatus {
         fn unwrap(self) -> Vec<PathSearchResult> {
             match self {
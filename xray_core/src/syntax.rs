@@ -0,0 +1,638 @@
+use buffer::{Edit, Snapshot, Version};
+use cross_platform;
+use futures::sync::mpsc;
+use futures::{Async, Future, Poll, Stream};
+use notify_cell::{NotifyCell, NotifyCellObserver};
+use std::cmp;
+use std::iter;
+use std::ops::Range;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Language {
+    JavaScript,
+    Rust,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Token {
+    pub start: u32,
+    pub end: u32,
+    pub scope: &'static str,
+}
+
+/// The tokens of every line in a buffer. Updating the highlights only re-tokenizes the lines that
+/// changed, along with any subsequent lines whose starting state was affected by the change.
+#[derive(Clone)]
+pub struct Highlights {
+    language: Language,
+    version: Option<Version>,
+    lines: Vec<Option<Arc<Line>>>,
+}
+
+/// Tokenizes snapshots of a buffer on a background thread, publishing the resulting highlights
+/// after each update. If several snapshots arrive while we're busy, only the latest is tokenized.
+pub struct Highlighter {
+    highlights: Highlights,
+    requests: mpsc::UnboundedReceiver<HighlightRequest>,
+    updates: NotifyCell<Option<Arc<Highlights>>>,
+}
+
+/// A snapshot of the buffer along with the edits that were applied to it since the snapshot
+/// sent in the previous request.
+pub struct HighlightRequest {
+    pub snapshot: Snapshot,
+    pub edits: Vec<Edit>,
+}
+
+struct Line {
+    start_state: State,
+    tokens: Vec<Token>,
+    // The brackets on the line that aren't part of a comment or string, in order.
+    brackets: Vec<u16>,
+    end_state: State,
+}
+
+// Describes the construct that is still open at the end of a line.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+    Normal,
+    BlockComment(u32),
+    String(u16),
+}
+
+struct Grammar {
+    line_comment: &'static str,
+    block_comment: (&'static str, &'static str),
+    nested_block_comments: bool,
+    string_delimiters: &'static [u8],
+    multi_line_string_delimiters: &'static [u8],
+    keywords: &'static [&'static str],
+    constants: &'static [&'static str],
+}
+
+const BRACKETS: &[(u8, u8)] = &[(b'(', b')'), (b'[', b']'), (b'{', b'}')];
+
+static JAVASCRIPT_GRAMMAR: Grammar = Grammar {
+    line_comment: "//",
+    block_comment: ("/*", "*/"),
+    nested_block_comments: false,
+    string_delimiters: b"'\"`",
+    multi_line_string_delimiters: b"`",
+    keywords: &[
+        "async", "await", "break", "case", "catch", "class", "const", "continue", "debugger",
+        "default", "delete", "do", "else", "export", "extends", "finally", "for", "from",
+        "function", "if", "import", "in", "instanceof", "let", "new", "of", "return", "static",
+        "super", "switch", "this", "throw", "try", "typeof", "var", "void", "while", "with",
+        "yield",
+    ],
+    constants: &["false", "Infinity", "NaN", "null", "true", "undefined"],
+};
+
+static RUST_GRAMMAR: Grammar = Grammar {
+    line_comment: "//",
+    block_comment: ("/*", "*/"),
+    nested_block_comments: true,
+    string_delimiters: b"\"",
+    multi_line_string_delimiters: b"\"",
+    keywords: &[
+        "as", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "fn", "for",
+        "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+        "self", "Self", "static", "struct", "super", "trait", "type", "unsafe", "use", "where",
+        "while",
+    ],
+    constants: &["false", "true"],
+};
+
+impl Language {
+    pub fn for_path(path: &cross_platform::Path) -> Option<Self> {
+        match path.extension()?.as_ref() {
+            "js" | "jsx" | "mjs" => Some(Language::JavaScript),
+            "rs" => Some(Language::Rust),
+            _ => None,
+        }
+    }
+
+    fn grammar(&self) -> &'static Grammar {
+        match self {
+            &Language::JavaScript => &JAVASCRIPT_GRAMMAR,
+            &Language::Rust => &RUST_GRAMMAR,
+        }
+    }
+}
+
+impl Highlights {
+    pub fn new(language: Language) -> Self {
+        Self {
+            language,
+            version: None,
+            lines: Vec::new(),
+        }
+    }
+
+    /// The version of the buffer these highlights were last brought up to date with.
+    pub fn version(&self) -> Option<&Version> {
+        self.version.as_ref()
+    }
+
+    pub fn tokens(&self, row: u32) -> &[Token] {
+        self.lines
+            .get(row as usize)
+            .and_then(|line| line.as_ref())
+            .map(|line| line.tokens.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Brings the highlights up to date with the given snapshot, returning the range of rows that
+    /// had to be re-tokenized. The edits must describe every change made to the buffer since the
+    /// previous update, and are ignored on the first update, which tokenizes the whole snapshot.
+    pub fn update(&mut self, snapshot: &Snapshot, edits: &[Edit]) -> Range<u32> {
+        let mut invalid_rows = Vec::new();
+        if self.lines.is_empty() {
+            let row_count = snapshot.max_point().row + 1;
+            self.lines.resize(row_count as usize, None);
+            invalid_rows.push(0..row_count);
+        } else {
+            for edit in edits {
+                self.splice(&mut invalid_rows, edit);
+            }
+        }
+
+        let grammar = self.language.grammar();
+        let row_count = self.lines.len() as u32;
+        let start_row = invalid_rows.first().map_or(row_count, |rows| rows.start);
+        let mut row = start_row;
+        for rows in invalid_rows {
+            if rows.end < row {
+                continue;
+            }
+
+            row = cmp::max(row, rows.start);
+            let mut state = self.start_state(row);
+            let mut chars = snapshot.iter_starting_at_row(row);
+            while row < row_count {
+                // Past the edited rows, we can stop as soon as a line starts in the same state as
+                // it did when it was last tokenized.
+                if row >= rows.end {
+                    if let Some(ref line) = self.lines[row as usize] {
+                        if line.start_state == state {
+                            break;
+                        }
+                    }
+                }
+
+                let text = chars
+                    .by_ref()
+                    .take_while(|c| *c != u16::from(b'\n'))
+                    .collect::<Vec<_>>();
+                let (tokens, brackets, end_state) = tokenize_line(grammar, state, &text);
+                self.lines[row as usize] = Some(Arc::new(Line {
+                    start_state: state,
+                    tokens,
+                    brackets,
+                    end_state,
+                }));
+                state = end_state;
+                row += 1;
+            }
+        }
+
+        self.version = Some(snapshot.version().clone());
+        start_row..row
+    }
+
+    // Replaces the lines covered by the edit with placeholders for the lines that replaced them,
+    // and records those placeholders as needing to be tokenized. Rows that were already invalid
+    // are shifted to account for the edit.
+    fn splice(&mut self, invalid_rows: &mut Vec<Range<u32>>, edit: &Edit) {
+        let old_range = &edit.old_range;
+        let new_range = &edit.new_range;
+        let start = old_range.start.row;
+        // An edit that ends at the start of a line in both the old and new text leaves that line
+        // unchanged, so its tokens can still be used if the line starts in the same state.
+        let (old_end, new_end) = if old_range.end.column == 0 && new_range.end.column == 0 {
+            (old_range.end.row, new_range.end.row)
+        } else {
+            (old_range.end.row + 1, new_range.end.row + 1)
+        };
+
+        self.lines.splice(
+            start as usize..old_end as usize,
+            iter::repeat(None).take((new_end - start) as usize),
+        );
+
+        let mut merged_rows = start..new_end;
+        let mut rows_before = Vec::new();
+        let mut rows_after = Vec::new();
+        for rows in invalid_rows.drain(..) {
+            if rows.end < start {
+                rows_before.push(rows);
+            } else if rows.start > old_end {
+                rows_after.push(rows.start - old_end + new_end..rows.end - old_end + new_end);
+            } else {
+                merged_rows.start = cmp::min(merged_rows.start, rows.start);
+                if rows.end > old_end {
+                    merged_rows.end = cmp::max(merged_rows.end, rows.end - old_end + new_end);
+                }
+            }
+        }
+        rows_before.push(merged_rows);
+        rows_before.extend(rows_after);
+        *invalid_rows = rows_before;
+    }
+
+    /// Returns the rows spanned by each bracketed node that opens and closes on different rows,
+    /// from the row of its opening bracket to the row of its closing bracket. Nodes are ordered
+    /// by their first row, with enclosing nodes preceding the nodes they contain.
+    pub fn folds(&self) -> Vec<Range<u32>> {
+        let mut open_brackets: Vec<(u16, u32)> = Vec::new();
+        let mut folds = Vec::new();
+        for (row, line) in self.lines.iter().enumerate() {
+            let row = row as u32;
+            let brackets = line.as_ref().map_or(&[][..], |line| line.brackets.as_slice());
+            for &c in brackets {
+                if BRACKETS.iter().any(|&(open, _)| c == u16::from(open)) {
+                    open_brackets.push((c, row));
+                } else if let Some(&(open, start_row)) = open_brackets.last() {
+                    // Closing brackets that don't match the innermost open bracket are ignored.
+                    if BRACKETS.contains(&(open as u8, c as u8)) {
+                        open_brackets.pop();
+                        if start_row < row {
+                            folds.push(start_row..row);
+                        }
+                    }
+                }
+            }
+        }
+        folds.sort_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+        folds
+    }
+
+    fn start_state(&self, row: u32) -> State {
+        if row > 0 {
+            self.lines[row as usize - 1]
+                .as_ref()
+                .map_or(State::Normal, |line| line.end_state)
+        } else {
+            State::Normal
+        }
+    }
+}
+
+impl Highlighter {
+    pub fn new(
+        language: Language,
+    ) -> (
+        Self,
+        mpsc::UnboundedSender<HighlightRequest>,
+        NotifyCellObserver<Option<Arc<Highlights>>>,
+    ) {
+        let (requests_tx, requests_rx) = mpsc::unbounded();
+        let updates = NotifyCell::new(None);
+        let updates_observer = updates.observe();
+        let highlighter = Self {
+            highlights: Highlights::new(language),
+            requests: requests_rx,
+            updates,
+        };
+        (highlighter, requests_tx, updates_observer)
+    }
+}
+
+impl Future for Highlighter {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut latest_snapshot = None;
+        let mut edits = Vec::new();
+        loop {
+            match self.requests.poll()? {
+                Async::Ready(Some(request)) => {
+                    latest_snapshot = Some(request.snapshot);
+                    edits.extend(request.edits);
+                }
+                Async::Ready(None) => return Ok(Async::Ready(())),
+                Async::NotReady => break,
+            }
+        }
+
+        if let Some(snapshot) = latest_snapshot {
+            self.highlights.update(&snapshot, &edits);
+            self.updates.set(Some(Arc::new(self.highlights.clone())));
+        }
+
+        Ok(Async::NotReady)
+    }
+}
+
+fn tokenize_line(grammar: &Grammar, state: State, line: &[u16]) -> (Vec<Token>, Vec<u16>, State) {
+    let mut tokens = Vec::new();
+    let mut brackets = Vec::new();
+    let mut push_token = |start: usize, end: usize, scope: &'static str| {
+        tokens.push(Token {
+            start: start as u32,
+            end: end as u32,
+            scope,
+        })
+    };
+
+    let (mut i, mut state) = match state {
+        State::Normal => (0, State::Normal),
+        State::BlockComment(depth) => {
+            let (end, state) = scan_block_comment(grammar, line, 0, depth);
+            push_token(0, end, "comment");
+            (end, state)
+        }
+        State::String(delimiter) => {
+            let (end, state) = scan_string(line, 0, delimiter);
+            push_token(0, end, "string");
+            (end, state)
+        }
+    };
+
+    while i < line.len() {
+        let c = line[i];
+        if starts_with(line, i, grammar.line_comment) {
+            push_token(i, line.len(), "comment");
+            i = line.len();
+        } else if starts_with(line, i, grammar.block_comment.0) {
+            let start = i + grammar.block_comment.0.len();
+            let (end, end_state) = scan_block_comment(grammar, line, start, 1);
+            push_token(i, end, "comment");
+            state = end_state;
+            i = end;
+        } else if is_one_of(c, grammar.string_delimiters) {
+            let (end, end_state) = scan_string(line, i + 1, c);
+            push_token(i, end, "string");
+            if is_one_of(c, grammar.multi_line_string_delimiters) {
+                state = end_state;
+            }
+            i = end;
+        } else if is_digit(c) {
+            let start = i;
+            while i < line.len()
+                && (is_identifier_char(line[i])
+                    || (line[i] == u16::from(b'.') && i + 1 < line.len() && is_digit(line[i + 1])))
+            {
+                i += 1;
+            }
+            push_token(start, i, "number");
+        } else if is_identifier_char(c) {
+            let start = i;
+            while i < line.len() && is_identifier_char(line[i]) {
+                i += 1;
+            }
+            let identifier = &line[start..i];
+            let scope = if grammar.keywords.iter().any(|k| eq_ascii(identifier, k)) {
+                Some("keyword")
+            } else if grammar.constants.iter().any(|k| eq_ascii(identifier, k)) {
+                Some("constant")
+            } else if identifier[0] >= u16::from(b'A') && identifier[0] <= u16::from(b'Z') {
+                Some("type")
+            } else if i < line.len() && (line[i] == u16::from(b'(') || line[i] == u16::from(b'!')) {
+                Some("function")
+            } else {
+                None
+            };
+            if let Some(scope) = scope {
+                push_token(start, i, scope);
+            }
+        } else {
+            if BRACKETS
+                .iter()
+                .any(|&(open, close)| c == u16::from(open) || c == u16::from(close))
+            {
+                brackets.push(c);
+            }
+            i += 1;
+        }
+    }
+
+    (tokens, brackets, state)
+}
+
+fn scan_block_comment(grammar: &Grammar, line: &[u16], start: usize, depth: u32) -> (usize, State) {
+    let (open, close) = grammar.block_comment;
+    let mut depth = depth;
+    let mut i = start;
+    while i < line.len() {
+        if grammar.nested_block_comments && starts_with(line, i, open) {
+            depth += 1;
+            i += open.len();
+        } else if starts_with(line, i, close) {
+            depth -= 1;
+            i += close.len();
+            if depth == 0 {
+                return (i, State::Normal);
+            }
+        } else {
+            i += 1;
+        }
+    }
+    (line.len(), State::BlockComment(depth))
+}
+
+fn scan_string(line: &[u16], start: usize, delimiter: u16) -> (usize, State) {
+    let mut i = start;
+    while i < line.len() {
+        if line[i] == u16::from(b'\\') {
+            i += 2;
+        } else if line[i] == delimiter {
+            return (i + 1, State::Normal);
+        } else {
+            i += 1;
+        }
+    }
+    (line.len(), State::String(delimiter))
+}
+
+fn starts_with(line: &[u16], start: usize, prefix: &str) -> bool {
+    line.len() >= start + prefix.len() && eq_ascii(&line[start..start + prefix.len()], prefix)
+}
+
+fn eq_ascii(text: &[u16], ascii: &str) -> bool {
+    text.len() == ascii.len() && text.iter().zip(ascii.bytes()).all(|(a, b)| *a == u16::from(b))
+}
+
+fn is_one_of(c: u16, chars: &[u8]) -> bool {
+    chars.iter().any(|d| c == u16::from(*d))
+}
+
+fn is_digit(c: u16) -> bool {
+    c >= u16::from(b'0') && c <= u16::from(b'9')
+}
+
+fn is_identifier_char(c: u16) -> bool {
+    c >= 0x80 || c == u16::from(b'_') || c == u16::from(b'$') || is_digit(c)
+        || (c >= u16::from(b'a') && c <= u16::from(b'z'))
+        || (c >= u16::from(b'A') && c <= u16::from(b'Z'))
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::{Rng, SeedableRng, StdRng};
+    use super::*;
+    use buffer::Buffer;
+
+    #[test]
+    fn test_tokenize() {
+        let mut buffer = Buffer::new(0);
+        buffer.edit(
+            0..0,
+            "fn main() {\n    let x = Some(\"a\\\"b\n c\"); // done\n}\n/* a /* b */ c */ 1.5",
+        );
+        let mut highlights = Highlights::new(Language::Rust);
+        highlights.update(&buffer.snapshot(), &[]);
+        assert_eq!(
+            summarize(&buffer, &highlights),
+            vec![
+                vec!["fn:keyword", "main:function"],
+                vec!["let:keyword", "Some:type", "\"a\\\"b:string"],
+                vec![" c\":string", "// done:comment"],
+                vec![],
+                vec!["/* a /* b */ c */:comment", "1.5:number"],
+            ]
+        );
+
+        let mut buffer = Buffer::new(0);
+        buffer.edit(0..0, "const a = 'x' + `y\nz`; /* c\nd */ null");
+        let mut highlights = Highlights::new(Language::JavaScript);
+        highlights.update(&buffer.snapshot(), &[]);
+        assert_eq!(
+            summarize(&buffer, &highlights),
+            vec![
+                vec!["const:keyword", "'x':string", "`y:string"],
+                vec!["z`:string", "/* c:comment"],
+                vec!["d */:comment", "null:constant"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_incremental_update() {
+        let mut buffer = Buffer::new(0);
+        buffer.edit(0..0, "a\nb\nc\nd\ne");
+        let edits = buffer.subscribe_edits();
+        let mut highlights = Highlights::new(Language::Rust);
+        assert_eq!(highlights.update(&buffer.snapshot(), &[]), 0..5);
+        assert_eq!(highlights.update(&buffer.snapshot(), &[]), 5..5);
+
+        // Editing a line that doesn't change the state of subsequent lines only re-tokenizes it.
+        buffer.edit(2..3, "fn");
+        assert_eq!(highlights.update(&buffer.snapshot(), &edits.take()), 1..2);
+        assert_eq!(summarize(&buffer, &highlights)[1], vec!["fn:keyword"]);
+
+        // Opening a block comment re-tokenizes every subsequent line until it's closed.
+        buffer.edit(0..0, "/*");
+        assert_eq!(highlights.update(&buffer.snapshot(), &edits.take()), 0..5);
+        buffer.edit(8..8, "*/");
+        assert_eq!(highlights.update(&buffer.snapshot(), &edits.take()), 2..5);
+        assert_eq!(
+            summarize(&buffer, &highlights),
+            vec![
+                vec!["/*a:comment"],
+                vec!["fn:comment"],
+                vec!["c*/:comment"],
+                vec![],
+                vec![],
+            ]
+        );
+
+        // Inserting and removing lines only re-tokenizes the lines that changed.
+        buffer.edit(11..11, "x\ny\n");
+        assert_eq!(highlights.update(&buffer.snapshot(), &edits.take()), 3..5);
+        assert_eq!(summarize(&buffer, &highlights).len(), 7);
+        buffer.edit(11..15, "");
+        assert_eq!(highlights.update(&buffer.snapshot(), &edits.take()), 3..3);
+        assert_eq!(buffer.to_string(), "/*a\nfn\nc*/\nd\ne");
+        assert_eq!(
+            summarize(&buffer, &highlights),
+            vec![
+                vec!["/*a:comment"],
+                vec!["fn:comment"],
+                vec!["c*/:comment"],
+                vec![],
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_folds() {
+        let mut buffer = Buffer::new(0);
+        buffer.edit(
+            0..0,
+            "fn a() {\nb(1,\n2);\n/* {\n*/ \"(\n}\"\n[c]\n}\nd {)\n}",
+        );
+        let mut highlights = Highlights::new(Language::Rust);
+        highlights.update(&buffer.snapshot(), &[]);
+        assert_eq!(highlights.version(), Some(&buffer.version));
+        assert_eq!(highlights.folds(), vec![0..7, 1..2, 8..9]);
+    }
+
+    #[test]
+    fn test_random_edits() {
+        const FRAGMENTS: &[&str] = &["\n", "/*", "*/", "\"", "fn", "a", " ", "//", "1"];
+        for seed in 0..50 {
+            let mut rng = StdRng::from_seed(&[seed]);
+            let mut buffer = Buffer::new(0);
+            let edits = buffer.subscribe_edits();
+            let mut highlights = Highlights::new(Language::Rust);
+            highlights.update(&buffer.snapshot(), &[]);
+
+            for _ in 0..20 {
+                // Apply several edits between updates, as happens when the highlighter falls
+                // behind the buffer.
+                for _ in 0..rng.gen_range(1, 4) {
+                    let end = rng.gen_range::<usize>(0, buffer.len() + 1);
+                    let start = rng.gen_range::<usize>(0, end + 1);
+                    let new_text = (0..rng.gen_range(0, 5))
+                        .map(|_| *rng.choose(FRAGMENTS).unwrap())
+                        .collect::<String>();
+                    buffer.edit(start..end, new_text.as_str());
+                }
+                highlights.update(&buffer.snapshot(), &edits.take());
+
+                let mut expected_highlights = Highlights::new(Language::Rust);
+                expected_highlights.update(&buffer.snapshot(), &[]);
+                assert_eq!(
+                    summarize(&buffer, &highlights),
+                    summarize(&buffer, &expected_highlights)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_language_for_path() {
+        assert_eq!(Language::for_path(&"a/b.rs".into()), Some(Language::Rust));
+        assert_eq!(
+            Language::for_path(&"a.b/c.js".into()),
+            Some(Language::JavaScript)
+        );
+        assert_eq!(Language::for_path(&"a.rs/b".into()), None);
+        assert_eq!(Language::for_path(&".rs".into()), None);
+    }
+
+    fn summarize(buffer: &Buffer, highlights: &Highlights) -> Vec<Vec<String>> {
+        buffer
+            .to_string()
+            .split('\n')
+            .enumerate()
+            .map(|(row, line)| {
+                let line = line.encode_utf16().collect::<Vec<_>>();
+                highlights
+                    .tokens(row as u32)
+                    .iter()
+                    .map(|token| {
+                        let text = &line[token.start as usize..token.end as usize];
+                        format!("{}:{}", String::from_utf16_lossy(text), token.scope)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,37 @@
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::Instant;
+
+#[cfg(target_arch = "wasm32")]
+pub use self::wasm::Instant;
+
+// `std::time::Instant::now` panics on wasm32-unknown-unknown, so in the browser we measure time
+// with `performance.now()` instead.
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    use std::ops::Sub;
+    use std::time::Duration;
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen(js_namespace = performance)]
+    extern "C" {
+        fn now() -> f64;
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+    pub struct Instant(f64);
+
+    impl Instant {
+        pub fn now() -> Self {
+            Instant(now())
+        }
+    }
+
+    impl Sub for Instant {
+        type Output = Duration;
+
+        fn sub(self, other: Self) -> Duration {
+            let millis = (self.0 - other.0).max(0.0);
+            Duration::from_millis(millis as u64)
+        }
+    }
+}
@@ -1,19 +1,24 @@
 use buffer::{self, Buffer, BufferId};
 use buffer_view::{BufferView, BufferViewDelegate};
+use clipboard::Clipboard;
 use cross_platform;
 use discussion::{Discussion, DiscussionService, DiscussionView, DiscussionViewDelegate};
 use file_finder::{FileFinderView, FileFinderViewDelegate};
-use futures::{Future, Poll, Stream};
+use find_view::{FindView, FindViewDelegate};
+use futures::{Async, Future, Poll, Stream};
 use never::Never;
 use notify_cell::NotifyCell;
 use notify_cell::NotifyCellObserver;
+use presence::{Presence, UserProfile, Viewport};
 use project::{self, LocalProject, PathSearch, PathSearchStatus, Project, ProjectService,
               RemoteProject, TreeId};
 use rpc::{self, client, server};
 use serde_json;
 use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
 use std::ops::Range;
 use std::rc::Rc;
+use syntax::Language;
 use window::{View, ViewHandle, WeakViewHandle, WeakWindowHandle, Window};
 use ForegroundExecutor;
 use IntoShared;
@@ -24,6 +29,8 @@ pub trait Workspace {
     fn project(&self) -> Ref<Project>;
     fn project_mut(&self) -> RefMut<Project>;
     fn discussion(&self) -> &Rc<RefCell<Discussion>>;
+    fn presence(&self) -> &Rc<RefCell<Presence>>;
+    fn set_user_name(&self, name: String);
 }
 
 pub struct LocalWorkspace {
@@ -31,37 +38,67 @@ pub struct LocalWorkspace {
     user_id: UserId,
     discussion: Rc<RefCell<Discussion>>,
     project: Rc<RefCell<LocalProject>>,
+    presence: Rc<RefCell<Presence>>,
 }
 
 pub struct RemoteWorkspace {
     user_id: UserId,
+    service: client::Service<WorkspaceService>,
     project: Rc<RefCell<RemoteProject>>,
     discussion: Rc<RefCell<Discussion>>,
+    presence: Rc<RefCell<Presence>>,
 }
 
 pub struct WorkspaceService {
     workspace: Rc<RefCell<LocalWorkspace>>,
+    user_id: Option<UserId>,
+    presence_updates: NotifyCellObserver<()>,
+    viewport_updates: NotifyCellObserver<()>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ServiceState {
     user_id: UserId,
+    users: Vec<UserProfile>,
+    viewports: HashMap<UserId, Viewport>,
     project: rpc::ServiceId,
     discussion: rpc::ServiceId,
 }
 
+#[derive(Serialize, Deserialize)]
+pub enum ServiceUpdate {
+    Users(Vec<UserProfile>),
+    Viewports(HashMap<UserId, Viewport>),
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum ServiceRequest {
+    SetViewport(Viewport),
+    SetUserName(String),
+}
+
 pub struct WorkspaceView {
     foreground: ForegroundExecutor,
     workspace: Rc<RefCell<Workspace>>,
     active_buffer_view: Option<WeakViewHandle<BufferView>>,
+    clipboard: Rc<RefCell<Clipboard>>,
     center_pane: Option<ViewHandle>,
     modal: Option<ViewHandle>,
     left_panel: Option<ViewHandle>,
     updates: NotifyCell<()>,
+    presence_updates: NotifyCellObserver<()>,
+    viewport_updates: NotifyCellObserver<()>,
+    following: Option<Following>,
     self_handle: Option<WeakViewHandle<WorkspaceView>>,
     window_handle: Option<WeakWindowHandle>,
 }
 
+struct Following {
+    leader_id: UserId,
+    last_viewport: Option<Viewport>,
+    opening_buffer_id: Option<BufferId>,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Anchor {
     buffer_id: BufferId,
@@ -72,16 +109,23 @@ pub struct Anchor {
 #[serde(tag = "type")]
 enum WorkspaceViewAction {
     ToggleFileFinder,
+    ToggleFind,
     ToggleDiscussion,
+    Follow { user_id: UserId },
+    Unfollow,
+    SetUserName { name: String },
 }
 
 impl LocalWorkspace {
     pub fn new(project: LocalProject) -> Self {
+        let mut presence = Presence::new();
+        presence.join(UserProfile::new(0));
         Self {
             user_id: 0,
             next_user_id: 1,
             project: project.into_shared(),
             discussion: Discussion::new(0).into_shared(),
+            presence: presence.into_shared(),
         }
     }
 }
@@ -102,6 +146,14 @@ impl Workspace for LocalWorkspace {
     fn discussion(&self) -> &Rc<RefCell<Discussion>> {
         &self.discussion
     }
+
+    fn presence(&self) -> &Rc<RefCell<Presence>> {
+        &self.presence
+    }
+
+    fn set_user_name(&self, name: String) {
+        self.presence.borrow_mut().set_user_name(self.user_id, name);
+    }
 }
 
 impl RemoteWorkspace {
@@ -112,15 +164,64 @@ impl RemoteWorkspace {
         let state = service.state()?;
         let project = RemoteProject::new(foreground.clone(), service.take_service(state.project)?)?;
         let discussion = Discussion::remote(
-            foreground,
+            foreground.clone(),
             state.user_id,
             service.take_service(state.discussion)?,
         )?;
 
+        let user_id = state.user_id;
+        let mut presence = Presence::new();
+        presence.set_users(state.users);
+        presence.set_viewports(state.viewports);
+        let presence = presence.into_shared();
+
+        let presence_weak = Rc::downgrade(&presence);
+        foreground
+            .execute(Box::new(service.updates()?.for_each(move |update| {
+                if let Some(presence) = presence_weak.upgrade() {
+                    let mut presence = presence.borrow_mut();
+                    match update {
+                        ServiceUpdate::Users(users) => presence.set_users(users),
+                        ServiceUpdate::Viewports(mut viewports) => {
+                            // The host may echo back a viewport we have since moved away from.
+                            match presence.viewport(user_id).cloned() {
+                                Some(viewport) => viewports.insert(user_id, viewport),
+                                None => viewports.remove(&user_id),
+                            };
+                            presence.set_viewports(viewports);
+                        }
+                    }
+                }
+                Ok(())
+            })))
+            .unwrap();
+
+        let presence_weak = Rc::downgrade(&presence);
+        let viewport_updates = presence.borrow().viewport_updates();
+        let mut last_sent_viewport = presence.borrow().viewport(user_id).cloned();
+        let viewport_service = service.clone();
+        foreground
+            .execute(Box::new(viewport_updates.for_each(move |_| {
+                if let Some(presence) = presence_weak.upgrade() {
+                    let viewport = presence.borrow().viewport(user_id).cloned();
+                    if viewport != last_sent_viewport {
+                        if let Some(ref viewport) = viewport {
+                            viewport_service
+                                .request(ServiceRequest::SetViewport(viewport.clone()));
+                        }
+                        last_sent_viewport = viewport;
+                    }
+                }
+                Ok(())
+            })))
+            .unwrap();
+
         Ok(Self {
-            user_id: state.user_id,
+            user_id,
+            service,
             project: project.into_shared(),
             discussion,
+            presence,
         })
     }
 }
@@ -141,26 +242,60 @@ impl Workspace for RemoteWorkspace {
     fn discussion(&self) -> &Rc<RefCell<Discussion>> {
         &self.discussion
     }
+
+    fn presence(&self) -> &Rc<RefCell<Presence>> {
+        &self.presence
+    }
+
+    // The name is shown locally right away; the host then broadcasts it to everybody else.
+    fn set_user_name(&self, name: String) {
+        self.presence
+            .borrow_mut()
+            .set_user_name(self.user_id, name.clone());
+        self.service.request(ServiceRequest::SetUserName(name));
+    }
 }
 
 impl WorkspaceService {
     pub fn new(workspace: Rc<RefCell<LocalWorkspace>>) -> Self {
-        Self { workspace }
+        let (presence_updates, viewport_updates) = {
+            let workspace = workspace.borrow();
+            let presence = workspace.presence.borrow();
+            (presence.updates(), presence.viewport_updates())
+        };
+        Self {
+            workspace,
+            user_id: None,
+            presence_updates,
+            viewport_updates,
+        }
     }
 }
 
 impl server::Service for WorkspaceService {
     type State = ServiceState;
-    type Update = Never;
-    type Request = Never;
-    type Response = Never;
+    type Update = ServiceUpdate;
+    type Request = ServiceRequest;
+    type Response = ();
 
     fn init(&mut self, connection: &server::Connection) -> ServiceState {
         let mut workspace = self.workspace.borrow_mut();
         let user_id = workspace.next_user_id;
         workspace.next_user_id += 1;
+        self.user_id = Some(user_id);
+
+        let (users, viewports) = {
+            let mut presence = workspace.presence.borrow_mut();
+            presence.join(UserProfile::new(user_id));
+            self.presence_updates = presence.updates();
+            self.viewport_updates = presence.viewport_updates();
+            (presence.users().to_vec(), presence.viewports().clone())
+        };
+
         ServiceState {
             user_id,
+            users,
+            viewports,
             project: connection
                 .add_service(ProjectService::new(workspace.project.clone()))
                 .service_id(),
@@ -172,18 +307,83 @@ impl server::Service for WorkspaceService {
                 .service_id(),
         }
     }
+
+    fn poll_update(&mut self, _: &server::Connection) -> Async<Option<Self::Update>> {
+        let workspace = self.workspace.borrow();
+        let presence = workspace.presence.borrow();
+        match self.presence_updates.poll() {
+            Ok(Async::Ready(Some(()))) => {
+                return Async::Ready(Some(ServiceUpdate::Users(presence.users().to_vec())))
+            }
+            Ok(Async::Ready(None)) | Err(_) => return Async::Ready(None),
+            Ok(Async::NotReady) => {}
+        }
+        match self.viewport_updates.poll() {
+            Ok(Async::Ready(Some(()))) => {
+                Async::Ready(Some(ServiceUpdate::Viewports(presence.viewports().clone())))
+            }
+            Ok(Async::Ready(None)) | Err(_) => Async::Ready(None),
+            Ok(Async::NotReady) => Async::NotReady,
+        }
+    }
+
+    fn request(
+        &mut self,
+        request: Self::Request,
+        _: &server::Connection,
+    ) -> Option<Box<Future<Item = Self::Response, Error = Never>>> {
+        match request {
+            ServiceRequest::SetViewport(viewport) => {
+                if let Some(user_id) = self.user_id {
+                    let workspace = self.workspace.borrow();
+                    workspace
+                        .presence
+                        .borrow_mut()
+                        .set_viewport(user_id, viewport);
+                }
+            }
+            ServiceRequest::SetUserName(name) => {
+                if let Some(user_id) = self.user_id {
+                    let workspace = self.workspace.borrow();
+                    workspace
+                        .presence
+                        .borrow_mut()
+                        .set_user_name(user_id, name);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Drop for WorkspaceService {
+    fn drop(&mut self) {
+        if let Some(user_id) = self.user_id {
+            let workspace = self.workspace.borrow();
+            workspace.presence.borrow_mut().leave(user_id);
+        }
+    }
 }
 
 impl WorkspaceView {
     pub fn new(foreground: ForegroundExecutor, workspace: Rc<RefCell<Workspace>>) -> Self {
+        let (presence_updates, viewport_updates) = {
+            let workspace = workspace.borrow();
+            let presence = workspace.presence().borrow();
+            (presence.updates(), presence.viewport_updates())
+        };
         WorkspaceView {
             workspace,
             foreground,
             active_buffer_view: None,
+            clipboard: Clipboard::new().into_shared(),
             center_pane: None,
             modal: None,
             left_panel: None,
             updates: NotifyCell::new(()),
+            presence_updates,
+            viewport_updates,
+            following: None,
             self_handle: None,
             window_handle: None,
         }
@@ -201,6 +401,18 @@ impl WorkspaceView {
         self.updates.set(());
     }
 
+    fn toggle_find(&mut self, window: &mut Window) {
+        if self.modal.is_some() {
+            self.modal = None;
+        } else if let Some(buffer_view) = self.active_buffer_view.as_ref().cloned() {
+            let delegate = self.self_handle.as_ref().cloned().unwrap();
+            let view = window.add_view(FindView::new(buffer_view, delegate));
+            view.focus().unwrap();
+            self.modal = Some(view);
+        }
+        self.updates.set(());
+    }
+
     fn toggle_discussion(&mut self, window: &mut Window) {
         if self.left_panel.is_some() {
             self.left_panel = None;
@@ -214,12 +426,92 @@ impl WorkspaceView {
         self.updates.set(());
     }
 
-    fn open_buffer<T>(&self, buffer: T, selected_range: Option<Range<buffer::Anchor>>)
-    where
+    fn follow(&mut self, leader_id: UserId) {
+        if leader_id != self.workspace.borrow().user_id() {
+            self.following = Some(Following {
+                leader_id,
+                last_viewport: None,
+                opening_buffer_id: None,
+            });
+            self.follow_leader();
+            self.updates.set(());
+        }
+    }
+
+    fn unfollow(&mut self) {
+        if self.following.take().is_some() {
+            self.updates.set(());
+        }
+    }
+
+    // Catches up with the viewport of the user being followed, opening the buffer they are
+    // looking at if it isn't the active one already.
+    fn follow_leader(&mut self) {
+        let (leader_id, last_viewport, opening_buffer_id) = match self.following {
+            Some(ref following) => (
+                following.leader_id,
+                following.last_viewport.clone(),
+                following.opening_buffer_id,
+            ),
+            None => return,
+        };
+        let (leader_present, viewport) = {
+            let workspace = self.workspace.borrow();
+            let presence = workspace.presence().borrow();
+            (
+                presence.user(leader_id).is_some(),
+                presence.viewport(leader_id).cloned(),
+            )
+        };
+        if !leader_present {
+            self.unfollow();
+            return;
+        }
+        let viewport = match viewport {
+            Some(ref viewport) if last_viewport.as_ref() != Some(viewport) => viewport.clone(),
+            _ => return,
+        };
+
+        let active_buffer_view = self.active_buffer_view.as_ref().and_then(|handle| {
+            if handle.map(|view| view.buffer_id()) == Some(viewport.buffer_id) {
+                Some(handle.clone())
+            } else {
+                None
+            }
+        });
+        if let Some(buffer_view) = active_buffer_view {
+            if let Some(ref mut following) = self.following {
+                following.last_viewport = Some(viewport.clone());
+                following.opening_buffer_id = None;
+            }
+            buffer_view.map(|view| {
+                view.scroll_to_user(leader_id, viewport.scroll_top);
+            });
+        } else if opening_buffer_id != Some(viewport.buffer_id) {
+            if let Some(ref mut following) = self.following {
+                following.opening_buffer_id = Some(viewport.buffer_id);
+            }
+            let workspace = self.workspace.borrow();
+            self.open_buffer(
+                workspace.project().open_buffer(viewport.buffer_id),
+                None,
+                None,
+            );
+        }
+    }
+
+    fn open_buffer<T>(
+        &self,
+        buffer: T,
+        language: Option<Language>,
+        selected_range: Option<Range<buffer::Anchor>>,
+    ) where
         T: 'static + Future<Item = Rc<RefCell<Buffer>>, Error = project::OpenError>,
     {
         if let Some(window_handle) = self.window_handle.clone() {
             let user_id = self.workspace.borrow().user_id();
+            let presence = self.workspace.borrow().presence().clone();
+            let clipboard = self.clipboard.clone();
             let view_handle = self.self_handle.clone();
             self.foreground
                 .execute(Box::new(buffer.then(move |result| {
@@ -228,7 +520,11 @@ impl WorkspaceView {
                             if let Some(view_handle) = view_handle {
                                 let mut buffer_view =
                                     BufferView::new(buffer, user_id, Some(view_handle.clone()));
-                                buffer_view.set_line_height(20.0);
+                                buffer_view
+                                    .set_line_height(20.0)
+                                    .set_language(language)
+                                    .set_clipboard(clipboard)
+                                    .set_presence(presence);
                                 if let Some(selected_range) = selected_range {
                                     if let Err(error) =
                                         buffer_view.set_selected_anchor_range(selected_range)
@@ -242,6 +538,7 @@ impl WorkspaceView {
                                 view_handle.map(|view| {
                                     view.center_pane = Some(buffer_view);
                                     view.modal = None;
+                                    view.follow_leader();
                                     view.updates.set(());
                                 });
                             }
@@ -264,10 +561,15 @@ impl View for WorkspaceView {
     }
 
     fn render(&self) -> serde_json::Value {
+        let workspace = self.workspace.borrow();
+        let presence = workspace.presence().borrow();
         json!({
             "center_pane": self.center_pane.as_ref().map(|view_handle| view_handle.view_id),
             "modal": self.modal.as_ref().map(|view_handle| view_handle.view_id),
-            "left_panel": self.left_panel.as_ref().map(|view_handle| view_handle.view_id)
+            "left_panel": self.left_panel.as_ref().map(|view_handle| view_handle.view_id),
+            "users": presence.users(),
+            "user_id": workspace.user_id(),
+            "following": self.following.as_ref().map(|following| following.leader_id)
         })
     }
 
@@ -279,7 +581,13 @@ impl View for WorkspaceView {
     fn dispatch_action(&mut self, action: serde_json::Value, window: &mut Window) {
         match serde_json::from_value(action) {
             Ok(WorkspaceViewAction::ToggleFileFinder) => self.toggle_file_finder(window),
+            Ok(WorkspaceViewAction::ToggleFind) => self.toggle_find(window),
             Ok(WorkspaceViewAction::ToggleDiscussion) => self.toggle_discussion(window),
+            Ok(WorkspaceViewAction::Follow { user_id }) => self.follow(user_id),
+            Ok(WorkspaceViewAction::Unfollow) => self.unfollow(),
+            Ok(WorkspaceViewAction::SetUserName { name }) => {
+                self.workspace.borrow().set_user_name(name)
+            }
             _ => eprintln!("Unrecognized action"),
         }
     }
@@ -289,6 +597,20 @@ impl BufferViewDelegate for WorkspaceView {
     fn set_active_buffer_view(&mut self, handle: WeakViewHandle<BufferView>) {
         self.active_buffer_view = Some(handle);
     }
+
+    fn save_buffer(&self, buffer_id: BufferId) {
+        let workspace = self.workspace.borrow();
+        self.foreground
+            .execute(Box::new(workspace.project().save_buffer(buffer_id).then(
+                |result| {
+                    if let Err(error) = result {
+                        eprintln!("Error saving buffer {:?}", error);
+                    }
+                    Ok(())
+                },
+            )))
+            .unwrap();
+    }
 }
 
 impl DiscussionViewDelegate for WorkspaceView {
@@ -305,6 +627,7 @@ impl DiscussionViewDelegate for WorkspaceView {
         let workspace = self.workspace.borrow();
         self.open_buffer(
             workspace.project().open_buffer(anchor.buffer_id),
+            None,
             Some(anchor.range.clone()),
         );
     }
@@ -329,7 +652,18 @@ impl FileFinderViewDelegate for WorkspaceView {
 
     fn did_confirm(&mut self, tree_id: TreeId, path: &cross_platform::Path, _: &mut Window) {
         let workspace = self.workspace.borrow();
-        self.open_buffer(workspace.project().open_path(tree_id, path), None);
+        self.open_buffer(
+            workspace.project().open_path(tree_id, path),
+            Language::for_path(path),
+            None,
+        );
+    }
+}
+
+impl FindViewDelegate for WorkspaceView {
+    fn did_close(&mut self) {
+        self.modal = None;
+        self.updates.set(());
     }
 }
 
@@ -338,6 +672,250 @@ impl Stream for WorkspaceView {
     type Error = ();
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        while let Async::Ready(Some(())) = self.viewport_updates.poll()? {
+            self.follow_leader();
+        }
+        if let Async::Ready(Some(())) = self.presence_updates.poll()? {
+            return Ok(Async::Ready(Some(())));
+        }
         self.updates.poll()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fs::tests::{TestFileProvider, TestTree};
+    use std::time::Duration;
+    use tokio_core::reactor;
+
+    #[test]
+    fn test_replicate_presence() {
+        let mut reactor = reactor::Core::new().unwrap();
+        let foreground = Rc::new(reactor.handle());
+        let file_provider = Rc::new(TestFileProvider::new());
+        let local_workspace = build_workspace(foreground.clone(), file_provider).into_shared();
+        local_workspace
+            .borrow()
+            .set_user_name(String::from("Nathan"));
+
+        let remote_workspace_1 = RemoteWorkspace::new(
+            foreground.clone(),
+            rpc::tests::connect(&mut reactor, WorkspaceService::new(local_workspace.clone())),
+        ).unwrap();
+        assert_eq!(remote_workspace_1.user_id(), 1);
+        assert_eq!(user_names(&*local_workspace.borrow()), ["Nathan", "Guest 1"]);
+        assert_eq!(user_names(&remote_workspace_1), ["Nathan", "Guest 1"]);
+
+        remote_workspace_1.set_user_name(String::from("Antonio"));
+        assert_eq!(user_names(&remote_workspace_1), ["Nathan", "Antonio"]);
+        turn_until(&mut reactor, || {
+            user_names(&*local_workspace.borrow()) == ["Nathan", "Antonio"]
+        });
+
+        let remote_workspace_2 = RemoteWorkspace::new(
+            foreground.clone(),
+            rpc::tests::connect(&mut reactor, WorkspaceService::new(local_workspace.clone())),
+        ).unwrap();
+        assert_eq!(remote_workspace_2.user_id(), 2);
+        assert_eq!(
+            user_names(&remote_workspace_2),
+            ["Nathan", "Antonio", "Guest 2"]
+        );
+        turn_until(&mut reactor, || {
+            user_names(&remote_workspace_1) == ["Nathan", "Antonio", "Guest 2"]
+        });
+
+        // Guests leave when their connection to the workspace goes away
+        drop(remote_workspace_1);
+        turn_until(&mut reactor, || {
+            user_names(&*local_workspace.borrow()) == ["Nathan", "Guest 2"]
+                && user_names(&remote_workspace_2) == ["Nathan", "Guest 2"]
+        });
+    }
+
+    #[test]
+    fn test_replicate_viewports() {
+        let mut reactor = reactor::Core::new().unwrap();
+        let foreground = Rc::new(reactor.handle());
+        let file_provider = Rc::new(TestFileProvider::new());
+        let local_workspace = build_workspace(foreground.clone(), file_provider).into_shared();
+        let remote_workspace_1 = RemoteWorkspace::new(
+            foreground.clone(),
+            rpc::tests::connect(&mut reactor, WorkspaceService::new(local_workspace.clone())),
+        ).unwrap();
+        let remote_workspace_2 = RemoteWorkspace::new(
+            foreground.clone(),
+            rpc::tests::connect(&mut reactor, WorkspaceService::new(local_workspace.clone())),
+        ).unwrap();
+
+        // The host relays each guest's viewport to everybody else
+        set_viewport(&remote_workspace_1, viewport(0, 40.0));
+        turn_until(&mut reactor, || {
+            get_viewport(&*local_workspace.borrow(), 1) == Some(viewport(0, 40.0))
+                && get_viewport(&remote_workspace_2, 1) == Some(viewport(0, 40.0))
+        });
+
+        // Guests keep their own viewport when the host echoes back one they have moved away from
+        set_viewport(&remote_workspace_1, viewport(0, 80.0));
+        set_viewport(&*local_workspace.borrow(), viewport(1, 0.0));
+        turn_until(&mut reactor, || {
+            get_viewport(&remote_workspace_1, 0) == Some(viewport(1, 0.0))
+                && get_viewport(&remote_workspace_2, 1) == Some(viewport(0, 80.0))
+        });
+        set_viewport(&remote_workspace_1, viewport(0, 120.0));
+        turn_until(&mut reactor, || {
+            get_viewport(&remote_workspace_2, 1) == Some(viewport(0, 120.0))
+        });
+        for _ in 0..5 {
+            reactor.turn(Some(Duration::from_millis(0)));
+        }
+        for workspace in &[
+            &*local_workspace.borrow() as &Workspace,
+            &remote_workspace_1,
+            &remote_workspace_2,
+        ] {
+            assert_eq!(get_viewport(*workspace, 1), Some(viewport(0, 120.0)));
+            assert_eq!(get_viewport(*workspace, 0), Some(viewport(1, 0.0)));
+        }
+    }
+
+    #[test]
+    fn test_follow() {
+        let mut reactor = reactor::Core::new().unwrap();
+        let foreground = Rc::new(reactor.handle());
+        let file_provider = Rc::new(TestFileProvider::new());
+        for name in &["a", "b"] {
+            let path = format!("/Users/someone/foo/{}", name);
+            let text = format!("{}\n", name).repeat(10);
+            file_provider.write_sync(cross_platform::Path::from(path.as_str()), text);
+        }
+        let local_workspace =
+            build_workspace(foreground.clone(), file_provider.clone()).into_shared();
+        let buffer_a = open_path(&mut reactor, &*local_workspace.borrow(), "a");
+        let buffer_b = open_path(&mut reactor, &*local_workspace.borrow(), "b");
+
+        let leader = RemoteWorkspace::new(
+            foreground.clone(),
+            rpc::tests::connect(&mut reactor, WorkspaceService::new(local_workspace.clone())),
+        ).unwrap();
+        let follower: Rc<RefCell<Workspace>> = RemoteWorkspace::new(
+            foreground.clone(),
+            rpc::tests::connect(&mut reactor, WorkspaceService::new(local_workspace.clone())),
+        ).unwrap()
+            .into_shared();
+
+        let mut window = Window::new(None, 100.0);
+        let workspace_view =
+            window.add_view(WorkspaceView::new(foreground.clone(), follower.clone()));
+        let renders = Rc::new(RefCell::new(HashMap::new()));
+        let renders_clone = renders.clone();
+        reactor.handle().spawn(window.updates().for_each(move |update| {
+            let update = serde_json::to_value(update).unwrap();
+            for view_update in update["updated"].as_array().unwrap() {
+                renders_clone.borrow_mut().insert(
+                    view_update["view_id"].as_u64().unwrap() as usize,
+                    view_update["props"].clone(),
+                );
+            }
+            Ok(())
+        }));
+
+        // Following opens the buffer the leader is looking at
+        set_viewport(&leader, viewport(buffer_a, 0.0));
+        turn_until(&mut reactor, || {
+            get_viewport(&*follower.borrow(), 1) == Some(viewport(buffer_a, 0.0))
+        });
+        window.dispatch_action(
+            workspace_view.view_id,
+            json!({"type": "Follow", "user_id": 1}),
+        );
+        turn_until(&mut reactor, || {
+            get_viewport(&*follower.borrow(), 2) == Some(viewport(buffer_a, 0.0))
+        });
+        assert_eq!(renders.borrow()[&workspace_view.view_id]["following"], json!(1));
+
+        // Scrolling and switching buffers are mirrored by the follower, even after somebody else
+        // has moved in the meantime
+        set_viewport(&*local_workspace.borrow(), viewport(buffer_b, 0.0));
+        turn_until(&mut reactor, || {
+            get_viewport(&*follower.borrow(), 0) == Some(viewport(buffer_b, 0.0))
+        });
+        set_viewport(&leader, viewport(buffer_a, 40.0));
+        turn_until(&mut reactor, || {
+            get_viewport(&*follower.borrow(), 2) == Some(viewport(buffer_a, 40.0))
+        });
+        set_viewport(&leader, viewport(buffer_b, 20.0));
+        turn_until(&mut reactor, || {
+            get_viewport(&*follower.borrow(), 2) == Some(viewport(buffer_b, 20.0))
+        });
+
+        // Followers stop following users who leave
+        drop(leader);
+        turn_until(&mut reactor, || {
+            renders.borrow()[&workspace_view.view_id]["following"].is_null()
+        });
+    }
+
+    fn turn_until<F: Fn() -> bool>(reactor: &mut reactor::Core, condition: F) {
+        let mut remaining_tries = 20;
+        while !condition() {
+            remaining_tries -= 1;
+            assert!(remaining_tries > 0, "Ran out of patience waiting for condition");
+            reactor.turn(Some(Duration::from_millis(0)));
+        }
+    }
+
+    fn build_workspace(
+        foreground: ForegroundExecutor,
+        file_provider: Rc<TestFileProvider>,
+    ) -> LocalWorkspace {
+        let tree = TestTree::from_json(
+            "/Users/someone/foo",
+            json!({
+                "a": null,
+                "b": null,
+            }),
+        );
+        tree.populated.set(true);
+        LocalWorkspace::new(LocalProject::new(foreground, file_provider, vec![tree]))
+    }
+
+    fn user_names(workspace: &Workspace) -> Vec<String> {
+        workspace
+            .presence()
+            .borrow()
+            .users()
+            .iter()
+            .map(|user| user.name.clone())
+            .collect()
+    }
+
+    fn open_path(reactor: &mut reactor::Core, workspace: &Workspace, path: &str) -> BufferId {
+        let buffer = workspace
+            .project()
+            .open_path(0, &cross_platform::Path::from(path));
+        let buffer = reactor.run(buffer).unwrap();
+        let buffer_id = buffer.borrow().id();
+        buffer_id
+    }
+
+    fn set_viewport(workspace: &Workspace, viewport: Viewport) {
+        let user_id = workspace.user_id();
+        workspace
+            .presence()
+            .borrow_mut()
+            .set_viewport(user_id, viewport);
+    }
+
+    fn get_viewport(workspace: &Workspace, user_id: UserId) -> Option<Viewport> {
+        workspace.presence().borrow().viewport(user_id).cloned()
+    }
+
+    fn viewport(buffer_id: BufferId, scroll_top: f64) -> Viewport {
+        Viewport {
+            buffer_id,
+            scroll_top,
+        }
+    }
+}
@@ -1,29 +1,54 @@
 use futures::{self, Future, Stream};
 use ignore::WalkBuilder;
+use notify::{self, DebouncedEvent, RecursiveMode, Watcher};
 use parking_lot::Mutex;
-use std::ffi::OsString;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::io::{self, Read};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::fs::MetadataExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
 use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 use xray_core::cross_platform;
 use xray_core::fs as xray_fs;
 use xray_core::notify_cell::NotifyCell;
 
+const WATCH_DELAY_MILLIS: u64 = 50;
+const SAVE_SUFFIX: &str = ".xray-save";
+
 pub struct Tree {
     path: cross_platform::Path,
     root: xray_fs::Entry,
     updates: NotifyCell<()>,
     populated: NotifyCell<bool>,
+    file_updates: FileUpdates,
+    _watcher: Option<TreeWatcher>,
+}
+
+// Notifies observers of individual files when those files change, keyed by absolute path.
+type FileUpdates = Arc<Mutex<HashMap<PathBuf, NotifyCell<()>>>>;
+
+// Dropping the watcher closes the channel of events, which stops the thread maintaining the tree.
+enum TreeWatcher {
+    Native(notify::RecommendedWatcher),
+    Polling(notify::PollWatcher),
 }
 
 pub struct FileProvider;
 
 pub struct File {
+    path: PathBuf,
+    state: Arc<Mutex<FileState>>,
+}
+
+// Saving replaces the file on disk, so both the handle and the inode it is identified by change.
+struct FileState {
     id: xray_fs::FileId,
-    file: Arc<Mutex<fs::File>>,
+    file: fs::File,
 }
 
 impl Tree {
@@ -33,17 +58,30 @@ impl Tree {
         let root = xray_fs::Entry::dir(file_name.into(), false, false);
         let updates = NotifyCell::new(());
         let populated = NotifyCell::new(false);
+        let file_updates = Arc::new(Mutex::new(HashMap::new()));
+        let (events_tx, events_rx) = mpsc::channel();
+        let watcher = match TreeWatcher::new(&path, events_tx) {
+            Ok(watcher) => Some(watcher),
+            Err(error) => {
+                eprintln!("Could not watch {:?} for changes: {:?}", path, error);
+                None
+            }
+        };
         Self::populate(
             path.clone(),
             root.clone(),
             updates.clone(),
             populated.clone(),
+            file_updates.clone(),
+            events_rx,
         );
         Ok(Self {
             path: cross_platform::Path::from(path.into_os_string()),
             root,
             updates,
             populated,
+            file_updates,
+            _watcher: watcher,
         })
     }
 
@@ -52,44 +90,222 @@ impl Tree {
         root: xray_fs::Entry,
         updates: NotifyCell<()>,
         populated: NotifyCell<bool>,
+        file_updates: FileUpdates,
+        events: mpsc::Receiver<DebouncedEvent>,
     ) {
         thread::spawn(move || {
-            let mut stack = vec![root];
-
-            let entries = WalkBuilder::new(path.clone())
-                .follow_links(true)
-                .include_ignored(true)
-                .build()
-                .skip(1)
-                .filter_map(|e| e.ok());
-
-            for entry in entries {
-                stack.truncate(entry.depth());
-
-                let file_type = entry.file_type().unwrap();
-                let file_name = entry.file_name();
-
-                if file_type.is_dir() {
-                    let dir = xray_fs::Entry::dir(
-                        file_name.into(),
-                        file_type.is_symlink(),
-                        entry.ignored(),
-                    );
-                    stack.last_mut().unwrap().insert(dir.clone()).unwrap();
-                    stack.push(dir);
-                } else if file_type.is_file() {
-                    let file = xray_fs::Entry::file(
-                        file_name.into(),
-                        file_type.is_symlink(),
-                        entry.ignored(),
-                    );
-                    stack.last_mut().unwrap().insert(file).unwrap();
+            Self::scan(&path, &root, Some(&updates));
+            populated.set(true);
+
+            // The watcher was started before the initial scan, so any changes that occurred while
+            // scanning are waiting for us in the channel.
+            while let Ok(event) = events.recv() {
+                let mut changed_dirs = Vec::new();
+                let mut changed_files = Vec::new();
+                let mut rescanned = false;
+                for event in Some(event).into_iter().chain(events.try_iter()) {
+                    match event {
+                        DebouncedEvent::Create(changed_path)
+                        | DebouncedEvent::Remove(changed_path) => {
+                            changed_dirs.extend(changed_path.parent().map(|p| p.to_path_buf()));
+                            changed_files.push(changed_path);
+                        }
+                        DebouncedEvent::Write(changed_path) => {
+                            changed_files.push(changed_path);
+                        }
+                        DebouncedEvent::Rename(old_path, new_path) => {
+                            changed_dirs.extend(old_path.parent().map(|p| p.to_path_buf()));
+                            changed_dirs.extend(new_path.parent().map(|p| p.to_path_buf()));
+                            changed_files.push(new_path);
+                        }
+                        DebouncedEvent::Rescan => {
+                            changed_dirs.extend(Self::dir_paths(&path, &root));
+                            rescanned = true;
+                        }
+                        DebouncedEvent::Error(error, error_path) => {
+                            eprintln!("Error watching {:?}: {:?}", error_path, error);
+                        }
+                        _ => {}
+                    }
+                }
+
+                changed_dirs.sort();
+                changed_dirs.dedup();
+                let mut changed = false;
+                for dir_path in changed_dirs {
+                    changed |= Self::refresh_dir(&path, &root, &dir_path);
+                }
+                if changed {
+                    updates.set(());
                 }
+
+                let file_updates = file_updates.lock();
+                if rescanned {
+                    for file_update in file_updates.values() {
+                        file_update.set(());
+                    }
+                } else {
+                    for file_path in changed_files {
+                        if let Some(file_update) = file_updates.get(&file_path) {
+                            file_update.set(());
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    // Walks the directory at the given path, inserting everything beneath it into `dir`.
+    fn scan(path: &Path, dir: &xray_fs::Entry, updates: Option<&NotifyCell<()>>) {
+        let mut stack = vec![dir.clone()];
+
+        let entries = WalkBuilder::new(path)
+            .follow_links(true)
+            .include_ignored(true)
+            .build()
+            .skip(1)
+            .filter_map(|e| e.ok());
+
+        for entry in entries {
+            stack.truncate(entry.depth());
+
+            let file_type = entry.file_type().unwrap();
+            let file_name = entry.file_name();
+
+            if file_type.is_dir() {
+                let dir = xray_fs::Entry::dir(
+                    file_name.into(),
+                    file_type.is_symlink(),
+                    entry.ignored(),
+                );
+                stack.last_mut().unwrap().insert(dir.clone()).unwrap();
+                stack.push(dir);
+            } else if file_type.is_file() && !is_temp_file(file_name) {
+                let file = xray_fs::Entry::file(
+                    file_name.into(),
+                    file_type.is_symlink(),
+                    entry.ignored(),
+                );
+                stack.last_mut().unwrap().insert(file).unwrap();
+            }
+            if let Some(updates) = updates {
                 updates.set(());
             }
+        }
+    }
 
-            populated.set(true);
-        });
+    // Brings the children of the directory at the given path up to date with the file system,
+    // returning whether anything changed. If the directory isn't in the tree yet, its closest
+    // ancestor that is gets refreshed instead.
+    fn refresh_dir(root_path: &Path, root: &xray_fs::Entry, dir_path: &Path) -> bool {
+        let relative_path = match dir_path.strip_prefix(root_path) {
+            Ok(relative_path) => relative_path,
+            Err(_) => return false,
+        };
+
+        let mut dir = root.clone();
+        let mut dir_path = root_path.to_path_buf();
+        for component in relative_path.iter() {
+            match dir.child(&component.into()) {
+                Some(child) => {
+                    if child.is_dir() {
+                        dir = child;
+                        dir_path.push(component);
+                    } else {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+
+        let mut entries_on_disk = BTreeMap::new();
+        let walk = WalkBuilder::new(&dir_path)
+            .follow_links(true)
+            .include_ignored(true)
+            .max_depth(Some(1))
+            .build()
+            .skip(1)
+            .filter_map(|e| e.ok());
+        for entry in walk {
+            let file_type = entry.file_type().unwrap();
+            if file_type.is_dir() || (file_type.is_file() && !is_temp_file(entry.file_name())) {
+                let name = cross_platform::PathComponent::from(entry.file_name());
+                let file_name = entry.file_name().to_os_string();
+                entries_on_disk.insert(name, (file_name, file_type, entry.ignored()));
+            }
+        }
+
+        let mut changed = false;
+        for child in dir.children().unwrap().iter() {
+            let unchanged = entries_on_disk.get(child.name()).map_or(
+                false,
+                |&(_, file_type, ignored)| {
+                    file_type.is_dir() == child.is_dir() && ignored == child.is_ignored()
+                },
+            );
+            if !unchanged {
+                dir.remove(child.name()).unwrap();
+                changed = true;
+            }
+        }
+
+        for (name, (file_name, file_type, ignored)) in entries_on_disk {
+            if dir.child(&name).is_some() {
+                continue;
+            }
+
+            if file_type.is_dir() {
+                let child = xray_fs::Entry::dir(name, file_type.is_symlink(), ignored);
+                Self::scan(&dir_path.join(file_name), &child, None);
+                dir.insert(child).unwrap();
+            } else {
+                dir.insert(xray_fs::Entry::file(name, file_type.is_symlink(), ignored))
+                    .unwrap();
+            }
+            changed = true;
+        }
+
+        changed
+    }
+
+    // Returns the absolute paths of every directory in the tree, starting with the root.
+    fn dir_paths(root_path: &Path, root: &xray_fs::Entry) -> Vec<PathBuf> {
+        let mut dir_paths = Vec::new();
+        let mut stack = vec![(root.clone(), root_path.to_path_buf())];
+        while let Some((dir, dir_path)) = stack.pop() {
+            for child in dir.children().unwrap().iter() {
+                if child.is_dir() {
+                    let &cross_platform::PathComponent::Unix(ref name) = child.name();
+                    let child_path = dir_path.join(OsStr::from_bytes(name));
+                    stack.push((child.clone(), child_path));
+                }
+            }
+            dir_paths.push(dir_path);
+        }
+        dir_paths
+    }
+}
+
+impl TreeWatcher {
+    // Prefer the platform's native notification mechanism (inotify on Linux), but fall back to
+    // polling when it's unavailable, e.g. because we've run out of inotify watches.
+    fn new(path: &Path, events: mpsc::Sender<DebouncedEvent>) -> Result<Self, notify::Error> {
+        let delay = Duration::from_millis(WATCH_DELAY_MILLIS);
+        let native_watcher = notify::RecommendedWatcher::new(events.clone(), delay)
+            .and_then(|mut watcher| {
+                watcher.watch(path, RecursiveMode::Recursive)?;
+                Ok(watcher)
+            });
+
+        match native_watcher {
+            Ok(watcher) => Ok(TreeWatcher::Native(watcher)),
+            Err(_) => {
+                let mut watcher = notify::PollWatcher::new(events, delay)?;
+                watcher.watch(path, RecursiveMode::Recursive)?;
+                Ok(TreeWatcher::Polling(watcher))
+            }
+        }
     }
 }
 
@@ -118,6 +334,20 @@ impl xray_fs::LocalTree for Tree {
         )
     }
 
+    fn file_updates(
+        &self,
+        relative_path: &cross_platform::Path,
+    ) -> Box<Stream<Item = (), Error = ()>> {
+        let path = self.path.to_path_buf().join(relative_path.to_path_buf());
+        Box::new(
+            self.file_updates
+                .lock()
+                .entry(path)
+                .or_insert_with(|| NotifyCell::new(()))
+                .observe(),
+        )
+    }
+
     fn as_tree(&self) -> &xray_fs::Tree {
         self
     }
@@ -139,7 +369,8 @@ impl xray_fs::FileProvider for FileProvider {
 
         thread::spawn(|| {
             fn open(path: PathBuf) -> Result<File, io::Error> {
-                Ok(File::new(fs::File::open(path)?)?)
+                let file = fs::File::open(&path)?;
+                Ok(File::new(path, file)?)
             }
 
             let _ = tx.send(open(path));
@@ -153,33 +384,242 @@ impl xray_fs::FileProvider for FileProvider {
 }
 
 impl File {
-    fn new(file: fs::File) -> Result<File, io::Error> {
+    fn new(path: PathBuf, file: fs::File) -> Result<File, io::Error> {
         Ok(File {
-            id: file.metadata()?.ino(),
-            file: Arc::new(Mutex::new(file)),
+            path,
+            state: Arc::new(Mutex::new(FileState {
+                id: file.metadata()?.ino(),
+                file,
+            })),
         })
     }
 }
 
 impl xray_fs::File for File {
     fn id(&self) -> xray_fs::FileId {
-        self.id
+        self.state.lock().id
     }
 
     fn read(&self) -> Box<Future<Item = String, Error = io::Error>> {
         let (tx, rx) = futures::sync::oneshot::channel();
-        let file = self.file.clone();
+        let state = self.state.clone();
         thread::spawn(move || {
-            fn read(file: &fs::File) -> Result<String, io::Error> {
+            // The handle is shared between reads, so rewind it before reading the file again.
+            fn read(mut file: &fs::File) -> Result<String, io::Error> {
+                file.seek(SeekFrom::Start(0))?;
                 let mut buf_reader = io::BufReader::new(file);
                 let mut contents = String::new();
                 buf_reader.read_to_string(&mut contents)?;
                 Ok(contents)
             }
 
-            let _ = tx.send(read(&file.lock()));
+            let _ = tx.send(read(&state.lock().file));
         });
 
         Box::new(rx.then(|result| result.expect("Sender should not be dropped")))
     }
+
+    fn write(&self, text: Vec<u16>) -> Box<Future<Item = (), Error = io::Error>> {
+        let (tx, rx) = futures::sync::oneshot::channel();
+        let path = self.path.clone();
+        let state = self.state.clone();
+        thread::spawn(move || {
+            // The new contents are written to a temporary file next to the original, which is
+            // only replaced once they have safely reached the disk. That way a crash or a full
+            // disk part way through saving leaves the original file intact.
+            fn write(path: &Path, text: Vec<u16>) -> Result<fs::File, io::Error> {
+                let contents = String::from_utf16(&text)
+                    .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+                // Replace the target of a symlink rather than the symlink itself.
+                let path = path.canonicalize()?;
+                let temp_path = temp_path(&path);
+                let result = write_temp(&path, &temp_path, contents.as_bytes())
+                    .and_then(|_| fs::rename(&temp_path, &path));
+                if result.is_err() {
+                    let _ = fs::remove_file(&temp_path);
+                }
+                result?;
+                fs::File::open(&path)
+            }
+
+            fn write_temp(
+                path: &Path,
+                temp_path: &Path,
+                contents: &[u8],
+            ) -> Result<(), io::Error> {
+                let file = fs::File::create(temp_path)?;
+                file.set_permissions(fs::metadata(path)?.permissions())?;
+                {
+                    let mut buf_writer = io::BufWriter::new(&file);
+                    buf_writer.write_all(contents)?;
+                    buf_writer.flush()?;
+                }
+                file.sync_all()
+            }
+
+            fn temp_path(path: &Path) -> PathBuf {
+                let mut file_name = OsString::from(".");
+                if let Some(name) = path.file_name() {
+                    file_name.push(name);
+                }
+                file_name.push(SAVE_SUFFIX);
+                path.with_file_name(file_name)
+            }
+
+            // Hold the lock so we don't race with a concurrent read of the same file.
+            let mut state = state.lock();
+            let result = write(&path, text).and_then(|file| {
+                state.id = file.metadata()?.ino();
+                state.file = file;
+                Ok(())
+            });
+            let _ = tx.send(result);
+        });
+
+        Box::new(rx.then(|result| result.expect("Sender should not be dropped")))
+    }
+}
+
+// Files are saved by way of a temporary file next to them, which shouldn't show up in the tree.
+fn is_temp_file(file_name: &OsStr) -> bool {
+    file_name.as_bytes().ends_with(SAVE_SUFFIX.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::rc::Rc;
+    use tempdir::TempDir;
+    use tokio_core::reactor;
+    use xray_core::fs::LocalTree;
+    use xray_core::project::{LocalProject, PathSearchStatus, Project};
+
+    #[test]
+    fn test_watch_tree() {
+        let temp_dir = TempDir::new("xray-tree").unwrap();
+        let root_path = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir(root_path.join("a")).unwrap();
+        fs::write(root_path.join("a/file-1"), "").unwrap();
+
+        let reactor = reactor::Core::new().unwrap();
+        let tree = Tree::new(&root_path).unwrap();
+        tree.populated().wait().unwrap();
+        let project = LocalProject::new(
+            Rc::new(reactor.handle()),
+            Rc::new(FileProvider::new()),
+            vec![tree],
+        );
+        assert_eq!(search_paths(&project, "file"), vec!["a/file-1"]);
+
+        fs::write(root_path.join("a/file-2"), "").unwrap();
+        fs::write(root_path.join("a/.file-2.xray-save"), "").unwrap();
+        fs::create_dir_all(root_path.join("b/c")).unwrap();
+        fs::write(root_path.join("b/c/file-3"), "").unwrap();
+        wait_for(|| {
+            search_paths(&project, "file") == vec!["a/file-1", "a/file-2", "b/c/file-3"]
+        });
+
+        fs::rename(root_path.join("a/file-1"), root_path.join("b/file-4")).unwrap();
+        wait_for(|| {
+            search_paths(&project, "file") == vec!["a/file-2", "b/c/file-3", "b/file-4"]
+        });
+
+        fs::remove_dir_all(root_path.join("b")).unwrap();
+        wait_for(|| search_paths(&project, "file") == vec!["a/file-2"]);
+    }
+
+    #[test]
+    fn test_reload_changed_file() {
+        let temp_dir = TempDir::new("xray-tree").unwrap();
+        let root_path = temp_dir.path().canonicalize().unwrap();
+        fs::write(root_path.join("file"), "abc").unwrap();
+
+        let mut reactor = reactor::Core::new().unwrap();
+        let tree = Tree::new(&root_path).unwrap();
+        tree.populated().wait().unwrap();
+        let project = LocalProject::new(
+            Rc::new(reactor.handle()),
+            Rc::new(FileProvider::new()),
+            vec![tree],
+        );
+        let buffer = reactor
+            .run(project.open_path(0, &OsString::from("file").into()))
+            .unwrap();
+
+        fs::write(root_path.join("file"), "abcdef").unwrap();
+        wait_for(|| {
+            reactor.turn(Some(Duration::from_millis(0)));
+            buffer.borrow().to_u16_chars() == "abcdef".encode_utf16().collect::<Vec<_>>()
+        });
+        assert!(!buffer.borrow().is_modified());
+    }
+
+    #[test]
+    fn test_read_file() {
+        let temp_dir = TempDir::new("xray-file").unwrap();
+        let path = temp_dir.path().join("file");
+        fs::write(&path, "abc").unwrap();
+
+        let file = xray_fs::FileProvider::open(
+            &FileProvider::new(),
+            &path.clone().into_os_string().into(),
+        ).wait()
+            .unwrap();
+        assert_eq!(file.read().wait().unwrap(), "abc");
+        fs::write(&path, "def").unwrap();
+        assert_eq!(file.read().wait().unwrap(), "def");
+    }
+
+    #[test]
+    fn test_write_file() {
+        let temp_dir = TempDir::new("xray-file").unwrap();
+        let path = temp_dir.path().join("file");
+        fs::write(&path, "abc").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o751)).unwrap();
+
+        let file = xray_fs::FileProvider::open(
+            &FileProvider::new(),
+            &path.clone().into_os_string().into(),
+        ).wait()
+            .unwrap();
+        file.write("def".encode_utf16().collect()).wait().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "def");
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o751);
+        assert_eq!(file.id(), fs::metadata(&path).unwrap().ino());
+
+        // The temporary file the contents were written to was moved into place
+        let mut entries = fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        entries.sort();
+        assert_eq!(entries, vec![OsString::from("file")]);
+    }
+
+    fn search_paths(project: &LocalProject, needle: &str) -> Vec<String> {
+        let (mut search, observer) = project.search_paths(needle, 10, true);
+        search.poll().unwrap();
+        match observer.get() {
+            PathSearchStatus::Ready(results) => {
+                let mut paths = results
+                    .into_iter()
+                    .map(|result| result.display_path)
+                    .collect::<Vec<_>>();
+                paths.sort();
+                paths
+            }
+            PathSearchStatus::Pending => panic!("Search should have completed"),
+        }
+    }
+
+    fn wait_for<F: FnMut() -> bool>(mut condition: F) {
+        for _ in 0..100 {
+            if condition() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("Condition was not met after waiting 5 seconds");
+    }
 }
@@ -7,11 +7,14 @@ extern crate bytes;
 extern crate futures;
 extern crate futures_cpupool;
 extern crate ignore;
+extern crate notify;
 extern crate parking_lot;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+#[cfg(test)]
+extern crate tempdir;
 extern crate tokio_core;
 extern crate tokio_io;
 extern crate tokio_process;
@@ -14,7 +14,16 @@ const theme = {
     backgroundColor: "white",
     baseTextColor: "black",
     fontSize: 14,
-    lineHeight: 1.5
+    lineHeight: 1.5,
+    scopeColors: {
+      comment: { r: 150, g: 152, b: 150, a: 255 },
+      constant: { r: 0, g: 134, b: 179, a: 255 },
+      function: { r: 121, g: 93, b: 163, a: 255 },
+      keyword: { r: 167, g: 29, b: 93, a: 255 },
+      number: { r: 0, g: 134, b: 179, a: 255 },
+      string: { r: 24, g: 54, b: 145, a: 255 },
+      type: { r: 0, g: 92, b: 197, a: 255 }
+    }
   },
   userColors: [
     { r: 31, g: 150, b: 255, a: 1 },
@@ -0,0 +1,187 @@
+const React = require("react");
+const { styled } = require("styletron-react");
+const $ = React.createElement;
+
+const Root = styled("div", {
+  boxShadow: "0 6px 12px -2px rgba(0, 0, 0, 0.4)",
+  backgroundColor: "#f2f2f2",
+  borderRadius: "6px",
+  width: 500 + "px",
+  padding: "10px",
+  marginTop: "20px",
+  fontSize: "10pt",
+  fontFamily: "sans-serif"
+});
+
+const Input = styled("input", {
+  width: "100%",
+  boxSizing: "border-box",
+  padding: "5px",
+  marginBottom: "8px",
+  fontSize: "10pt",
+  outline: "none",
+  border: "1px solid #556de8",
+  boxShadow: "0 0 0 1px #556de8",
+  backgroundColor: "#ebeeff",
+  borderRadius: "3px",
+  color: "#232324"
+});
+
+const Footer = styled("div", {
+  display: "flex",
+  alignItems: "center"
+});
+
+const Option = styled("label", {
+  marginRight: "10px"
+});
+
+const Status = styled("span", {
+  flex: 1,
+  color: "#5b5b5c"
+});
+
+const ErrorMessage = styled("span", {
+  flex: 1,
+  color: "#d0021b"
+});
+
+const Button = styled("button", {
+  marginLeft: "5px",
+  fontSize: "10pt"
+});
+
+module.exports = class FindView extends React.Component {
+  constructor() {
+    super();
+    this.didChangeQuery = this.didChangeQuery.bind(this);
+    this.didChangeReplacement = this.didChangeReplacement.bind(this);
+    this.didChangeRegex = this.didChangeRegex.bind(this);
+    this.didChangeCaseSensitive = this.didChangeCaseSensitive.bind(this);
+    this.didKeyDownInQuery = this.didKeyDownInQuery.bind(this);
+    this.didKeyDownInReplacement = this.didKeyDownInReplacement.bind(this);
+    this.replace = this.replace.bind(this);
+    this.replaceAll = this.replaceAll.bind(this);
+  }
+
+  render() {
+    const status = this.props.error
+      ? $(ErrorMessage, null, this.props.error)
+      : $(Status, null, `${this.props.match_count} matches`);
+
+    return $(
+      Root,
+      null,
+      $(Input, {
+        $ref: inputNode => (this.queryInput = inputNode),
+        placeholder: "Find",
+        value: this.props.query,
+        onChange: this.didChangeQuery,
+        onKeyDown: this.didKeyDownInQuery
+      }),
+      $(Input, {
+        placeholder: "Replace",
+        value: this.props.replacement,
+        onChange: this.didChangeReplacement,
+        onKeyDown: this.didKeyDownInReplacement
+      }),
+      $(
+        Footer,
+        null,
+        status,
+        $(
+          Option,
+          null,
+          $("input", {
+            type: "checkbox",
+            checked: this.props.regex,
+            onChange: this.didChangeRegex
+          }),
+          "Regex"
+        ),
+        $(
+          Option,
+          null,
+          $("input", {
+            type: "checkbox",
+            checked: this.props.case_sensitive,
+            onChange: this.didChangeCaseSensitive
+          }),
+          "Match Case"
+        ),
+        $(Button, { onClick: this.replace }, "Replace"),
+        $(Button, { onClick: this.replaceAll }, "Replace All")
+      )
+    );
+  }
+
+  focus() {
+    this.queryInput.focus();
+  }
+
+  didChangeQuery(event) {
+    this.props.dispatch({
+      type: "UpdateQuery",
+      query: event.target.value
+    });
+  }
+
+  didChangeReplacement(event) {
+    this.props.dispatch({
+      type: "UpdateReplacement",
+      replacement: event.target.value
+    });
+  }
+
+  didChangeRegex(event) {
+    this.props.dispatch({
+      type: "UpdateRegex",
+      regex: event.target.checked
+    });
+  }
+
+  didChangeCaseSensitive(event) {
+    this.props.dispatch({
+      type: "UpdateCaseSensitive",
+      case_sensitive: event.target.checked
+    });
+  }
+
+  didKeyDownInQuery(event) {
+    switch (event.key) {
+      case "Enter":
+        if (event.shiftKey) {
+          this.props.dispatch({ type: "SelectPrevious" });
+        } else {
+          this.props.dispatch({ type: "SelectNext" });
+        }
+        break;
+      case "Escape":
+        this.props.dispatch({ type: "Close" });
+        break;
+    }
+  }
+
+  didKeyDownInReplacement(event) {
+    switch (event.key) {
+      case "Enter":
+        if (event.metaKey || event.ctrlKey) {
+          this.replaceAll();
+        } else {
+          this.replace();
+        }
+        break;
+      case "Escape":
+        this.props.dispatch({ type: "Close" });
+        break;
+    }
+  }
+
+  replace() {
+    this.props.dispatch({ type: "Replace" });
+  }
+
+  replaceAll() {
+    this.props.dispatch({ type: "ReplaceAll" });
+  }
+};
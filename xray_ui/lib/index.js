@@ -1,4 +1,5 @@
 const FileFinder = require("./file_finder");
+const FindView = require("./find_view");
 const ViewRegistry = require("./view_registry");
 const Workspace = require("./workspace");
 const TextEditorView = require("./text_editor/text_editor");
@@ -13,6 +14,7 @@ exports.buildViewRegistry = function buildViewRegistry(client) {
   });
   viewRegistry.addComponent("Workspace", Workspace);
   viewRegistry.addComponent("FileFinder", FileFinder);
+  viewRegistry.addComponent("FindView", FindView);
   viewRegistry.addComponent("BufferView", TextEditorView);
   viewRegistry.addComponent("Discussion", Discussion);
   return viewRegistry;
@@ -8,13 +8,28 @@ const $ = React.createElement;
 
 const CURSOR_BLINK_RESUME_DELAY = 300;
 const CURSOR_BLINK_PERIOD = 800;
+const PADDING_LEFT = 5;
 
 const Root = styled("div", {
+  position: "relative",
   width: "100%",
   height: "100%",
   overflow: "hidden"
 });
 
+const UserLabel = styled("div", {
+  position: "absolute",
+  padding: "0 3px",
+  borderRadius: "2px",
+  fontFamily: "sans-serif",
+  fontSize: "10px",
+  lineHeight: "14px",
+  color: "white",
+  whiteSpace: "nowrap",
+  pointerEvents: "none",
+  transform: "translateY(-100%)"
+});
+
 class TextEditor extends React.Component {
   static getDerivedStateFromProps(nextProps, prevState) {
     let derivedState = null;
@@ -38,6 +53,9 @@ class TextEditor extends React.Component {
     super(props);
     this.handleMouseWheel = this.handleMouseWheel.bind(this);
     this.handleKeyDown = this.handleKeyDown.bind(this);
+    this.handleMouseDown = this.handleMouseDown.bind(this);
+    this.handleMouseMove = this.handleMouseMove.bind(this);
+    this.handleMouseUp = this.handleMouseUp.bind(this);
     this.debouncedStartCursorBlinking = debounce(
       this.startCursorBlinking.bind(this),
       CURSOR_BLINK_RESUME_DELAY
@@ -65,6 +83,12 @@ class TextEditor extends React.Component {
       this.setState(dimensions);
     }
 
+    this.charWidth = this.measureCharWidth();
+    this.props.dispatch({
+      type: "SetCharWidth",
+      char_width: this.charWidth
+    });
+
     element.addEventListener("wheel", this.handleMouseWheel, { passive: true });
 
     this.startCursorBlinking();
@@ -72,6 +96,7 @@ class TextEditor extends React.Component {
 
   componentWillUnmount() {
     this.stopCursorBlinking();
+    this.handleMouseUp();
     const element = ReactDOM.findDOMNode(this);
     element.removeEventListener("wheel", this.handleMouseWheel, {
       passive: true
@@ -87,12 +112,20 @@ class TextEditor extends React.Component {
     });
   }
 
+  measureCharWidth() {
+    const { fontFamily, fontSize } = this.context.theme.editor;
+    const ctx = document.createElement("canvas").getContext("2d");
+    ctx.font = `${fontSize}px ${fontFamily}`;
+    return ctx.measureText("x").width;
+  }
+
   render() {
     return $(
       Root,
       {
         tabIndex: -1,
         onKeyDown: this.handleKeyDown,
+        onMouseDown: this.handleMouseDown,
         $ref: element => {
           this.element = element;
         }
@@ -101,22 +134,99 @@ class TextEditor extends React.Component {
         showLocalCursors: this.state.showLocalCursors,
         lineHeight: this.props.line_height,
         scrollTop: this.props.scroll_top,
-        paddingLeft: 5,
+        paddingLeft: PADDING_LEFT,
         height: this.props.height,
         width: this.props.width,
         selections: this.props.selections,
+        highlights: this.props.search_matches,
+        tokens: this.props.tokens,
         firstVisibleRow: this.props.first_visible_row,
         lines: this.props.lines,
-      })
+      }),
+      this.renderUserLabels()
     );
   }
 
+  renderUserLabels() {
+    if (!this.props.selections || this.charWidth == null) return null;
+
+    const labeledUsers = new Set();
+    const labels = [];
+    for (const selection of this.props.selections) {
+      const { user } = selection;
+      if (!user || labeledUsers.has(user.user_id)) continue;
+      labeledUsers.add(user.user_id);
+
+      const cursor = selection.reversed ? selection.start : selection.end;
+      const { r, g, b } = user.color;
+      labels.push(
+        $(
+          UserLabel,
+          {
+            key: user.user_id,
+            style: {
+              left: PADDING_LEFT + cursor.column * this.charWidth + "px",
+              top: cursor.row * this.props.line_height - this.props.scroll_top + "px",
+              backgroundColor: `rgb(${r}, ${g}, ${b})`
+            }
+          },
+          user.name
+        )
+      );
+    }
+    return labels;
+  }
+
   handleMouseWheel(event) {
     this.props.dispatch({ type: "UpdateScrollTop", delta: event.deltaY });
   }
 
+  handleMouseDown(event) {
+    if (event.button !== 0) return;
+    event.preventDefault();
+    this.focus();
+    this.pauseCursorBlinking();
+
+    const { x, y } = this.positionForMouseEvent(event);
+    let type;
+    if (event.detail === 2) {
+      type = "DoubleClick";
+    } else if (event.detail >= 3) {
+      type = "TripleClick";
+    } else if (event.metaKey) {
+      type = "CmdClick";
+    } else if (event.shiftKey) {
+      type = "ShiftClick";
+    } else {
+      type = "Click";
+    }
+    this.props.dispatch({ type, x, y });
+
+    window.addEventListener("mousemove", this.handleMouseMove);
+    window.addEventListener("mouseup", this.handleMouseUp);
+  }
+
+  handleMouseMove(event) {
+    this.pauseCursorBlinking();
+    const { x, y } = this.positionForMouseEvent(event);
+    this.props.dispatch({ type: "Drag", x, y });
+  }
+
+  handleMouseUp() {
+    window.removeEventListener("mousemove", this.handleMouseMove);
+    window.removeEventListener("mouseup", this.handleMouseUp);
+  }
+
+  positionForMouseEvent(event) {
+    const { left, top } = this.element.getBoundingClientRect();
+    return {
+      x: event.clientX - left - PADDING_LEFT,
+      y: event.clientY - top
+    };
+  }
+
   handleKeyDown(event) {
-    if (event.key.length === 1 && !event.metaKey) {
+    if (event.key.length === 1 && !event.metaKey && !event.ctrlKey) {
       this.props.dispatch({ type: "Edit", text: event.key });
       return;
     } else if (event.key === 'Enter') {
@@ -126,6 +236,7 @@ class TextEditor extends React.Component {
 
     const action = actionForKeyDownEvent(event);
     if (action) {
+      event.preventDefault();
       this.pauseCursorBlinking();
       this.props.dispatch({ type: action });
     }
@@ -170,6 +281,10 @@ function actionForKeyDownEvent(event) {
     case "ArrowUp":
       if (event.ctrlKey && event.shiftKey) {
         return "AddSelectionAbove";
+      } else if (event.altKey) {
+        return "MoveLinesUp";
+      } else if (event.metaKey) {
+        return event.shiftKey ? "SelectToTop" : "MoveToTop";
       } else if (event.shiftKey) {
         return "SelectUp";
       } else {
@@ -178,19 +293,124 @@ function actionForKeyDownEvent(event) {
     case "ArrowDown":
       if (event.ctrlKey && event.shiftKey) {
         return "AddSelectionBelow";
+      } else if (event.altKey) {
+        return event.shiftKey ? "DuplicateLines" : "MoveLinesDown";
+      } else if (event.metaKey) {
+        return event.shiftKey ? "SelectToBottom" : "MoveToBottom";
       } else if (event.shiftKey) {
         return "SelectDown";
       } else {
         return "MoveDown";
       }
     case "ArrowLeft":
-      return event.shiftKey ? "SelectLeft" : "MoveLeft";
+      if (event.metaKey) {
+        return event.shiftKey ? "SelectToBeginningOfLine" : "MoveToBeginningOfLine";
+      } else if (event.altKey) {
+        return event.shiftKey ? "SelectToPreviousWordStart" : "MoveToPreviousWordStart";
+      } else {
+        return event.shiftKey ? "SelectLeft" : "MoveLeft";
+      }
     case "ArrowRight":
-      return event.shiftKey ? "SelectRight" : "MoveRight";
+      if (event.metaKey) {
+        return event.shiftKey ? "SelectToEndOfLine" : "MoveToEndOfLine";
+      } else if (event.altKey) {
+        return event.shiftKey ? "SelectToNextWordEnd" : "MoveToNextWordEnd";
+      } else {
+        return event.shiftKey ? "SelectRight" : "MoveRight";
+      }
+    case "Tab":
+      return event.shiftKey ? "Outdent" : "Tab";
+    case "PageUp":
+      return event.shiftKey ? "SelectPageUp" : "PageUp";
+    case "PageDown":
+      return event.shiftKey ? "SelectPageDown" : "PageDown";
     case "Backspace":
-      return "Backspace";
+      if (event.metaKey) {
+        return "DeleteToBeginningOfLine";
+      } else if (event.altKey) {
+        return "DeleteToPreviousWordStart";
+      } else {
+        return "Backspace";
+      }
     case "Delete":
-      return "Delete";
+      if (event.metaKey) {
+        return "DeleteToEndOfLine";
+      } else if (event.altKey) {
+        return "DeleteToNextWordEnd";
+      } else {
+        return "Delete";
+      }
+    case "[":
+      if (event.metaKey && event.altKey) {
+        return "Fold";
+      } else if (event.metaKey) {
+        return "Outdent";
+      }
+      break;
+    case "]":
+      if (event.metaKey && event.altKey) {
+        return "Unfold";
+      } else if (event.metaKey) {
+        return "Indent";
+      }
+      break;
+    case "{":
+      if (event.metaKey && event.altKey) {
+        return "FoldAll";
+      }
+      break;
+    case "d":
+    case "D":
+      if (event.metaKey) {
+        return event.shiftKey ? "SkipOccurrence" : "SelectNextOccurrence";
+      }
+      break;
+    case "g":
+      if (event.metaKey && event.ctrlKey) {
+        return "SelectAllOccurrences";
+      }
+      break;
+    case "j":
+      if (event.metaKey) {
+        return "JoinLines";
+      }
+      break;
+    case "K":
+      if (event.metaKey && event.shiftKey) {
+        return "DeleteLines";
+      }
+      break;
+    case "m":
+      if (event.ctrlKey) {
+        return "GoToMatchingBracket";
+      }
+      break;
+    case "z":
+    case "Z":
+      if (event.metaKey) {
+        return event.shiftKey ? "Redo" : "Undo";
+      }
+      break;
+    case "s":
+      if (event.metaKey) {
+        return "Save";
+      }
+      break;
+    case "c":
+      if (event.metaKey) {
+        return "Copy";
+      }
+      break;
+    case "x":
+      if (event.metaKey) {
+        return "Cut";
+      }
+      break;
+    case "v":
+      if (event.metaKey) {
+        return "Paste";
+      }
+      break;
   }
 }
 
@@ -34,7 +34,8 @@ class TextPlane extends React.Component {
       fontFamily,
       fontSize,
       backgroundColor,
-      baseTextColor
+      baseTextColor,
+      scopeColors
     } = this.context.theme.editor;
 
     const cursorColors = userColors;
@@ -65,7 +66,10 @@ class TextPlane extends React.Component {
       paddingLeft: this.props.paddingLeft || 0,
       firstVisibleRow: this.props.firstVisibleRow,
       lines: this.props.lines,
+      tokens: this.props.tokens || [],
+      scopeColors: scopeColors || {},
       selections: this.props.selections,
+      highlights: this.props.highlights || [],
       showLocalCursors: this.props.showLocalCursors,
       selectionColors,
       cursorColors,
@@ -328,7 +332,10 @@ class Renderer {
     paddingLeft,
     firstVisibleRow,
     lines,
+    tokens,
+    scopeColors,
     selections,
+    highlights,
     showLocalCursors,
     selectionColors,
     cursorColors
@@ -338,6 +345,7 @@ class Renderer {
     const viewportScaleY = -2 / canvasHeight;
 
     const textColor = { r: 0, g: 0, b: 0, a: 255 };
+    const highlightColor = { r: 255, g: 213, b: 0, a: 0.4 };
     const cursorWidth = 2;
 
     const xPositions = new Map();
@@ -346,12 +354,19 @@ class Renderer {
       xPositions.set(keyForPoint(start), 0);
       xPositions.set(keyForPoint(end), 0);
     }
+    for (let i = 0; i < highlights.length; i++) {
+      const { start, end } = highlights[i];
+      xPositions.set(keyForPoint(start), 0);
+      xPositions.set(keyForPoint(end), 0);
+    }
 
     const glyphCount = this.populateGlyphInstances(
       scrollTop,
       firstVisibleRow,
       paddingLeft,
       lines,
+      tokens,
+      scopeColors,
       selections,
       textColor,
       xPositions
@@ -365,7 +380,9 @@ class Renderer {
       canvasWidth,
       paddingLeft,
       selections,
+      highlights,
       xPositions,
+      highlightColor,
       selectionColors,
       cursorColors,
       cursorWidth,
@@ -491,6 +508,8 @@ class Renderer {
     firstVisibleRow,
     paddingLeft,
     lines,
+    tokens,
+    scopeColors,
     selections,
     textColor,
     xPositions
@@ -506,6 +525,8 @@ class Renderer {
       position.row = firstVisibleRow + i;
       let x = paddingLeft;
       const line = lines[i];
+      const lineTokens = tokens[i] || [];
+      let tokenIndex = 0;
 
       for (
         position.column = 0;
@@ -523,12 +544,24 @@ class Renderer {
             Math.round(x * SUBPIXEL_DIVISOR) % SUBPIXEL_DIVISOR;
           const glyph = this.atlas.getGlyph(char, variantIndex);
 
+          while (
+            tokenIndex < lineTokens.length &&
+            lineTokens[tokenIndex].end <= position.column
+          ) {
+            tokenIndex++;
+          }
+          const token = lineTokens[tokenIndex];
+          const color =
+            token && token.start <= position.column
+              ? scopeColors[token.scope] || textColor
+              : textColor;
+
           this.updateGlyphInstance(
             glyphCount++,
             Math.round(x - glyph.variantOffset),
             y,
             glyph,
-            textColor
+            color
           );
 
           x += glyph.subpixelWidth;
@@ -573,7 +606,9 @@ class Renderer {
     canvasWidth,
     paddingLeft,
     selections,
+    highlights,
     xPositions,
+    highlightColor,
     selectionColors,
     cursorColors,
     cursorWidth,
@@ -584,63 +619,75 @@ class Renderer {
     let selectionSolidCount = 0;
     let cursorSolidCount = 0;
 
-    for (var i = 0; i < selections.length; i++) {
-      const selection = selections[i];
-      const colorIndex = selection.user_id % selectionColors.length;
-      const selectionColor = selectionColors[colorIndex];
-      const cursorColor = cursorColors[colorIndex];
+    const addRange = (range, color) => {
+      const rowSpan = range.end.row - range.start.row;
+      const startX = xPositions.get(keyForPoint(range.start));
+      const endX = xPositions.get(keyForPoint(range.end));
 
-      if (comparePoints(selection.start, selection.end) !== 0) {
-        const rowSpan = selection.end.row - selection.start.row;
-        const startX = xPositions.get(keyForPoint(selection.start));
-        const endX = xPositions.get(keyForPoint(selection.end));
-
-        if (rowSpan === 0) {
-          this.updateSolidInstance(
-            this.selectionSolidInstances,
-            selectionSolidCount++,
-            Math.round(startX),
-            yForRow(selection.start.row),
-            Math.round(endX - startX),
-            yForRow(selection.start.row + 1) - yForRow(selection.start.row),
-            selectionColor
-          );
-        } else {
-          // First line of selection
-          this.updateSolidInstance(
-            this.selectionSolidInstances,
-            selectionSolidCount++,
-            Math.round(startX),
-            yForRow(selection.start.row),
-            Math.round(canvasWidth - startX),
-            yForRow(selection.start.row + 1) - yForRow(selection.start.row),
-            selectionColor
-          );
-
-          // Lines entirely spanned by selection
-          if (rowSpan > 1) {
-            this.updateSolidInstance(
-              this.selectionSolidInstances,
-              selectionSolidCount++,
-              paddingLeft,
-              yForRow(selection.start.row + 1),
-              Math.round(canvasWidth),
-              yForRow(selection.end.row) - yForRow(selection.start.row + 1),
-              selectionColor
-            );
-          }
+      if (rowSpan === 0) {
+        this.updateSolidInstance(
+          this.selectionSolidInstances,
+          selectionSolidCount++,
+          Math.round(startX),
+          yForRow(range.start.row),
+          Math.round(endX - startX),
+          yForRow(range.start.row + 1) - yForRow(range.start.row),
+          color
+        );
+      } else {
+        // First line of range
+        this.updateSolidInstance(
+          this.selectionSolidInstances,
+          selectionSolidCount++,
+          Math.round(startX),
+          yForRow(range.start.row),
+          Math.round(canvasWidth - startX),
+          yForRow(range.start.row + 1) - yForRow(range.start.row),
+          color
+        );
 
-          // Last line of selection
+        // Lines entirely spanned by range
+        if (rowSpan > 1) {
           this.updateSolidInstance(
             this.selectionSolidInstances,
             selectionSolidCount++,
             paddingLeft,
-            yForRow(selection.end.row),
-            Math.round(endX - paddingLeft),
-            yForRow(selection.end.row + 1) - yForRow(selection.end.row),
-            selectionColor
+            yForRow(range.start.row + 1),
+            Math.round(canvasWidth),
+            yForRow(range.end.row) - yForRow(range.start.row + 1),
+            color
           );
         }
+
+        // Last line of range
+        this.updateSolidInstance(
+          this.selectionSolidInstances,
+          selectionSolidCount++,
+          paddingLeft,
+          yForRow(range.end.row),
+          Math.round(endX - paddingLeft),
+          yForRow(range.end.row + 1) - yForRow(range.end.row),
+          color
+        );
+      }
+    };
+
+    for (var i = 0; i < highlights.length; i++) {
+      addRange(highlights[i], highlightColor);
+    }
+
+    for (var i = 0; i < selections.length; i++) {
+      const selection = selections[i];
+      const colorIndex = selection.user_id % selectionColors.length;
+      let selectionColor = selectionColors[colorIndex];
+      let cursorColor = cursorColors[colorIndex];
+      if (selection.user) {
+        cursorColor = Object.assign({ a: 1 }, selection.user.color);
+        selectionColor = Object.assign({}, cursorColor, { a: 0.5 });
+      }
+
+      if (comparePoints(selection.start, selection.end) !== 0) {
+        addRange(selection, selectionColor);
       }
 
       if (showLocalCursors || selection.remote) {
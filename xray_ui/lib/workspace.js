@@ -34,6 +34,37 @@ const PaneInner = styled("div", {
   right: 0
 });
 
+const UserList = styled("div", {
+  position: "absolute",
+  top: "4px",
+  right: "12px",
+  zIndex: 1,
+  display: "flex",
+  fontFamily: "sans-serif",
+  fontSize: "11px"
+});
+
+const UserItem = styled("div", ({ $clickable, $following }) => ({
+  display: "flex",
+  alignItems: "center",
+  marginLeft: "8px",
+  padding: "1px 4px",
+  borderRadius: "3px",
+  cursor: $clickable ? "pointer" : "default",
+  backgroundColor: $following ? "rgb(234, 234, 235)" : "transparent"
+}));
+
+const UserDot = styled("div", ({ $color }) => {
+  const { r, g, b } = $color;
+  return {
+    backgroundColor: `rgb(${r}, ${g}, ${b})`,
+    width: "8px",
+    height: "8px",
+    borderRadius: "50%",
+    marginRight: "4px"
+  };
+});
+
 const BackgroundTip = styled("div", {
   fontFamily: "sans-serif",
   height: "100%",
@@ -75,11 +106,51 @@ class Workspace extends React.Component {
       },
       $(VerticalToolbar, { onToggleDiscussion: this.toggleDiscussion }),
       leftPanel,
-      $(Pane, null, $(PaneInner, null, centerItem)),
+      $(Pane, null, this.renderUserList(), $(PaneInner, null, centerItem)),
       modal
     );
   }
 
+  renderUserList() {
+    const { users, user_id, following } = this.props;
+    if (!users || users.length < 2) return null;
+
+    return $(
+      UserList,
+      null,
+      ...users.map(user => {
+        const isLocalUser = user.user_id === user_id;
+        const isFollowed = user.user_id === following;
+        let name = user.name;
+        let title = isFollowed ? `Stop following ${name}` : `Follow ${name}`;
+        if (isLocalUser) {
+          name = `${name} (you)`;
+          title = name;
+        }
+        return $(
+          UserItem,
+          {
+            key: user.user_id,
+            title,
+            $clickable: !isLocalUser,
+            $following: isFollowed,
+            onClick: isLocalUser ? null : () => this.toggleFollow(user.user_id)
+          },
+          $(UserDot, { $color: user.color }),
+          name
+        );
+      })
+    );
+  }
+
+  toggleFollow(userId) {
+    if (this.props.following === userId) {
+      this.props.dispatch({ type: "Unfollow" });
+    } else {
+      this.props.dispatch({ type: "Follow", user_id: userId });
+    }
+  }
+
   componentDidMount() {
     ReactDOM.findDOMNode(this).focus();
   }
@@ -89,6 +160,9 @@ class Workspace extends React.Component {
       if (event.key === "t") {
         this.props.dispatch({ type: "ToggleFileFinder" });
         event.stopPropagation();
+      } else if (event.key === "f") {
+        this.props.dispatch({ type: "ToggleFind" });
+        event.stopPropagation();
       }
     }
   }